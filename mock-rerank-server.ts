@@ -0,0 +1,99 @@
+import * as fs from "fs";
+import * as http from "http";
+import * as https from "https";
+
+// Local stand-in for a cross-encoder rerank endpoint.
+//
+//   POST /rerank with {query, documents, top_n}  -> Cohere/Jina response
+//   POST /rerank with {query, texts}             -> TEI response
+//
+// Scores are the share of query terms found in each text, so results are
+// deterministic and easy to predict in tests. Canister outcalls require HTTPS:
+// set MOCK_TLS_KEY and MOCK_TLS_CERT to serve over TLS.
+
+interface RerankRequest {
+  query?: string;
+  documents?: string[];
+  texts?: string[];
+  top_n?: number;
+}
+
+const port = Number(process.env.MOCK_PORT || 8089);
+// Requests fail with HTTP 503 while this is set, to exercise fallback_on_error
+const failing = process.env.MOCK_FAIL === "1";
+
+function terms(text: string): string[] {
+  return text.toLowerCase().match(/[a-z0-9]+/g) || [];
+}
+
+function score(query: string, text: string): number {
+  const queryTerms = new Set(terms(query));
+  if (queryTerms.size === 0) return 0;
+  const textTerms = new Set(terms(text));
+  let hits = 0;
+  queryTerms.forEach((term) => {
+    if (textTerms.has(term)) hits++;
+  });
+  return hits / queryTerms.size;
+}
+
+function rerank(body: RerankRequest): object {
+  const query = body.query || "";
+  const tei = Array.isArray(body.texts);
+  const texts = (tei ? body.texts : body.documents) || [];
+
+  const ranked = texts
+    .map((text, index) => ({ index, score: score(query, text) }))
+    .sort((a, b) => b.score - a.score)
+    .slice(0, body.top_n || texts.length);
+
+  if (tei) return ranked;
+  return {
+    id: `mock-${Date.now()}`,
+    results: ranked.map((r) => ({ index: r.index, relevance_score: r.score })),
+    meta: { billed_units: { search_units: 1 } },
+  };
+}
+
+function handler(req: http.IncomingMessage, res: http.ServerResponse) {
+  const reply = (status: number, payload: object) => {
+    res.writeHead(status, { "Content-Type": "application/json" });
+    res.end(JSON.stringify(payload));
+  };
+
+  if (req.method !== "POST" || req.url !== "/rerank") {
+    return reply(404, { error: { message: "Not found" } });
+  }
+  if (failing) {
+    return reply(503, { error: { message: "Mock rerank unavailable" } });
+  }
+
+  let raw = "";
+  req.on("data", (chunk) => (raw += chunk));
+  req.on("end", () => {
+    try {
+      const body: RerankRequest = JSON.parse(raw);
+      if (!body.query) return reply(400, { error: { message: "query is required" } });
+      console.log(`rerank: "${body.query}" over ${(body.texts || body.documents || []).length} texts`);
+      reply(200, rerank(body));
+    } catch {
+      reply(400, { error: { message: "Invalid JSON body" } });
+    }
+  });
+}
+
+const server =
+  process.env.MOCK_TLS_KEY && process.env.MOCK_TLS_CERT
+    ? https.createServer(
+        {
+          key: fs.readFileSync(process.env.MOCK_TLS_KEY),
+          cert: fs.readFileSync(process.env.MOCK_TLS_CERT),
+        },
+        handler
+      )
+    : http.createServer(handler);
+
+server.listen(port, () => {
+  const scheme = server instanceof https.Server ? "https" : "http";
+  console.log(`Mock rerank server on ${scheme}://127.0.0.1:${port}/rerank`);
+});
@@ -0,0 +1,422 @@
+// auth.rs
+use crate::storage::{collections, documents};
+use crate::types::{BluebandError, CollectionId, CollectionPermission};
+use candid::Principal;
+
+/// What an endpoint requires of its caller
+#[derive(Clone, Debug, PartialEq)]
+pub enum Permission {
+    /// A role on the collection that grants the permission
+    Collection(CollectionId, CollectionPermission),
+    /// As `Collection`, and the collection must hold the document
+    Document {
+        collection_id: CollectionId,
+        document_id: String,
+        permission: CollectionPermission,
+    },
+    /// Genesis admin of the collection
+    GenesisAdmin(CollectionId),
+    /// Owner or editor of the collection, who may approve and execute proposals
+    Approver(CollectionId),
+    /// Controller of the canister
+    Controller,
+}
+
+/// Single entry point for endpoint authorization. Every mutating endpoint
+/// calls this before touching state.
+pub fn authorize(permission: Permission) -> Result<(), BluebandError> {
+    let caller = caller();
+
+    match permission {
+        Permission::Collection(collection_id, permission) => {
+            collections::require_permission(&collection_id, &caller.to_string(), permission)
+        }
+        Permission::Document {
+            collection_id,
+            document_id,
+            permission,
+        } => {
+            collections::require_permission(&collection_id, &caller.to_string(), permission)?;
+            if !documents::document_exists(&collection_id, &document_id) {
+                return Err(BluebandError::not_found("Document", &document_id));
+            }
+            Ok(())
+        }
+        Permission::GenesisAdmin(collection_id) => {
+            collections::require_genesis_admin(&collection_id, &caller.to_string())
+        }
+        Permission::Approver(collection_id) => {
+            if collections::is_collection_admin(&collection_id, &caller.to_string()) {
+                Ok(())
+            } else {
+                Err(BluebandError::Unauthorized(
+                    "Owner or editor role required".to_string(),
+                ))
+            }
+        }
+        Permission::Controller => {
+            if is_controller(&caller) {
+                Ok(())
+            } else {
+                Err(BluebandError::Unauthorized(
+                    "Canister controller access required".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+// =============================================================================
+// CALLER CONTEXT
+// =============================================================================
+
+// The system API traps outside a canister, so unit tests swap in a settable caller
+
+#[cfg(not(test))]
+pub fn caller() -> Principal {
+    ic_cdk::caller()
+}
+
+#[cfg(not(test))]
+pub fn is_controller(principal: &Principal) -> bool {
+    ic_cdk::api::is_controller(principal)
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_CALLER: std::cell::Cell<Principal> = const { std::cell::Cell::new(Principal::anonymous()) };
+}
+
+#[cfg(test)]
+pub fn caller() -> Principal {
+    TEST_CALLER.with(|c| c.get())
+}
+
+#[cfg(test)]
+pub fn is_controller(principal: &Principal) -> bool {
+    *principal == tests::controller()
+}
+
+#[cfg(test)]
+pub fn set_test_caller(principal: Principal) {
+    TEST_CALLER.with(|c| c.set(principal));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    pub fn controller() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    fn owner() -> Principal {
+        Principal::from_slice(&[2])
+    }
+
+    fn stranger() -> Principal {
+        Principal::from_slice(&[3])
+    }
+
+    /// Unauthorized calls must be refused before the endpoint awaits anything
+    fn ready<F: Future>(future: F) -> F::Output {
+        let mut context = Context::from_waker(Waker::noop());
+        match std::pin::pin!(future).poll(&mut context) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("endpoint awaited before authorizing the caller"),
+        }
+    }
+
+    fn setup() -> (String, String) {
+        set_test_caller(owner());
+        let collection_id = "auth_test".to_string();
+        if collections::get_collection(&collection_id).is_none() {
+            crate::create_collection(CreateCollectionRequest {
+                id: collection_id.clone(),
+                name: "Auth test".to_string(),
+                description: None,
+                settings: None,
+                visibility: None,
+            })
+            .unwrap();
+        }
+
+        let document = documents::add_document(AddDocumentRequest {
+            collection_id: collection_id.clone(),
+            title: "Doc".to_string(),
+            content: "Some content to chunk".to_string(),
+            content_type: None,
+            source_url: None,
+            author: None,
+            tags: None,
+        })
+        .unwrap();
+
+        set_test_caller(stranger());
+        (collection_id, document.id)
+    }
+
+    #[test]
+    fn every_mutating_endpoint_refuses_unauthorized_callers() {
+        let (c, d) = setup();
+        let s = stranger().to_text();
+        let add_request = || AddDocumentRequest {
+            collection_id: c.clone(),
+            title: "Injected".to_string(),
+            content: "Injected content".to_string(),
+            content_type: None,
+            source_url: None,
+            author: None,
+            tags: None,
+        };
+
+        let results: Vec<(&str, Result<(), String>)> = vec![
+            (
+                "update_collection_settings",
+                crate::update_collection_settings(c.clone(), CollectionSettings::default()),
+            ),
+            (
+                "update_collection_metadata",
+                crate::update_collection_metadata(c.clone(), Some("x".to_string()), None),
+            ),
+            ("delete_collection", crate::delete_collection(c.clone())),
+            (
+                "add_collection_admin",
+                crate::add_collection_admin(c.clone(), s.clone()),
+            ),
+            (
+                "remove_collection_admin",
+                crate::remove_collection_admin(c.clone(), owner().to_text()),
+            ),
+            (
+                "transfer_genesis_admin",
+                crate::transfer_genesis_admin(c.clone(), s.clone()),
+            ),
+            (
+                "set_collection_secret_headers",
+                crate::set_collection_secret_headers(c.clone(), Vec::new()).map(|_| ()),
+            ),
+            (
+                "clear_collection_secret_headers",
+                crate::clear_collection_secret_headers(c.clone()),
+            ),
+            (
+                "set_collection_visibility",
+                crate::set_collection_visibility(c.clone(), CollectionVisibility::Public),
+            ),
+            (
+                "add_collection_reader",
+                crate::add_collection_reader(c.clone(), s.clone()),
+            ),
+            (
+                "remove_collection_reader",
+                crate::remove_collection_reader(c.clone(), s.clone()),
+            ),
+            (
+                "set_collection_member",
+                crate::set_collection_member(c.clone(), s.clone(), CollectionRole::Owner),
+            ),
+            (
+                "remove_collection_member",
+                crate::remove_collection_member(c.clone(), owner().to_text()),
+            ),
+            (
+                "recover_collection",
+                crate::recover_collection(c.clone(), s.clone()),
+            ),
+            (
+                "set_approval_policy",
+                crate::set_approval_policy(c.clone(), None),
+            ),
+            (
+                "propose_collection_action",
+                crate::propose_collection_action(c.clone(), ProposalAction::DeleteCollection)
+                    .map(|_| ()),
+            ),
+            (
+                "add_document",
+                ready(crate::add_document(add_request())).map(|_| ()),
+            ),
+            (
+                "add_document_and_embed",
+                ready(crate::add_document_and_embed(
+                    add_request(),
+                    "https://example.com".to_string(),
+                ))
+                .map(|_| ()),
+            ),
+            (
+                "delete_document",
+                crate::delete_document(c.clone(), d.clone()),
+            ),
+            (
+                "embed_existing_document",
+                ready(crate::embed_existing_document(c.clone(), d.clone())).map(|_| ()),
+            ),
+            (
+                "bulk_embed_collection",
+                ready(crate::bulk_embed_collection(c.clone())).map(|_| ()),
+            ),
+            (
+                "enqueue_embedding_job",
+                crate::enqueue_embedding_job(c.clone(), None).map(|_| ()),
+            ),
+            (
+                "migrate_embedding_model",
+                crate::migrate_embedding_model(c.clone(), "text-embedding-3-small".to_string())
+                    .map(|_| ()),
+            ),
+            (
+                "delete_document_vectors",
+                crate::delete_document_vectors(c.clone(), d.clone()),
+            ),
+            (
+                "clear_collection_vectors",
+                crate::clear_collection_vectors(c.clone()),
+            ),
+            (
+                "validate_collection_vectors",
+                crate::validate_collection_vectors(c.clone(), Some(true)).map(|_| ()),
+            ),
+            (
+                "store_document_chunks",
+                crate::store_document_chunks(d.clone(), Vec::new()),
+            ),
+            (
+                "delete_collection_documents",
+                crate::delete_collection_documents(c.clone()),
+            ),
+            (
+                "invalidate_collection_cache",
+                crate::invalidate_collection_cache(c.clone()),
+            ),
+            (
+                "set_canister_config",
+                crate::set_canister_config(CanisterConfig::default()).map(|_| ()),
+            ),
+            ("clear_cache", crate::clear_cache()),
+            (
+                "set_rate_limit_config",
+                crate::set_rate_limit_config(RateLimitConfig::default()),
+            ),
+            ("set_token_price", crate::set_token_price(1)),
+        ];
+
+        for (endpoint, result) in results {
+            assert!(
+                result.is_err(),
+                "{} accepted an unauthorized caller",
+                endpoint
+            );
+        }
+
+        // Refused administrative calls are still audited
+        let audited = crate::storage::audit::query(&AuditQuery {
+            caller: Some(s.clone()),
+            action: Some("delete_collection".to_string()),
+            ..Default::default()
+        });
+        assert!(matches!(
+            audited.entries[0].outcome,
+            AuditOutcome::Failure(_)
+        ));
+
+        // The v2 interface returns the refusal as a typed error with the same text
+        let err = crate::delete_collection_v2(c.clone()).unwrap_err();
+        assert!(matches!(err, BluebandError::Unauthorized(_)));
+        assert_eq!(
+            crate::delete_collection(c.clone()).unwrap_err(),
+            err.to_string()
+        );
+
+        // State is untouched
+        set_test_caller(owner());
+        assert!(documents::document_exists(&c, &d));
+        assert_eq!(documents::get_document_chunks(&d).len(), 1);
+        assert_eq!(
+            collections::get_collection(&c).unwrap().genesis_admin,
+            owner().to_text()
+        );
+    }
+
+    #[test]
+    fn cross_collection_document_access_is_refused() {
+        let (c, d) = setup();
+        set_test_caller(owner());
+
+        let err = authorize(Permission::Document {
+            collection_id: c.clone(),
+            document_id: format!("{}_other", d),
+            permission: CollectionPermission::WriteDocuments,
+        })
+        .unwrap_err();
+        assert!(matches!(err, BluebandError::NotFound { .. }));
+
+        assert!(authorize(Permission::Document {
+            collection_id: c,
+            document_id: d,
+            permission: CollectionPermission::WriteDocuments,
+        })
+        .is_ok());
+        assert!(authorize(Permission::Controller).is_err());
+
+        set_test_caller(controller());
+        assert!(authorize(Permission::Controller).is_ok());
+    }
+
+    #[test]
+    fn roles_grant_only_their_permissions() {
+        let (c, _) = setup();
+        let ingestor = Principal::from_slice(&[4]);
+        let auditor = Principal::from_slice(&[5]);
+
+        set_test_caller(owner());
+        crate::set_collection_member(c.clone(), ingestor.to_text(), CollectionRole::Ingestor)
+            .unwrap();
+        crate::set_collection_member(c.clone(), auditor.to_text(), CollectionRole::Auditor)
+            .unwrap();
+        assert!(crate::set_collection_member(
+            c.clone(),
+            "not-a-principal".to_string(),
+            CollectionRole::Searcher
+        )
+        .is_err());
+
+        set_test_caller(ingestor);
+        assert!(authorize(Permission::Collection(
+            c.clone(),
+            CollectionPermission::WriteDocuments
+        ))
+        .is_ok());
+        assert!(
+            crate::update_collection_settings(c.clone(), CollectionSettings::default()).is_err()
+        );
+        assert!(crate::list_collection_members(c.clone()).is_err());
+
+        set_test_caller(auditor);
+        assert_eq!(crate::list_collection_members(c.clone()).unwrap().len(), 3);
+        assert!(authorize(Permission::Collection(
+            c.clone(),
+            CollectionPermission::WriteDocuments
+        ))
+        .is_err());
+        assert!(crate::recover_collection(c.clone(), auditor.to_text()).is_err());
+
+        // Controllers recover a collection whose genesis admin key is lost
+        set_test_caller(controller());
+        crate::recover_collection(c.clone(), stranger().to_text()).unwrap();
+        let collection = collections::get_collection(&c).unwrap();
+        assert_eq!(collection.genesis_admin, stranger().to_text());
+        assert_eq!(
+            collections::get_role(&c, &stranger().to_text()),
+            Some(CollectionRole::Owner)
+        );
+        assert_eq!(
+            collections::get_role(&c, &owner().to_text()),
+            Some(CollectionRole::Owner)
+        );
+    }
+}
@@ -0,0 +1,377 @@
+// compute/ask.rs
+
+use super::providers::{self, OutcallError};
+use crate::storage;
+use crate::storage::tokens::{self, Encoding};
+use crate::types::*;
+use ic_cdk::api::management_canister::http_request::{
+    CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformArgs,
+    TransformContext, TransformFunc,
+};
+use ic_cdk_macros::query;
+use std::collections::BTreeSet;
+
+const DEFAULT_MAX_CONTEXT_TOKENS: u32 = 3_000;
+
+const DEFAULT_MAX_ANSWER_TOKENS: u32 = 512;
+
+const DEFAULT_SYSTEM_PROMPT: &str =
+    "You answer questions about a document collection, using only the context you are given.";
+
+pub const DEFAULT_PROMPT_TEMPLATE: &str = "Answer the question using only the numbered \
+context passages below. Cite the passages you rely on as [n]. If the context does not \
+contain the answer, say that you don't know.\n\nContext:\n{context}\n\nQuestion: {question}";
+
+/// Returned without calling the model when retrieval finds nothing
+const NO_CONTEXT_ANSWER: &str =
+    "No passages in this collection match the question, so it cannot be answered.";
+
+/// Builds a prompt from the retrieved matches and asks the collection's chat model
+pub async fn answer_question(
+    collection: &Collection,
+    question: &str,
+    matches: Vec<VectorMatch>,
+    config: &AskConfig,
+    options: &AskOptions,
+) -> Result<AskResponse, BluebandError> {
+    let template = config
+        .prompt_template
+        .as_deref()
+        .unwrap_or(DEFAULT_PROMPT_TEMPLATE);
+    let max_context_tokens = options
+        .max_context_tokens
+        .or(config.max_context_tokens)
+        .unwrap_or(DEFAULT_MAX_CONTEXT_TOKENS);
+
+    let encoding = tokens::encoding_for_model(&config.model);
+    let (context, mut citations) =
+        build_context(&collection.id, matches, max_context_tokens, encoding);
+    if citations.is_empty() {
+        return Ok(AskResponse {
+            answer: NO_CONTEXT_ANSWER.to_string(),
+            citations,
+            model: config.model.clone(),
+            usage_tokens: None,
+        });
+    }
+    let prompt = render_prompt(template, &context, question)?;
+
+    let request_body = serde_json::json!({
+        "model": config.model,
+        "messages": [
+            {
+                "role": "system",
+                "content": config.system_prompt.as_deref().unwrap_or(DEFAULT_SYSTEM_PROMPT),
+            },
+            { "role": "user", "content": prompt },
+        ],
+        "max_tokens": config.max_answer_tokens.unwrap_or(DEFAULT_MAX_ANSWER_TOKENS),
+        "temperature": options.temperature.unwrap_or(0.0),
+    })
+    .to_string();
+
+    let request = send_chat_request(&config.chat_url, request_body, config, &collection.id);
+    let (answer, usage_tokens) = providers::send_tracked(&config.chat_url, request)
+        .await
+        .map_err(|e| e.with_context("Failed to generate answer"))?;
+
+    let cited = cited_indices(&answer);
+    for citation in &mut citations {
+        citation.cited = cited.contains(&citation.index);
+    }
+
+    Ok(AskResponse {
+        answer,
+        citations,
+        model: config.model.clone(),
+        usage_tokens,
+    })
+}
+
+/// Numbers the matches as `[n]` passages until the token budget is spent. A
+/// first passage larger than the whole budget is cut down rather than dropped.
+fn build_context(
+    collection_id: &str,
+    matches: Vec<VectorMatch>,
+    max_tokens: u32,
+    encoding: Encoding,
+) -> (String, Vec<Citation>) {
+    let mut passages = Vec::new();
+    let mut citations = Vec::new();
+    let mut used_tokens = 0u32;
+
+    for m in matches {
+        let Some(text) = m.chunk_text.filter(|t| !t.trim().is_empty()) else {
+            continue;
+        };
+        let document = storage::get_document(collection_id, &m.document_id);
+        let title = m
+            .document_title
+            .or_else(|| document.as_ref().map(|d| d.title.clone()));
+
+        let index = citations.len() as u32 + 1;
+        // Title, Markdown headings and PDF page locate the passage for the model
+        let page = m.page.map(|p| format!("page {}", p));
+        let location: Vec<&str> = title
+            .iter()
+            .chain(m.heading_path.iter().flatten())
+            .chain(page.iter())
+            .map(String::as_str)
+            .collect();
+        let mut passage = if location.is_empty() {
+            format!("[{}] {}", index, text)
+        } else {
+            format!("[{}] {}\n{}", index, location.join(" > "), text)
+        };
+        let mut passage_tokens = tokens::count_tokens(&passage, encoding);
+
+        if used_tokens + passage_tokens > max_tokens {
+            if !passages.is_empty() {
+                break;
+            }
+            passage = tokens::truncate_to_tokens(&passage, max_tokens, encoding).to_string();
+            passage_tokens = tokens::count_tokens(&passage, encoding);
+        }
+        used_tokens += passage_tokens;
+        passages.push(passage);
+
+        citations.push(Citation {
+            index,
+            document_id: m.document_id,
+            chunk_id: m.chunk_id,
+            document_title: title,
+            source_url: document.and_then(|d| d.source_url),
+            score: m.score,
+            cited: false,
+            page: m.page,
+        });
+    }
+
+    (passages.join("\n\n"), citations)
+}
+
+/// Fills `{context}` and `{question}` in a single pass, so placeholders inside
+/// the question or the documents are left as they are
+fn render_prompt(template: &str, context: &str, question: &str) -> Result<String, BluebandError> {
+    if !template.contains("{context}") || !template.contains("{question}") {
+        return Err("Prompt template must contain {context} and {question} placeholders".into());
+    }
+
+    let mut prompt = String::with_capacity(template.len() + context.len() + question.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        prompt.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("{context}") {
+            prompt.push_str(context);
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("{question}") {
+            prompt.push_str(question);
+            rest = after;
+        } else {
+            prompt.push('{');
+            rest = &tail[1..];
+        }
+    }
+    prompt.push_str(rest);
+
+    Ok(prompt)
+}
+
+/// Passage numbers referenced as `[n]` or `[n, m]` in the answer
+fn cited_indices(answer: &str) -> BTreeSet<u32> {
+    answer
+        .split('[')
+        .skip(1)
+        .filter_map(|part| part.split_once(']'))
+        .flat_map(|(inside, _)| {
+            let numbers: Option<Vec<u32>> =
+                inside.split(',').map(|n| n.trim().parse().ok()).collect();
+            numbers.unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Single outcall to the chat endpoint, returning the answer and token usage
+async fn send_chat_request(
+    url: &str,
+    request_body: String,
+    config: &AskConfig,
+    collection_id: &str,
+) -> Result<(String, Option<u32>), OutcallError> {
+    let max_answer_tokens = config
+        .max_answer_tokens
+        .unwrap_or(DEFAULT_MAX_ANSWER_TOKENS);
+    let max_response_bytes = (max_answer_tokens as u64 * 16 + 2_000).min(
+        storage::config::get_canister_config()
+            .outcalls
+            .max_response_bytes,
+    );
+    let cycles_needed = providers::outcall_cycles(request_body.len(), max_response_bytes as usize);
+
+    let http_request_arg = CanisterHttpRequestArgument {
+        url: url.to_string(),
+        method: HttpMethod::POST,
+        headers: vec![
+            HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            },
+            HttpHeader {
+                name: "User-Agent".to_string(),
+                value: "IC-VectorDB/1.0".to_string(),
+            },
+            // Lets the proxy return one completion to every replica
+            HttpHeader {
+                name: "Idempotency-Key".to_string(),
+                value: idempotency_key(url, &request_body),
+            },
+        ],
+        body: Some(request_body.into_bytes()),
+        max_response_bytes: Some(max_response_bytes),
+        transform: Some(TransformContext {
+            function: TransformFunc(candid::Func {
+                principal: ic_cdk::api::id(),
+                method: "transform_chat_response".to_string(),
+            }),
+            context: vec![],
+        }),
+    };
+
+    providers::metered_outcall(
+        Some(collection_id),
+        http_request_arg,
+        cycles_needed,
+        |response| {
+            let (answer, tokens) = parse_chat_response(&response.body)?;
+            Ok(((answer, tokens), tokens.unwrap_or(0) as u64))
+        },
+    )
+    .await
+}
+
+fn idempotency_key(url: &str, request_body: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let hash = Sha256::new()
+        .chain_update(url.as_bytes())
+        .chain_update(request_body.as_bytes())
+        .finalize();
+    let hex: String = hash[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("icp-vdb-ask-{}", hex)
+}
+
+/// Parses the canonical body produced by `transform_chat_response`
+fn parse_chat_response(response_body: &[u8]) -> Result<(String, Option<u32>), OutcallError> {
+    let response_json: serde_json::Value = serde_json::from_slice(response_body)
+        .map_err(|e| format!("Failed to parse chat response: {}", e))?;
+
+    if let Some(error) = response_json.get("error") {
+        return Err(OutcallError::fatal(format!(
+            "API error: {}",
+            error.to_string().chars().take(200).collect::<String>()
+        )));
+    }
+
+    let answer = response_json["content"]
+        .as_str()
+        .ok_or_else(|| "Missing answer in chat response".to_string())?;
+    let usage_tokens = response_json["total_tokens"].as_u64().map(|t| t as u32);
+
+    Ok((answer.trim().to_string(), usage_tokens))
+}
+
+#[query]
+fn transform_chat_response(raw: TransformArgs) -> HttpResponse {
+    HttpResponse {
+        status: raw.response.status,
+        headers: Vec::new(),
+        body: canonicalize_chat_body(&raw.response.body),
+    }
+}
+
+/// Keeps only the first choice's message and the total token usage of a
+/// chat-completions response, dropping ids and timestamps that differ per replica
+fn canonicalize_chat_body(body: &[u8]) -> Vec<u8> {
+    let json: serde_json::Value = match serde_json::from_slice(body) {
+        Ok(json) => json,
+        Err(_) => return br#"{"error":{"message":"Unparseable provider response"}}"#.to_vec(),
+    };
+
+    if let Some(error) = json.get("error") {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .or_else(|| error.as_str())
+            .unwrap_or("Provider returned an error");
+        let message: String = message.chars().take(200).collect();
+        return serde_json::json!({ "error": { "message": message } })
+            .to_string()
+            .into_bytes();
+    }
+
+    let content = json["choices"][0]["message"]["content"].as_str();
+    let total_tokens = json["usage"]["total_tokens"].as_u64();
+    serde_json::json!({ "content": content, "total_tokens": total_tokens })
+        .to_string()
+        .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_fills_placeholders_once_and_tracks_citations() {
+        let prompt = render_prompt(
+            "Q: {question}\n{context}\n{other}",
+            "[1] uses {question}",
+            "what is {context}?",
+        )
+        .unwrap();
+        assert_eq!(
+            prompt,
+            "Q: what is {context}?\n[1] uses {question}\n{other}"
+        );
+        assert!(render_prompt("{context} only", "c", "q").is_err());
+
+        let cited = cited_indices("Pizza is Italian [1][3]. See also [2, 4] and [note].");
+        assert_eq!(cited.into_iter().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn context_stays_within_token_budget() {
+        let matches: Vec<VectorMatch> = [
+            " pizza".repeat(100),
+            " pasta".repeat(100),
+            " wine".repeat(10),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| VectorMatch {
+            score: 1.0 - i as f64 / 10.0,
+            document_id: format!("doc-{}", i),
+            chunk_id: format!("chunk-{}", i),
+            document_title: Some("Title".to_string()),
+            chunk_text: Some(text),
+            heading_path: None,
+            page: None,
+        })
+        .collect();
+
+        // The second passage does not fit, so the smaller third one is not used either
+        let (context, citations) = build_context("missing", matches.clone(), 150, Encoding::Cl100k);
+        assert_eq!(citations.len(), 1);
+        assert!(context.starts_with("[1] Title\n pizza pizza"));
+
+        let (context, citations) = build_context("missing", matches, 20, Encoding::Cl100k);
+        assert_eq!(citations.len(), 1);
+        assert_eq!(tokens::count_tokens(&context, Encoding::Cl100k), 20);
+
+        let body = br#"{"id":"chatcmpl-1","created":1,"choices":[{"index":0,
+            "message":{"role":"assistant","content":" Italian [1] "}}],
+            "usage":{"prompt_tokens":90,"completion_tokens":4,"total_tokens":94}}"#;
+        let parsed = parse_chat_response(&canonicalize_chat_body(body)).unwrap();
+        assert_eq!(parsed, ("Italian [1]".to_string(), Some(94)));
+    }
+}
@@ -18,16 +18,18 @@ struct BoundedCache {
     total_memory: usize,
     max_memory: usize,
     max_entries: usize,
+    ttl: u64,
 }
 
 impl BoundedCache {
-    fn new(max_memory: usize, max_entries: usize) -> Self {
+    fn new(config: &CacheConfig) -> Self {
         Self {
             entries: HashMap::new(),
             access_order: Vec::new(),
             total_memory: 0,
-            max_memory,
-            max_entries,
+            max_memory: config.max_memory_bytes as usize,
+            max_entries: config.max_entries as usize,
+            ttl: config.ttl_seconds.saturating_mul(1_000_000_000),
         }
     }
 
@@ -36,7 +38,7 @@ impl BoundedCache {
 
         // First check if entry exists and is valid
         let is_valid = if let Some(entry) = self.entries.get(collection_id) {
-            now - entry.timestamp < CACHE_TTL
+            now - entry.timestamp < self.ttl
         } else {
             false
         };
@@ -131,7 +133,7 @@ impl BoundedCache {
         let expired_ids: Vec<String> = self
             .entries
             .iter()
-            .filter(|(_, entry)| now - entry.timestamp >= CACHE_TTL)
+            .filter(|(_, entry)| now - entry.timestamp >= self.ttl)
             .map(|(id, _)| id.clone())
             .collect();
 
@@ -158,14 +160,20 @@ impl BoundedCache {
 
 thread_local! {
     static CACHE: RefCell<BoundedCache> = RefCell::new(
-        BoundedCache::new(
-            100 * 1024 * 1024,  // 100MB memory limit
-            1000,                // 1000 collections max
-        )
+        BoundedCache::new(&crate::storage::config::get_canister_config().cache)
     );
 }
 
-const CACHE_TTL: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Applies new limits, evicting entries that no longer fit
+pub fn configure_cache(config: &CacheConfig) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.max_memory = config.max_memory_bytes as usize;
+        cache.max_entries = config.max_entries as usize;
+        cache.ttl = config.ttl_seconds.saturating_mul(1_000_000_000);
+        cache.make_space_for(0, 0);
+    });
+}
 
 pub fn get_cached_vectors(collection_id: &str) -> Vec<Vector> {
     let cache_hit = CACHE.with(|cache| cache.borrow_mut().get(collection_id));
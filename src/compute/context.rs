@@ -0,0 +1,224 @@
+// compute/context.rs
+
+use crate::storage;
+use crate::types::*;
+use std::collections::HashMap;
+
+/// Largest number of neighbouring chunks added on each side of a match
+pub const MAX_CONTEXT_WINDOW: u32 = 10;
+
+/// Matching chunk widened with its neighbours
+#[derive(Clone, Debug)]
+pub struct ContextPassage {
+    pub document_id: DocumentId,
+    /// Best-scoring match in the passage
+    pub chunk_id: ChunkId,
+    pub score: f64,
+    /// Headings and PDF page of the best match
+    pub heading_path: Option<Vec<String>>,
+    pub page: Option<u32>,
+    /// Chunks merged into `text`, in document order
+    pub chunk_ids: Vec<ChunkId>,
+    pub text: String,
+    pub char_start: u64,
+    pub char_end: u64,
+}
+
+// Position range of one or more merged windows within a document
+struct Window {
+    first: u32,
+    last: u32,
+    best: VectorMatch,
+    rank: usize,
+}
+
+/// Widens each match with the `window` chunks before and after it. Windows of
+/// the same document that overlap or touch become one passage, which keeps the
+/// score and chunk id of its best match and takes that match's place in the order.
+pub fn expand_matches(matches: Vec<VectorMatch>, window: u32) -> Vec<ContextPassage> {
+    let window = window.min(MAX_CONTEXT_WINDOW);
+    let mut chunks_by_document: HashMap<DocumentId, Vec<SemanticChunk>> = HashMap::new();
+    let mut windows_by_document: HashMap<DocumentId, Vec<Window>> = HashMap::new();
+
+    for (rank, m) in matches.into_iter().enumerate() {
+        let chunks = chunks_by_document
+            .entry(m.document_id.clone())
+            .or_insert_with(|| storage::documents::get_document_chunks(&m.document_id));
+        // Chunks of a document deleted since the search have nothing to expand
+        let Some(position) = chunks
+            .iter()
+            .find(|c| c.id == m.chunk_id)
+            .map(|c| c.position)
+        else {
+            continue;
+        };
+
+        windows_by_document
+            .entry(m.document_id.clone())
+            .or_default()
+            .push(Window {
+                first: position.saturating_sub(window),
+                last: position.saturating_add(window),
+                best: m,
+                rank,
+            });
+    }
+
+    let mut passages: Vec<(usize, ContextPassage)> = Vec::new();
+    for (document_id, windows) in windows_by_document {
+        let mut chunks = chunks_by_document.remove(&document_id).unwrap_or_default();
+        chunks.sort_by_key(|c| c.position);
+
+        for window in merge_windows(windows) {
+            let selected: Vec<SemanticChunk> = chunks
+                .iter()
+                .filter(|c| c.position >= window.first && c.position <= window.last)
+                .cloned()
+                .collect();
+            let Some((text, char_start, char_end)) = storage::documents::merge_chunks(&selected)
+            else {
+                continue;
+            };
+
+            passages.push((
+                window.rank,
+                ContextPassage {
+                    document_id: document_id.clone(),
+                    chunk_id: window.best.chunk_id.clone(),
+                    score: window.best.score,
+                    heading_path: window.best.heading_path.clone(),
+                    page: window.best.page,
+                    chunk_ids: selected.into_iter().map(|c| c.id).collect(),
+                    text,
+                    char_start,
+                    char_end,
+                },
+            ));
+        }
+    }
+
+    // Rank of the best match keeps the search (or rerank) order
+    passages.sort_by_key(|(rank, _)| *rank);
+    passages.into_iter().map(|(_, passage)| passage).collect()
+}
+
+/// Replaces each match's chunk text with its widened passage, so callers that
+/// consume matches (such as the ask prompt) see the same context as search results
+pub fn widen_matches(matches: Vec<VectorMatch>, window: u32) -> Vec<VectorMatch> {
+    if window == 0 {
+        return matches;
+    }
+    expand_matches(matches, window)
+        .into_iter()
+        .map(|p| VectorMatch {
+            score: p.score,
+            document_id: p.document_id,
+            chunk_id: p.chunk_id,
+            document_title: None,
+            chunk_text: Some(p.text),
+            heading_path: p.heading_path,
+            page: p.page,
+        })
+        .collect()
+}
+
+/// Merges overlapping or adjacent windows, keeping the best-ranked match of each group
+fn merge_windows(mut windows: Vec<Window>) -> Vec<Window> {
+    windows.sort_by_key(|w| w.first);
+
+    let mut merged: Vec<Window> = Vec::new();
+    for window in windows {
+        match merged.last_mut() {
+            Some(current) if window.first <= current.last.saturating_add(1) => {
+                current.last = current.last.max(window.last);
+                if window.rank < current.rank {
+                    current.rank = window.rank;
+                    current.best = window.best;
+                }
+            }
+            _ => merged.push(window),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_and_adjacent_windows_merge() {
+        let window = |first, last, rank: usize| Window {
+            first,
+            last,
+            best: VectorMatch {
+                score: 1.0 - rank as f64 / 10.0,
+                document_id: "doc".to_string(),
+                chunk_id: format!("chunk_{}", rank),
+                document_title: None,
+                chunk_text: None,
+                heading_path: None,
+                page: None,
+            },
+            rank,
+        };
+
+        let merged = merge_windows(vec![
+            window(8, 10, 2),
+            window(0, 2, 1),
+            window(3, 5, 0),
+            window(12, 14, 3),
+        ]);
+        let ranges: Vec<_> = merged
+            .iter()
+            .map(|w| (w.first, w.last, w.best.chunk_id.as_str()))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![(0, 5, "chunk_0"), (8, 10, "chunk_2"), (12, 14, "chunk_3")]
+        );
+    }
+
+    #[test]
+    fn widened_matches_carry_the_passage_text() {
+        let document_id = "context_doc".to_string();
+        let chunks: Vec<SemanticChunk> = ["Alpha. ", "Beta. ", "Gamma. ", "Delta."]
+            .iter()
+            .scan(0u64, |start, text| {
+                let char_start = *start;
+                *start += text.len() as u64;
+                Some((char_start, *start, text))
+            })
+            .enumerate()
+            .map(|(position, (char_start, char_end, text))| SemanticChunk {
+                id: format!("{}_chunk_{}", document_id, position),
+                document_id: document_id.clone(),
+                text: text.to_string(),
+                position: position as u32,
+                char_start,
+                char_end,
+                ..Default::default()
+            })
+            .collect();
+        storage::documents::store_document_chunks(&document_id, chunks).unwrap();
+
+        let best = VectorMatch {
+            score: 0.9,
+            document_id: document_id.clone(),
+            chunk_id: format!("{}_chunk_1", document_id),
+            document_title: None,
+            chunk_text: Some("Beta. ".to_string()),
+            heading_path: None,
+            page: None,
+        };
+
+        let unchanged = widen_matches(vec![best.clone()], 0);
+        assert_eq!(unchanged[0].chunk_text.as_deref(), Some("Beta. "));
+
+        let widened = widen_matches(vec![best], 1);
+        assert_eq!(widened.len(), 1);
+        assert_eq!(widened[0].chunk_id, format!("{}_chunk_1", document_id));
+        assert_eq!(widened[0].chunk_text.as_deref(), Some("Alpha. Beta. Gamma. "));
+    }
+}
@@ -1,9 +1,11 @@
 // compute/embeddings.rs
 
+use super::providers::{self, OutcallError};
 use super::{calculate_norm, validate_embedding};
+use crate::storage;
 use crate::types::*;
 use ic_cdk::api::management_canister::http_request::{
-    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformArgs,
+    CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformArgs,
     TransformContext, TransformFunc,
 };
 use ic_cdk_macros::query;
@@ -14,23 +16,11 @@ fn calculate_embedding_cycles(
     text_count: usize,
     request_body_size: usize,
 ) -> u128 {
-    let n = 13u128;
-
-    let base_fee = (3_000_000 + 60_000 * n) * n;
-    let request_fee = 400 * n * (request_body_size as u128);
-
     // Estimate response size based on embedding dimensions
     let dimensions = model.expected_dimensions().unwrap_or(1536);
     let response_bytes = (dimensions * 4 * text_count) + (text_count * 150) + 1000; // JSON overhead
-    let response_fee = 800 * n * (response_bytes as u128);
-
-    let total_calculated = base_fee + request_fee + response_fee;
-
-    // Use 2.5x buffer (learned from XRC and other production canisters)
-    let with_buffer = (total_calculated as f64 * 2.5) as u128;
 
-    // Ensure minimum viable amount (1B cycles like XRC)
-    with_buffer.max(1_000_000_000)
+    providers::outcall_cycles(request_body_size, response_bytes)
 }
 
 /// Calculate max response bytes for the request
@@ -47,17 +37,61 @@ fn calculate_max_response_bytes(model: &EmbeddingModel, text_count: usize) -> u6
     let with_buffer = (estimated_bytes as f64 * 1.5) as u64;
 
     // Ensure reasonable bounds
-    with_buffer.max(50_000).min(2_000_000) // 50KB min, 2MB max
+    let max_response_bytes = storage::config::get_canister_config()
+        .outcalls
+        .max_response_bytes;
+    with_buffer.clamp(50_000, max_response_bytes.max(50_000)) // 50KB min, configured max
 }
 
-/// Generate embeddings for multiple texts via HTTP outcall (SIMPLIFIED)
-pub async fn get_embeddings_async(request: EmbeddingRequest) -> Result<EmbeddingResponse, String> {
-    validate_embedding_request(&request)?;
+/// Generate embeddings for multiple texts via HTTP outcall, retrying transient
+/// failures and failing over to the collection's fallback proxies
+pub async fn get_embeddings_async(
+    request: EmbeddingRequest,
+) -> Result<EmbeddingResponse, BluebandError> {
+    let settings = request
+        .collection_id
+        .as_deref()
+        .and_then(crate::storage::get_collection)
+        .map(|collection| collection.settings);
+    let max_input_tokens = settings
+        .as_ref()
+        .and_then(|s| s.max_input_tokens)
+        .or_else(|| request.model.max_input_tokens());
+    validate_embedding_request(&request, max_input_tokens)?;
+    let policy = settings
+        .as_ref()
+        .and_then(|s| s.retry_policy.clone())
+        .unwrap_or_default();
+
+    let mut endpoints = vec![request.proxy_url.clone()];
+    for url in settings
+        .and_then(|s| s.fallback_proxy_urls)
+        .unwrap_or_default()
+    {
+        if !endpoints.contains(&url) {
+            endpoints.push(url);
+        }
+    }
+    for url in &endpoints {
+        storage::config::check_proxy_url(url)?;
+    }
+
+    let request = &request;
+    providers::send_with_failover("embedding", &endpoints, &policy, |url| async move {
+        send_embedding_request(request, &url).await
+    })
+    .await
+}
 
-    let request_body = create_embedding_request_body(&request)?;
+/// Single outcall to one endpoint, classifying failures as retryable or not
+async fn send_embedding_request(
+    request: &EmbeddingRequest,
+    url: &str,
+) -> Result<EmbeddingResponse, OutcallError> {
+    let request_body = create_embedding_request_body(request).map_err(OutcallError::fatal)?;
     let request_body_bytes = request_body.as_bytes();
 
-    let idempotency_key = generate_idempotency_key(&request);
+    let idempotency_key = generate_idempotency_key(request, url);
 
     // Calculate cycles using proven ICP formula
     let cycles_needed = calculate_embedding_cycles(
@@ -69,7 +103,7 @@ pub async fn get_embeddings_async(request: EmbeddingRequest) -> Result<Embedding
     let max_response_bytes = calculate_max_response_bytes(&request.model, request.texts.len());
 
     let http_request_arg = CanisterHttpRequestArgument {
-        url: request.proxy_url.clone(),
+        url: url.to_string(),
         method: HttpMethod::POST,
         body: Some(request_body_bytes.to_vec()),
         max_response_bytes: Some(max_response_bytes),
@@ -80,65 +114,66 @@ pub async fn get_embeddings_async(request: EmbeddingRequest) -> Result<Embedding
             }),
             context: vec![],
         }),
-        headers: vec![
-            HttpHeader {
-                name: "Content-Type".to_string(),
-                value: "application/json".to_string(),
-            },
-            HttpHeader {
-                name: "User-Agent".to_string(),
-                value: "IC-VectorDB/1.0".to_string(),
-            },
-            // ADD: Idempotency key for preventing duplicate requests
-            HttpHeader {
-                name: "Idempotency-Key".to_string(),
-                value: idempotency_key,
-            },
-        ],
+        headers: build_request_headers(request, url, idempotency_key),
     };
 
-    match http_request(http_request_arg, cycles_needed).await {
-        Ok((response,)) => {
-            if response.status < 200u16 || response.status >= 300u16 {
-                return Err(format!(
-                    "HTTP {} error: {}",
-                    response.status,
-                    String::from_utf8_lossy(&response.body)
-                        .chars()
-                        .take(200)
-                        .collect::<String>()
-                ));
-            }
+    providers::metered_outcall(
+        request.collection_id.as_deref(),
+        http_request_arg,
+        cycles_needed,
+        |response| {
+            let parsed = parse_embedding_response(&response.body, &request.model)?;
+            let tokens = parsed.usage_tokens.unwrap_or(0) as u64;
+            Ok((parsed, tokens))
+        },
+    )
+    .await
+}
 
-            parse_embedding_response(&response.body, &request.model)
-        }
-        Err((rejection_code, message)) => {
-            if message.contains("cycles") || message.contains("OutOfCycles") {
-                Err(format!(
-                    "Insufficient cycles: sent {} cycles but need more. Error: {}",
-                    cycles_needed, message
-                ))
-            } else if message.contains("SysTransient") || message.contains("timeout") {
-                Err(format!(
-                    "Network error (consider retry): {:?} - {}",
-                    rejection_code, message
-                ))
-            } else {
-                Err(format!(
-                    "HTTP request failed: {:?} - {}",
-                    rejection_code, message
-                ))
-            }
-        }
+/// Standard outcall headers plus the collection's secret headers when `url`
+/// is one of its configured endpoints
+fn build_request_headers(
+    request: &EmbeddingRequest,
+    url: &str,
+    idempotency_key: String,
+) -> Vec<HttpHeader> {
+    let mut headers = vec![
+        HttpHeader {
+            name: "Content-Type".to_string(),
+            value: "application/json".to_string(),
+        },
+        HttpHeader {
+            name: "User-Agent".to_string(),
+            value: "IC-VectorDB/1.0".to_string(),
+        },
+        // ADD: Idempotency key for preventing duplicate requests
+        HttpHeader {
+            name: "Idempotency-Key".to_string(),
+            value: idempotency_key,
+        },
+    ];
+
+    if let Some(collection_id) = &request.collection_id {
+        headers.extend(
+            crate::storage::secrets::outcall_secret_headers(collection_id, url)
+                .into_iter()
+                .map(|secret| HttpHeader {
+                    name: secret.name,
+                    value: secret.value,
+                }),
+        );
     }
+
+    headers
 }
 
 /// Generate embeddings for document chunks
 pub async fn embed_document_chunks(
+    collection_id: &str,
     chunks: &[SemanticChunk],
     collection_settings: &CollectionSettings,
     proxy_url: String,
-) -> Result<Vec<Vector>, String> {
+) -> Result<Vec<Vector>, BluebandError> {
     if chunks.is_empty() {
         return Ok(Vec::new());
     }
@@ -149,28 +184,33 @@ pub async fn embed_document_chunks(
     let model = parse_embedding_model(&collection_settings.embedding_model)?;
 
     for chunk_batch in chunks.chunks(BATCH_SIZE) {
-        let texts: Vec<String> = chunk_batch.iter().map(|chunk| chunk.text.clone()).collect();
-        
+        let texts: Vec<String> = chunk_batch
+            .iter()
+            .map(|chunk| embedding_text(chunk, collection_settings))
+            .collect();
+
         let request = EmbeddingRequest {
             texts,
             model: model.clone(),
             proxy_url: proxy_url.clone(),
+            collection_id: Some(collection_id.to_string()),
         };
 
         // Validate request body size before sending
         let request_body = create_embedding_request_body(&request)?;
-        if request_body.len() > 45_000 { // Leave some buffer for headers
-            return Err("Request body too large, try reducing batch size or chunk size".to_string());
+        if request_body.len() > 45_000 {
+            // Leave some buffer for headers
+            return Err("Request body too large, try reducing batch size or chunk size".into());
         }
 
         let response = get_embeddings_async(request).await?;
 
         if response.embeddings.len() != chunk_batch.len() {
-            return Err(format!(
+            return Err(BluebandError::provider_error(format!(
                 "Embedding count mismatch: expected {}, got {}",
                 chunk_batch.len(),
                 response.embeddings.len()
-            ));
+            )));
         }
 
         for (chunk, embedding) in chunk_batch.iter().zip(response.embeddings.iter()) {
@@ -205,25 +245,121 @@ pub async fn embed_document_chunks(
     Ok(all_vectors)
 }
 
+/// Text sent for a chunk: its heading path goes first when the collection asks for it
+fn embedding_text(chunk: &SemanticChunk, settings: &CollectionSettings) -> String {
+    match &chunk.heading_path {
+        Some(path) if settings.embed_heading_path == Some(true) && !path.is_empty() => {
+            format!("{}\n\n{}", path.join(" > "), chunk.text)
+        }
+        _ => chunk.text.clone(),
+    }
+}
+
+/// Embeds the chunks of a document that have no vector yet, one outcall batch
+/// at a time. Each batch is stored and marked embedded before the next one is
+/// sent; the first failing batch is marked failed and ends the run, so a retry
+/// only embeds what is still missing.
+pub async fn embed_pending_chunks(
+    collection: &Collection,
+    document_id: &str,
+    proxy_url: String,
+    max_chunks: Option<usize>,
+) -> Result<ChunkEmbeddingReport, BluebandError> {
+    const BATCH_SIZE: usize = 3;
+
+    let mut chunks = storage::documents::get_unembedded_chunks(&collection.id, document_id);
+    if let Some(max_chunks) = max_chunks {
+        chunks.truncate(max_chunks);
+    }
+
+    let mut embedded_chunks = 0u32;
+    let mut failed_chunks = 0u32;
+    let mut error = None;
+
+    for chunk_batch in chunks.chunks(BATCH_SIZE) {
+        let chunk_ids: Vec<String> = chunk_batch.iter().map(|chunk| chunk.id.clone()).collect();
+
+        let result = embed_document_chunks(
+            &collection.id,
+            chunk_batch,
+            &collection.settings,
+            proxy_url.clone(),
+        )
+        .await;
+
+        // The document may have been deleted while the outcall was in flight
+        if !storage::documents::document_exists(&collection.id, document_id) {
+            return Err(BluebandError::not_found("Document", document_id));
+        }
+
+        // A model migration may have swapped in another model meanwhile; its
+        // vectors already cover this chunk
+        let current_model =
+            storage::get_collection(&collection.id).map(|c| c.settings.embedding_model);
+        if current_model.as_ref() != Some(&collection.settings.embedding_model) {
+            return Err("Collection embedding model changed during embedding".into());
+        }
+
+        match result.and_then(storage::store_vectors_batch) {
+            Ok(_) => {
+                storage::documents::set_chunk_status(
+                    document_id,
+                    &chunk_ids,
+                    ChunkEmbeddingStatus::Embedded,
+                )?;
+                embedded_chunks += chunk_ids.len() as u32;
+            }
+            Err(e) => {
+                storage::documents::set_chunk_status(
+                    document_id,
+                    &chunk_ids,
+                    ChunkEmbeddingStatus::Failed(e.to_string()),
+                )?;
+                failed_chunks += chunk_ids.len() as u32;
+                error = Some(e);
+                break;
+            }
+        }
+    }
+
+    let document = storage::refresh_document_embedding_state(&collection.id, document_id)?;
+    if embedded_chunks > 0 {
+        super::invalidate_collection_cache(&collection.id);
+    }
+
+    Ok(ChunkEmbeddingReport {
+        embedded_chunks,
+        failed_chunks,
+        pending_chunks: document
+            .total_chunks
+            .saturating_sub(document.embedded_chunks),
+        error,
+    })
+}
+
 pub async fn embed_query_text(
     query: &str,
     model: EmbeddingModel,
     proxy_url: String,
-) -> Result<(Vec<f32>, f32), String> {
+    collection_id: Option<&str>,
+) -> Result<(Vec<f32>, f32), BluebandError> {
     if query.trim().is_empty() {
-        return Err("Query text cannot be empty".to_string());
+        return Err("Query text cannot be empty".into());
     }
 
     let request = EmbeddingRequest {
         texts: vec![query.to_string()],
         model,
         proxy_url,
+        collection_id: collection_id.map(|id| id.to_string()),
     };
 
     let response = get_embeddings_async(request).await?;
 
     if response.embeddings.is_empty() {
-        return Err("No embedding returned for query".to_string());
+        return Err(BluebandError::provider_error(
+            "No embedding returned for query".to_string(),
+        ));
     }
 
     let embedding = &response.embeddings[0];
@@ -232,18 +368,29 @@ pub async fn embed_query_text(
     Ok((embedding.clone(), norm))
 }
 
-fn validate_embedding_request(request: &EmbeddingRequest) -> Result<(), String> {
+fn validate_embedding_request(
+    request: &EmbeddingRequest,
+    max_input_tokens: Option<u32>,
+) -> Result<(), String> {
+    let limits = storage::config::get_canister_config().outcalls;
+
     if request.texts.is_empty() {
         return Err("No texts provided for embedding".to_string());
     }
 
-    if request.texts.len() > 50 {
-        return Err("Too many texts in single request (max 50)".to_string());
+    if request.texts.len() > limits.max_texts_per_request as usize {
+        return Err(format!(
+            "Too many texts in single request (max {})",
+            limits.max_texts_per_request
+        ));
     }
 
     let total_chars: usize = request.texts.iter().map(|t| t.len()).sum();
-    if total_chars > 50_000 {
-        return Err("Total text content too large (max 50k characters)".to_string());
+    if total_chars > limits.max_chars_per_request as usize {
+        return Err(format!(
+            "Total text content too large (max {} characters)",
+            limits.max_chars_per_request
+        ));
     }
 
     if !request.proxy_url.starts_with("https://") {
@@ -255,15 +402,30 @@ fn validate_embedding_request(request: &EmbeddingRequest) -> Result<(), String>
             return Err(format!("Text at index {} is empty", i));
         }
 
-        if text.len() > 4000 {
+        if text.len() > limits.max_chars_per_text as usize {
             return Err(format!(
-                "Text at index {} is too long ({} chars, max 4000)",
+                "Text at index {} is too long ({} chars, max {})",
                 i,
-                text.len()
+                text.len(),
+                limits.max_chars_per_text
             ));
         }
     }
 
+    // Counted only once the texts are known to be small enough to tokenize
+    if let Some(max_tokens) = max_input_tokens {
+        let encoding = storage::tokens::encoding_for_model(&request.model.model_name());
+        for (i, text) in request.texts.iter().enumerate() {
+            let tokens = storage::tokens::count_tokens(text, encoding);
+            if tokens > max_tokens {
+                return Err(format!(
+                    "Text at index {} is too long ({} tokens, max {})",
+                    i, tokens, max_tokens
+                ));
+            }
+        }
+    }
+
     Ok(())
 }
 
@@ -280,31 +442,33 @@ fn create_embedding_request_body(request: &EmbeddingRequest) -> Result<String, S
 fn parse_embedding_response(
     response_body: &[u8],
     model: &EmbeddingModel,
-) -> Result<EmbeddingResponse, String> {
+) -> Result<EmbeddingResponse, OutcallError> {
     if response_body.len() > 2_000_000 {
-        return Err("Response body too large".to_string());
+        return Err(OutcallError::fatal("Response body too large".to_string()));
     }
 
     let body_str = String::from_utf8(response_body.to_vec())
         .map_err(|e| format!("Failed to decode response as UTF-8: {}", e))?;
 
     if body_str.trim().is_empty() {
-        return Err("Received empty response body".to_string());
+        return Err(OutcallError::fatal(
+            "Received empty response body".to_string(),
+        ));
     }
 
     let response_json: serde_json::Value = serde_json::from_str(&body_str)
         .map_err(|e| format!("Failed to parse JSON response: {}", e))?;
 
     if let Some(error) = response_json.get("error") {
-        return Err(format!(
+        return Err(OutcallError::fatal(format!(
             "API error: {}",
             error.to_string().chars().take(200).collect::<String>()
-        ));
+        )));
     }
 
     let data = response_json["data"]
         .as_array()
-        .ok_or("Missing 'data' field in response")?;
+        .ok_or_else(|| "Missing 'data' field in response".to_string())?;
 
     let mut embeddings = Vec::new();
 
@@ -327,12 +491,11 @@ fn parse_embedding_response(
 
         if let Some(expected_dim) = model.expected_dimensions() {
             if embedding.len() != expected_dim {
-                return Err(format!(
-                    "Dimension mismatch for embedding {}: expected {}, got {}",
-                    i,
-                    expected_dim,
-                    embedding.len()
-                ));
+                return Err(BluebandError::DimensionMismatch {
+                    expected: expected_dim as u64,
+                    actual: embedding.len() as u64,
+                }
+                .into());
             }
         }
 
@@ -380,17 +543,113 @@ fn transform_embedding_response(raw: TransformArgs) -> HttpResponse {
             && name_lower != "vary"
     });
 
+    response.body = canonicalize_embedding_body(&response.body);
+
     response
 }
 
-fn generate_idempotency_key(request: &EmbeddingRequest) -> String {
+/// Decimal places kept for embedding values in the transformed body
+const CANONICAL_FLOAT_PRECISION: usize = 7;
+
+/// Reduces a provider response to the fields we parse (embeddings, usage, error message)
+/// and re-serializes it deterministically, so replicas agree byte-for-byte on the same
+/// embedding regardless of float formatting, key order or per-request fields.
+fn canonicalize_embedding_body(body: &[u8]) -> Vec<u8> {
+    let json: serde_json::Value = match serde_json::from_slice(body) {
+        Ok(json) => json,
+        Err(_) => return br#"{"error":{"message":"Unparseable provider response"}}"#.to_vec(),
+    };
+
+    if let Some(error) = json.get("error") {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .or_else(|| error.as_str())
+            .unwrap_or("Provider returned an error");
+        let message = serde_json::Value::String(message.chars().take(200).collect());
+        return format!(r#"{{"error":{{"message":{}}}}}"#, message).into_bytes();
+    }
+
+    let mut items: Vec<(u64, &Vec<serde_json::Value>)> = json
+        .get("data")
+        .and_then(|d| d.as_array())
+        .map(|data| {
+            data.iter()
+                .enumerate()
+                .filter_map(|(i, item)| {
+                    let index = item
+                        .get("index")
+                        .and_then(|idx| idx.as_u64())
+                        .unwrap_or(i as u64);
+                    item.get("embedding")
+                        .and_then(|e| e.as_array())
+                        .map(|embedding| (index, embedding))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    items.sort_by_key(|(index, _)| *index);
+
+    let mut out = String::from(r#"{"data":["#);
+    for (i, (index, embedding)) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&format!(r#"{{"index":{},"embedding":["#, index));
+        for (j, value) in embedding.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            match value.as_f64() {
+                Some(v) => out.push_str(&canonical_float(v)),
+                None => out.push_str("null"),
+            }
+        }
+        out.push_str("]}");
+    }
+    out.push(']');
+
+    if let Some(usage) = json.get("usage") {
+        let prompt_tokens = usage.get("prompt_tokens").and_then(|t| t.as_u64());
+        let total_tokens = usage.get("total_tokens").and_then(|t| t.as_u64());
+
+        out.push_str(r#","usage":{"#);
+        let fields: Vec<String> = [
+            ("prompt_tokens", prompt_tokens),
+            ("total_tokens", total_tokens),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.map(|v| format!(r#""{}":{}"#, name, v)))
+        .collect();
+        out.push_str(&fields.join(","));
+        out.push('}');
+    }
+
+    out.push('}');
+    out.into_bytes()
+}
+
+/// Fixed-precision float without trailing zeros (`-0` normalized to `0`)
+fn canonical_float(value: f64) -> String {
+    let mut formatted = format!("{:.*}", CANONICAL_FLOAT_PRECISION, value);
+    if formatted.contains('.') {
+        let trimmed = formatted.trim_end_matches('0').trim_end_matches('.').len();
+        formatted.truncate(trimmed);
+    }
+    if formatted == "-0" {
+        formatted = "0".to_string();
+    }
+    formatted
+}
+
+fn generate_idempotency_key(request: &EmbeddingRequest, url: &str) -> String {
     use sha2::{Digest, Sha256};
 
     let mut hasher = Sha256::new();
 
     // Hash ONLY the request content
     hasher.update(request.model.model_name().as_bytes());
-    hasher.update(request.proxy_url.as_bytes());
+    hasher.update(url.as_bytes());
 
     // Hash each text in deterministic order
     for text in &request.texts {
@@ -406,3 +665,141 @@ fn generate_idempotency_key(request: &EmbeddingRequest) -> String {
             .fold(0u64, |acc, &b| acc.wrapping_mul(256).wrapping_add(b as u64))
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_body_ignores_formatting_and_extra_fields() {
+        let a = br#"{"object":"list","id":"req_1","created":1700000000,
+            "data":[{"object":"embedding","index":0,"embedding":[0.1,-2.5e-3,1.0]}],
+            "model":"text-embedding-3-small","usage":{"prompt_tokens":3,"total_tokens":3}}"#;
+        let b = br#"{"usage":{"total_tokens":3,"prompt_tokens":3},"id":"req_2",
+            "data":[{"embedding":[0.10000000149011612,-0.0025,1],"index":0}]}"#;
+
+        let canonical = canonicalize_embedding_body(a);
+        assert_eq!(canonical, canonicalize_embedding_body(b));
+        assert_eq!(
+            String::from_utf8(canonical).unwrap(),
+            r#"{"data":[{"index":0,"embedding":[0.1,-0.0025,1]}],"usage":{"prompt_tokens":3,"total_tokens":3}}"#
+        );
+    }
+
+    #[test]
+    fn canonical_body_orders_embeddings_by_index() {
+        let body = br#"{"data":[{"index":1,"embedding":[2.0]},{"index":0,"embedding":[1.0]}]}"#;
+        let parsed = parse_embedding_response(
+            &canonicalize_embedding_body(body),
+            &EmbeddingModel::Custom("test".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(parsed.embeddings, vec![vec![1.0], vec![2.0]]);
+    }
+
+    #[test]
+    fn canonical_body_keeps_only_error_message() {
+        let body = br#"{"error":{"message":"Invalid API key","request_id":"abc"}}"#;
+        assert_eq!(
+            canonicalize_embedding_body(body),
+            br#"{"error":{"message":"Invalid API key"}}"#.to_vec()
+        );
+        assert_eq!(canonical_float(-0.00000001), "0");
+    }
+
+    #[test]
+    fn over_limit_texts_are_refused_before_any_outcall() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+
+        let refused = |request: EmbeddingRequest| {
+            let mut context = Context::from_waker(Waker::noop());
+            match std::pin::pin!(get_embeddings_async(request)).poll(&mut context) {
+                Poll::Ready(result) => result.unwrap_err().to_string(),
+                Poll::Pending => panic!("request reached the outcall"),
+            }
+        };
+        let proxy_url = "https://proxy.example.com/embed".to_string();
+
+        // The model's own limit applies without a collection
+        let err = refused(EmbeddingRequest {
+            texts: vec!["x ".repeat(10_000)],
+            model: EmbeddingModel::OpenAISmall,
+            proxy_url: proxy_url.clone(),
+            collection_id: None,
+        });
+        assert!(err.contains("tokens, max 8191"), "{}", err);
+
+        // A collection's limit overrides it, also for custom models
+        crate::auth::set_test_caller(candid::Principal::from_slice(&[2]));
+        let mut settings = storage::config::get_canister_config().default_collection_settings;
+        settings.embedding_model = "bge-small".to_string();
+        settings.max_input_tokens = Some(8);
+        crate::create_collection(CreateCollectionRequest {
+            id: "short".to_string(),
+            name: "Short".to_string(),
+            description: None,
+            settings: Some(settings),
+            visibility: None,
+        })
+        .unwrap();
+        let err = refused(EmbeddingRequest {
+            texts: vec![
+                "a short text".to_string(),
+                "one two three four five six seven eight nine".to_string(),
+            ],
+            model: EmbeddingModel::Custom("bge-small".to_string()),
+            proxy_url: proxy_url.clone(),
+            collection_id: Some("short".to_string()),
+        });
+        assert!(
+            err.contains("Text at index 1") && err.contains("max 8"),
+            "{}",
+            err
+        );
+
+        assert!(providers::get_provider_health()
+            .iter()
+            .all(|health| !health.endpoint.contains("proxy.example.com")));
+    }
+
+    #[test]
+    fn override_urls_never_receive_secret_headers() {
+        crate::auth::set_test_caller(candid::Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: "keyed".to_string(),
+            name: "Keyed".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        crate::set_collection_secret_headers(
+            "keyed".to_string(),
+            vec![SecretHeader {
+                name: "Authorization".to_string(),
+                value: "Bearer sk-secret".to_string(),
+            }],
+        )
+        .unwrap();
+        let configured = crate::get_collection("keyed".to_string())
+            .unwrap()
+            .settings
+            .proxy_url;
+
+        let request = EmbeddingRequest {
+            texts: vec!["text".to_string()],
+            model: EmbeddingModel::OpenAIAda002,
+            proxy_url: "https://capture.example.com/v1".to_string(),
+            collection_id: Some("keyed".to_string()),
+        };
+        let secret_sent = |url: &str| {
+            build_request_headers(&request, url, "key".to_string())
+                .iter()
+                .any(|header| header.value.contains("sk-secret"))
+        };
+        assert!(!secret_sent(&request.proxy_url));
+        assert!(secret_sent(&configured));
+    }
+}
@@ -0,0 +1,363 @@
+// compute/jobs.rs
+use crate::storage;
+use crate::types::*;
+use std::cell::Cell;
+use std::time::Duration;
+
+// Chunks embedded per timer tick (one outcall batch)
+const JOB_BATCH_CHUNKS: usize = 3;
+
+// Retired vectors deleted per timer tick
+const GC_BATCH_VECTORS: usize = 200;
+
+// A batch that has not finished after this long is assumed to have trapped
+const BATCH_LEASE_NS: u64 = 10 * 60 * 1_000_000_000;
+
+thread_local! {
+    // Start time of the batch currently scheduled or in flight
+    static WORKER_LEASE: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Arms the worker timer when there is queued work or retired vectors to
+/// delete, and no batch is in flight.
+/// Timers do not survive upgrades, so this is also called from `post_upgrade`.
+pub fn schedule_job_processing() {
+    let now = current_time();
+    let busy = WORKER_LEASE.with(|l| {
+        l.get()
+            .is_some_and(|started| now - started < BATCH_LEASE_NS)
+    });
+    let idle =
+        storage::jobs::next_active_job().is_none() && !storage::vectors::has_retired_vectors();
+    if busy || idle {
+        return;
+    }
+
+    WORKER_LEASE.with(|l| l.set(Some(now)));
+    arm_worker();
+}
+
+#[cfg(not(test))]
+fn arm_worker() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(run_next_batch()));
+}
+
+/// Native unit tests have no timers; batches are run by hand
+#[cfg(test)]
+fn arm_worker() {}
+
+/// Re-arms the worker every lease period, so a batch that trapped while
+/// holding the lease does not stall the queue until the next job arrives
+pub fn start_job_watchdog() {
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_nanos(BATCH_LEASE_NS),
+        schedule_job_processing,
+    );
+}
+
+async fn run_next_batch() {
+    // Deleting needs no outcall, so it runs ahead of the job batch
+    storage::vectors::delete_retired_vectors(GC_BATCH_VECTORS);
+
+    if let Err(e) = process_next_batch().await {
+        ic_cdk::println!("Embedding job batch failed: {}", e);
+    }
+
+    WORKER_LEASE.with(|l| l.set(None));
+    schedule_job_processing();
+}
+
+/// Embeds the next batch of chunks of the oldest active job and checkpoints it
+async fn process_next_batch() -> Result<(), String> {
+    let mut job = match storage::jobs::next_active_job() {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+    job.status = JobStatus::Running;
+
+    let collection = match storage::get_collection(&job.collection_id) {
+        Some(collection) => collection,
+        None => {
+            fail_job(job, "Collection not found");
+            return Ok(());
+        }
+    };
+
+    if let Some(migration) = job.migration.clone() {
+        return process_migration_batch(job, collection, migration).await;
+    }
+
+    let doc_idx = match next_document(&job) {
+        Some(idx) => idx,
+        None => {
+            finish_job(&mut job);
+            storage::jobs::save_job(job);
+            return Ok(());
+        }
+    };
+
+    let document_id = job.documents[doc_idx].document_id.clone();
+
+    let document = match storage::get_document(&collection.id, &document_id) {
+        Some(document) => document,
+        None => {
+            mark_document_failed(&mut job, doc_idx, "Document not found".to_string());
+            storage::jobs::save_job(job);
+            return Ok(());
+        }
+    };
+
+    if document.is_embedded {
+        job.documents[doc_idx].status = JobDocumentStatus::Skipped;
+        job.skipped += 1;
+        storage::jobs::save_job(job);
+        return Ok(());
+    }
+
+    if document.total_chunks == 0 {
+        mark_document_failed(
+            &mut job,
+            doc_idx,
+            "No chunks found for document".to_string(),
+        );
+        storage::jobs::save_job(job);
+        return Ok(());
+    }
+
+    job.documents[doc_idx].status = JobDocumentStatus::InProgress;
+    job.documents[doc_idx].total_chunks = document.total_chunks;
+    let job_id = job.id.clone();
+    storage::jobs::save_job(job);
+
+    // Chunk state is checkpointed per batch, so a retried or cancelled
+    // document keeps the vectors it already has
+    let result = super::embed_pending_chunks(
+        &collection,
+        &document_id,
+        collection.settings.proxy_url.clone(),
+        Some(JOB_BATCH_CHUNKS),
+    )
+    .await;
+
+    // Reload: the job may have been cancelled or its collection deleted meanwhile
+    let mut job = match storage::jobs::get_job(&job_id) {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+
+    if job.status == JobStatus::Cancelled {
+        return Ok(());
+    }
+
+    match result {
+        Ok(report) => {
+            let entry = &mut job.documents[doc_idx];
+            entry.chunks_embedded = entry.total_chunks.saturating_sub(report.pending_chunks);
+
+            if let Some(e) = report.error {
+                mark_document_failed(&mut job, doc_idx, e.to_string());
+            } else if report.pending_chunks == 0 {
+                entry.status = JobDocumentStatus::Embedded;
+                job.embedded += 1;
+            }
+        }
+        Err(e) => mark_document_failed(&mut job, doc_idx, e.to_string()),
+    }
+
+    finish_job(&mut job);
+    storage::jobs::save_job(job);
+    Ok(())
+}
+
+/// Embeds the next batch of chunks of a migration into the shadow set, and
+/// swaps the set in once every document has been embedded
+async fn process_migration_batch(
+    mut job: EmbeddingJob,
+    collection: Collection,
+    migration: ModelMigration,
+) -> Result<(), String> {
+    let doc_idx = match next_document(&job) {
+        Some(idx) => idx,
+        None => {
+            // Documents added while the migration ran are embedded before the swap
+            let result = match storage::jobs::queue_new_documents(&mut job) {
+                Ok(0) => complete_migration(&mut job, &migration),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e),
+            };
+            if result.is_err() {
+                fail_migration(&mut job);
+            }
+            storage::jobs::save_job(job);
+            return result.map_err(|e| e.to_string());
+        }
+    };
+
+    let document_id = job.documents[doc_idx].document_id.clone();
+    if !storage::documents::document_exists(&collection.id, &document_id) {
+        job.documents[doc_idx].status = JobDocumentStatus::Skipped;
+        job.skipped += 1;
+        storage::jobs::save_job(job);
+        return Ok(());
+    }
+
+    let chunks = storage::documents::get_document_chunks(&document_id);
+    let entry = &mut job.documents[doc_idx];
+    entry.status = JobDocumentStatus::InProgress;
+    entry.total_chunks = chunks.len() as u32;
+    let batch: Vec<SemanticChunk> = chunks
+        .into_iter()
+        .skip(entry.chunks_embedded as usize)
+        .take(JOB_BATCH_CHUNKS)
+        .collect();
+    let job_id = job.id.clone();
+    storage::jobs::save_job(job);
+
+    let mut settings = collection.settings.clone();
+    settings.embedding_model = migration.to_model.clone();
+    let result = super::embeddings::embed_document_chunks(
+        &collection.id,
+        &batch,
+        &settings,
+        settings.proxy_url.clone(),
+    )
+    .await;
+
+    // Reload: the job may have been cancelled or its collection deleted meanwhile
+    let mut job = match storage::jobs::get_job(&job_id) {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+    if job.status == JobStatus::Cancelled {
+        return Ok(());
+    }
+
+    // A deleted document took its shadow vectors with it
+    if !storage::documents::document_exists(&collection.id, &document_id) {
+        job.documents[doc_idx].status = JobDocumentStatus::Skipped;
+        job.skipped += 1;
+        storage::jobs::save_job(job);
+        return Ok(());
+    }
+
+    match result.and_then(|vectors| storage::vectors::store_shadow_vectors(&collection.id, vectors))
+    {
+        Ok(stored) => {
+            let entry = &mut job.documents[doc_idx];
+            entry.chunks_embedded += stored;
+            if entry.chunks_embedded >= entry.total_chunks {
+                entry.status = JobDocumentStatus::Embedded;
+                job.embedded += 1;
+            }
+        }
+        Err(e) => {
+            mark_document_failed(&mut job, doc_idx, e.to_string());
+            fail_migration(&mut job);
+        }
+    }
+
+    storage::jobs::save_job(job);
+    Ok(())
+}
+
+/// Switches the collection to the new model and its shadow vectors in one
+/// message, so no search sees a mix of the two. Every chunk now has a vector
+/// of the new model, including chunks that had none before.
+fn complete_migration(
+    job: &mut EmbeddingJob,
+    migration: &ModelMigration,
+) -> Result<(), BluebandError> {
+    storage::collections::set_embedding_model(&job.collection_id, &migration.to_model)?;
+    storage::vectors::swap_in_shadow_vectors(&job.collection_id);
+
+    for entry in &job.documents {
+        if entry.status != JobDocumentStatus::Embedded
+            || !storage::documents::document_exists(&job.collection_id, &entry.document_id)
+        {
+            continue;
+        }
+        let chunk_ids: Vec<String> = storage::documents::get_document_chunks(&entry.document_id)
+            .into_iter()
+            .map(|chunk| chunk.id)
+            .collect();
+        storage::documents::set_chunk_status(
+            &entry.document_id,
+            &chunk_ids,
+            ChunkEmbeddingStatus::Embedded,
+        )?;
+        storage::refresh_document_embedding_state(&job.collection_id, &entry.document_id)?;
+    }
+
+    super::invalidate_collection_cache(&job.collection_id);
+    job.status = JobStatus::Completed;
+    Ok(())
+}
+
+/// A migration swaps all documents or none: the first failure ends it and
+/// discards the shadow vectors, leaving the collection on its old model
+fn fail_migration(job: &mut EmbeddingJob) {
+    for entry in job.documents.iter_mut() {
+        if matches!(
+            entry.status,
+            JobDocumentStatus::Pending | JobDocumentStatus::InProgress
+        ) {
+            entry.status = JobDocumentStatus::Cancelled;
+        }
+    }
+    job.status = JobStatus::Failed;
+    storage::vectors::discard_shadow_vectors(&job.collection_id);
+}
+
+fn next_document(job: &EmbeddingJob) -> Option<usize> {
+    job.documents.iter().position(|d| {
+        matches!(
+            d.status,
+            JobDocumentStatus::Pending | JobDocumentStatus::InProgress
+        )
+    })
+}
+
+fn mark_document_failed(job: &mut EmbeddingJob, doc_idx: usize, error: String) {
+    let entry = &mut job.documents[doc_idx];
+    entry.status = JobDocumentStatus::Failed;
+    entry.error = Some(
+        error
+            .chars()
+            .take(storage::jobs::MAX_JOB_ERROR_CHARS)
+            .collect(),
+    );
+    job.failed += 1;
+}
+
+/// Completes the job once no document is left to process
+fn finish_job(job: &mut EmbeddingJob) {
+    let remaining = job.documents.iter().any(|d| {
+        matches!(
+            d.status,
+            JobDocumentStatus::Pending | JobDocumentStatus::InProgress
+        )
+    });
+
+    if !remaining {
+        job.status = if job.failed > 0 && job.embedded == 0 {
+            JobStatus::Failed
+        } else {
+            JobStatus::Completed
+        };
+    }
+}
+
+fn fail_job(mut job: EmbeddingJob, error: &str) {
+    for entry in job.documents.iter_mut() {
+        if matches!(
+            entry.status,
+            JobDocumentStatus::Pending | JobDocumentStatus::InProgress
+        ) {
+            entry.status = JobDocumentStatus::Failed;
+            entry.error = Some(error.to_string());
+            job.failed += 1;
+        }
+    }
+    job.status = JobStatus::Failed;
+    storage::jobs::save_job(job);
+}
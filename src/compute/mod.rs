@@ -1,6 +1,11 @@
 // compute/mod.rs
+pub mod ask;
 pub mod cache;
+pub mod context;
 pub mod embeddings;
+pub mod jobs;
+pub mod providers;
+pub mod rerank;
 pub mod similarity;
 
 
@@ -10,21 +15,34 @@ pub use similarity::{
     SimilarityConfig,
 };
 
-pub use embeddings::{embed_document_chunks, embed_query_text};
+pub use embeddings::{embed_pending_chunks, embed_query_text};
 
 pub use crate::types::{EmbeddingModel};
+use crate::types::BluebandError;
 
-pub use cache::{cleanup_cache, clear_cache, get_cache_stats, invalidate_collection_cache};
+pub use cache::{
+    cleanup_cache, clear_cache, configure_cache, get_cache_stats, invalidate_collection_cache,
+};
+
+pub use jobs::{schedule_job_processing, start_job_watchdog};
+
+pub use providers::get_provider_health;
+
+pub use rerank::{rerank_matches, resolve_rerank};
+
+pub use ask::answer_question;
+
+pub use context::{expand_matches, widen_matches};
 
 
-pub fn validate_embedding(embedding: &[f32]) -> Result<(), String> {
+pub fn validate_embedding(embedding: &[f32]) -> Result<(), BluebandError> {
     if embedding.is_empty() {
-        return Err("Embedding is empty".to_string());
+        return Err("Embedding is empty".into());
     }
 
     for (i, &val) in embedding.iter().enumerate() {
         if !val.is_finite() {
-            return Err(format!("Invalid value at position {}: {}", i, val));
+            return Err(format!("Invalid value at position {}: {}", i, val).into());
         }
     }
 
@@ -32,30 +50,38 @@ pub fn validate_embedding(embedding: &[f32]) -> Result<(), String> {
 }
 
 
-pub fn calculate_norm(embedding: &[f32]) -> Result<f32, String> {
+pub fn calculate_norm(embedding: &[f32]) -> Result<f32, BluebandError> {
     validate_embedding(embedding)?;
 
     let norm_squared: f32 = embedding.iter().map(|x| x * x).sum();
     let norm = norm_squared.sqrt();
 
     if !norm.is_finite() || norm <= 0.0 {
-        return Err(format!("Invalid norm: {}", norm));
+        return Err(format!("Invalid norm: {}", norm).into());
     }
 
     Ok(norm)
 }
 
 
-pub fn cosine_similarity(a: &[f32], b: &[f32], norm_a: f32, norm_b: f32) -> Result<f64, String> {
+pub fn cosine_similarity(
+    a: &[f32],
+    b: &[f32],
+    norm_a: f32,
+    norm_b: f32,
+) -> Result<f64, BluebandError> {
     if a.len() != b.len() {
-        return Err("Dimension mismatch".to_string());
+        return Err(BluebandError::DimensionMismatch {
+            expected: a.len() as u64,
+            actual: b.len() as u64,
+        });
     }
 
     let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
     let similarity = (dot_product / (norm_a * norm_b)) as f64;
 
     if !similarity.is_finite() {
-        return Err("Invalid similarity result".to_string());
+        return Err("Invalid similarity result".into());
     }
 
     Ok(similarity)
@@ -0,0 +1,372 @@
+// compute/rerank.rs
+
+use super::providers::{self, OutcallError};
+use crate::types::*;
+use ic_cdk::api::management_canister::http_request::{
+    CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformArgs,
+    TransformContext, TransformFunc,
+};
+use ic_cdk_macros::query;
+
+/// Candidates sent to the reranker when neither the collection nor the request sets `top_n`
+pub const DEFAULT_RERANK_TOP_N: u32 = 20;
+
+const MAX_RERANK_TOP_N: u32 = 100;
+
+/// Decimal places kept for relevance scores in the transformed body
+const CANONICAL_SCORE_PRECISION: usize = 6;
+
+/// Rerank stage to apply to a search, with the number of candidates to send.
+/// The request can turn the collection's stage off or change its `top_n`.
+pub fn resolve_rerank(
+    settings: &CollectionSettings,
+    request: &SearchRequest,
+) -> Result<Option<(RerankConfig, u32)>, BluebandError> {
+    let config = match (request.rerank, &settings.rerank) {
+        (Some(false), _) | (None, None) => return Ok(None),
+        (Some(true), None) => {
+            return Err("Reranking requested but the collection has no rerank endpoint".into())
+        }
+        (_, Some(config)) => config.clone(),
+    };
+
+    let top_n = request
+        .rerank_top_n
+        .or(config.top_n)
+        .unwrap_or(DEFAULT_RERANK_TOP_N)
+        .clamp(1, MAX_RERANK_TOP_N);
+
+    Ok(Some((config, top_n)))
+}
+
+/// Re-sorts the first `top_n` cosine matches by cross-encoder relevance and
+/// returns at most `limit` of them. Reranked matches carry the provider's score.
+pub async fn rerank_matches(
+    query: &str,
+    mut matches: Vec<VectorMatch>,
+    config: &RerankConfig,
+    top_n: u32,
+    limit: usize,
+    collection_id: Option<&str>,
+) -> Result<Vec<VectorMatch>, BluebandError> {
+    matches.truncate(top_n as usize);
+    if matches.len() < 2 {
+        return Ok(matches);
+    }
+
+    let texts: Vec<String> = matches
+        .iter()
+        .map(|m| m.chunk_text.clone().unwrap_or_default())
+        .collect();
+
+    let request = send_rerank_request(query, &texts, config, collection_id);
+    match providers::send_tracked(&config.url, request).await {
+        Ok(scores) => apply_rerank_scores(matches, &scores, limit),
+        Err(e) if config.fallback_on_error => {
+            ic_cdk::println!("Rerank failed, keeping cosine order: {}", e);
+            matches.truncate(limit);
+            Ok(matches)
+        }
+        Err(e) => Err(e.with_context("Rerank failed")),
+    }
+}
+
+/// Orders matches by their rerank score. Matches the provider left out keep
+/// their cosine score and follow the scored ones in their original order.
+fn apply_rerank_scores(
+    matches: Vec<VectorMatch>,
+    scores: &[(usize, f64)],
+    limit: usize,
+) -> Result<Vec<VectorMatch>, BluebandError> {
+    let mut rerank_scores = vec![None; matches.len()];
+    for &(index, score) in scores {
+        let slot = rerank_scores.get_mut(index).ok_or_else(|| {
+            BluebandError::provider_error(format!("Rerank result index {} out of range", index))
+        })?;
+        if slot.replace(score).is_some() {
+            return Err(BluebandError::provider_error(format!(
+                "Duplicate rerank result for index {}",
+                index
+            )));
+        }
+    }
+
+    let (mut scored, unscored): (Vec<_>, Vec<_>) = matches
+        .into_iter()
+        .zip(rerank_scores)
+        .partition(|(_, score)| score.is_some());
+
+    // Stable sort: ties keep the cosine order
+    scored.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+
+    Ok(scored
+        .into_iter()
+        .map(|(mut m, score)| {
+            m.score = score.unwrap_or(m.score);
+            m
+        })
+        .chain(unscored.into_iter().map(|(m, _)| m))
+        .take(limit)
+        .collect())
+}
+
+/// Single outcall to the rerank endpoint, returning `(index, score)` pairs
+async fn send_rerank_request(
+    query: &str,
+    texts: &[String],
+    config: &RerankConfig,
+    collection_id: Option<&str>,
+) -> Result<Vec<(usize, f64)>, OutcallError> {
+    let request_body = create_rerank_request_body(query, texts, config);
+
+    // Index and score per result plus JSON overhead
+    let max_response_bytes = (texts.len() * 200 + 1_000) as u64;
+    let cycles_needed = providers::outcall_cycles(request_body.len(), max_response_bytes as usize);
+
+    let http_request_arg = CanisterHttpRequestArgument {
+        url: config.url.clone(),
+        method: HttpMethod::POST,
+        body: Some(request_body.into_bytes()),
+        max_response_bytes: Some(max_response_bytes),
+        transform: Some(TransformContext {
+            function: TransformFunc(candid::Func {
+                principal: ic_cdk::api::id(),
+                method: "transform_rerank_response".to_string(),
+            }),
+            context: vec![],
+        }),
+        headers: vec![
+            HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            },
+            HttpHeader {
+                name: "User-Agent".to_string(),
+                value: "IC-VectorDB/1.0".to_string(),
+            },
+        ],
+    };
+
+    // Rerank providers bill by search unit, not by token
+    providers::metered_outcall(collection_id, http_request_arg, cycles_needed, |response| {
+        parse_rerank_response(&response.body).map(|scores| (scores, 0))
+    })
+    .await
+}
+
+fn create_rerank_request_body(query: &str, texts: &[String], config: &RerankConfig) -> String {
+    let mut body = match config.format {
+        RerankFormat::Cohere => serde_json::json!({
+            "query": query,
+            "documents": texts,
+            "top_n": texts.len(),
+            "return_documents": false,
+        }),
+        RerankFormat::Tei => serde_json::json!({
+            "query": query,
+            "texts": texts,
+            "return_text": false,
+        }),
+    };
+    if let Some(model) = &config.model {
+        body["model"] = serde_json::Value::String(model.clone());
+    }
+    body.to_string()
+}
+
+/// Parses the canonical body produced by `transform_rerank_response`
+fn parse_rerank_response(response_body: &[u8]) -> Result<Vec<(usize, f64)>, OutcallError> {
+    let response_json: serde_json::Value = serde_json::from_slice(response_body)
+        .map_err(|e| format!("Failed to parse rerank response: {}", e))?;
+
+    if let Some(error) = response_json.get("error") {
+        return Err(OutcallError::fatal(format!(
+            "API error: {}",
+            error.to_string().chars().take(200).collect::<String>()
+        )));
+    }
+
+    let results = response_json["results"]
+        .as_array()
+        .ok_or_else(|| "Missing 'results' field in rerank response".to_string())?;
+
+    results
+        .iter()
+        .map(|item| {
+            let index = item["index"].as_u64();
+            let score = item["relevance_score"].as_f64();
+            match (index, score) {
+                (Some(index), Some(score)) if score.is_finite() => Ok((index as usize, score)),
+                _ => Err(OutcallError::fatal(format!(
+                    "Invalid rerank result: {}",
+                    item
+                ))),
+            }
+        })
+        .collect()
+}
+
+#[query]
+fn transform_rerank_response(raw: TransformArgs) -> HttpResponse {
+    HttpResponse {
+        status: raw.response.status,
+        headers: Vec::new(),
+        body: canonicalize_rerank_body(&raw.response.body),
+    }
+}
+
+/// Reduces a Cohere/Jina (`{results: [{index, relevance_score}]}`) or TEI
+/// (`[{index, score}]`) response to `{"results":[{"index":..,"relevance_score":..}]}`
+/// ordered by index with fixed-precision scores, so replicas agree byte-for-byte.
+fn canonicalize_rerank_body(body: &[u8]) -> Vec<u8> {
+    let json: serde_json::Value = match serde_json::from_slice(body) {
+        Ok(json) => json,
+        Err(_) => return br#"{"error":{"message":"Unparseable provider response"}}"#.to_vec(),
+    };
+
+    if let Some(error) = json.get("error") {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .or_else(|| error.as_str())
+            .unwrap_or("Provider returned an error");
+        let message = serde_json::Value::String(message.chars().take(200).collect());
+        return format!(r#"{{"error":{{"message":{}}}}}"#, message).into_bytes();
+    }
+
+    let items = json
+        .get("results")
+        .and_then(|r| r.as_array())
+        .or_else(|| json.as_array());
+
+    let mut results: Vec<(u64, f64)> = items
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| {
+                    let index = item.get("index")?.as_u64()?;
+                    let score = item
+                        .get("relevance_score")
+                        .or_else(|| item.get("score"))?
+                        .as_f64()?;
+                    Some((index, score))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    results.sort_by_key(|(index, _)| *index);
+
+    let results: Vec<String> = results
+        .iter()
+        .map(|(index, score)| {
+            format!(
+                r#"{{"index":{},"relevance_score":{:.*}}}"#,
+                index, CANONICAL_SCORE_PRECISION, score
+            )
+        })
+        .collect();
+    format!(r#"{{"results":[{}]}}"#, results.join(",")).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector_match(chunk_id: &str, score: f64) -> VectorMatch {
+        VectorMatch {
+            score,
+            document_id: "doc".to_string(),
+            chunk_id: chunk_id.to_string(),
+            document_title: None,
+            chunk_text: Some(chunk_id.to_string()),
+            heading_path: None,
+            page: None,
+        }
+    }
+
+    #[test]
+    fn canonical_body_accepts_cohere_and_tei_shapes() {
+        // Shapes returned by mock/rerank-server.ts for the same request
+        let cohere = br#"{"id":"r1","results":[{"index":1,"relevance_score":0.91},
+            {"index":0,"relevance_score":0.125}],"meta":{"billed_units":{"search_units":1}}}"#;
+        let tei = br#"[{"index":1,"score":0.9100000001},{"index":0,"score":0.125}]"#;
+
+        let canonical = canonicalize_rerank_body(cohere);
+        assert_eq!(canonical, canonicalize_rerank_body(tei));
+        assert_eq!(
+            parse_rerank_response(&canonical).unwrap(),
+            vec![(0, 0.125), (1, 0.91)]
+        );
+
+        let error = canonicalize_rerank_body(br#"{"error":"model not loaded","code":503}"#);
+        assert!(parse_rerank_response(&error).is_err());
+    }
+
+    #[test]
+    fn rerank_scores_reorder_and_truncate_matches() {
+        let matches = vec![
+            vector_match("a", 0.9),
+            vector_match("b", 0.8),
+            vector_match("c", 0.7),
+            vector_match("d", 0.6),
+        ];
+
+        // "c" was left out by the provider and follows the scored matches
+        let reranked =
+            apply_rerank_scores(matches.clone(), &[(0, 0.2), (1, 0.2), (3, 0.95)], 3).unwrap();
+        let order: Vec<_> = reranked.iter().map(|m| m.chunk_id.as_str()).collect();
+        assert_eq!(order, vec!["d", "a", "b"]);
+        assert_eq!(reranked[0].score, 0.95);
+
+        // A malformed answer is the provider's failure, not the caller's
+        for scores in [vec![(4, 0.5)], vec![(1, 0.5), (1, 0.4)]] {
+            let err = apply_rerank_scores(matches.clone(), &scores, 3).unwrap_err();
+            assert!(matches!(
+                err,
+                BluebandError::OutcallFailed {
+                    retryable: false,
+                    ..
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn request_can_override_collection_rerank() {
+        let mut settings = CollectionSettings::default();
+        let mut request = SearchRequest {
+            collection_id: "c".to_string(),
+            query: "q".to_string(),
+            limit: None,
+            min_score: None,
+            filter: None,
+            use_approximate: None,
+            include_partial: None,
+            rerank: None,
+            rerank_top_n: None,
+            context_window: None,
+        };
+        assert!(resolve_rerank(&settings, &request).unwrap().is_none());
+
+        request.rerank = Some(true);
+        assert!(resolve_rerank(&settings, &request).is_err());
+
+        settings.rerank = Some(RerankConfig {
+            url: "https://rerank.example.com/rerank".to_string(),
+            model: None,
+            format: RerankFormat::Tei,
+            top_n: Some(30),
+            fallback_on_error: false,
+        });
+        assert_eq!(resolve_rerank(&settings, &request).unwrap().unwrap().1, 30);
+
+        request.rerank_top_n = Some(1_000);
+        assert_eq!(
+            resolve_rerank(&settings, &request).unwrap().unwrap().1,
+            MAX_RERANK_TOP_N
+        );
+
+        request.rerank = Some(false);
+        assert!(resolve_rerank(&settings, &request).unwrap().is_none());
+    }
+}
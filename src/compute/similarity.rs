@@ -11,6 +11,8 @@ pub struct SimilarityConfig {
     pub max_results: u32,
     pub use_approximate: bool,
     pub candidate_factor: f32,
+    /// Whether chunks of partially embedded documents can match
+    pub include_partial: bool,
 }
 
 impl Default for SimilarityConfig {
@@ -20,6 +22,7 @@ impl Default for SimilarityConfig {
             max_results: 10,
             use_approximate: true,
             candidate_factor: 3.0,
+            include_partial: true,
         }
     }
 }
@@ -288,12 +291,16 @@ pub fn cosine_similarity_search(
     query_embedding: &[f32],
     collection_id: &str,
     config: &SimilarityConfig,
-) -> Result<Vec<VectorMatch>, String> {
+) -> Result<Vec<VectorMatch>, BluebandError> {
 
     validate_embedding(query_embedding)?;
 
     // Get vectors from storage (with caching)
-    let vectors = super::cache::get_cached_vectors(collection_id);
+    let vectors = exclude_partial_documents(
+        collection_id,
+        super::cache::get_cached_vectors(collection_id),
+        config,
+    );
 
     if vectors.is_empty() {
         return Ok(Vec::new());
@@ -313,13 +320,16 @@ pub fn cosine_similarity_search(
     let mut matches = Vec::new();
 
     for (score, vector) in scored_vectors {
+        let chunk = storage::documents::get_chunk(&vector.document_id, &vector.chunk_id);
         let vector_match = VectorMatch {
             score,
             document_id: vector.document_id.clone(),
             chunk_id: vector.chunk_id.clone(),
             // Enrich with actual content
             document_title: storage::get_document_title(collection_id, &vector.document_id),
-            chunk_text: storage::get_chunk_text(&vector.document_id, &vector.chunk_id),
+            chunk_text: chunk.as_ref().map(|c| c.text.clone()),
+            heading_path: chunk.as_ref().and_then(|c| c.heading_path.clone()),
+            page: chunk.and_then(|c| c.page),
         };
 
         matches.push(vector_match);
@@ -328,6 +338,27 @@ pub fn cosine_similarity_search(
     Ok(matches)
 }
 
+/// Drops vectors of partially embedded documents unless the config includes them
+fn exclude_partial_documents(
+    collection_id: &str,
+    vectors: Vec<Vector>,
+    config: &SimilarityConfig,
+) -> Vec<Vector> {
+    if config.include_partial {
+        return vectors;
+    }
+
+    let partial = storage::documents::partially_embedded_document_ids(collection_id);
+    if partial.is_empty() {
+        return vectors;
+    }
+
+    vectors
+        .into_iter()
+        .filter(|vector| !partial.contains(&vector.document_id))
+        .collect()
+}
+
 /// Exact similarity search (original algorithm from Vectra db)
 fn exact_similarity_search(
     query_embedding: &[f32],
@@ -376,18 +407,17 @@ pub fn find_similar_documents(
     source_document_id: &str,
     collection_id: &str,
     config: &SimilarityConfig,
-) -> Result<Vec<VectorMatch>, String> {
-
-    let source_vectors = storage::get_document_vectors(source_document_id);
+) -> Result<Vec<VectorMatch>, BluebandError> {
+    let source_vectors = storage::get_document_vectors(collection_id, source_document_id);
 
     if source_vectors.is_empty() {
-        return Err(format!(
-            "No vectors found for document: {}",
-            source_document_id
+        return Err(BluebandError::not_found(
+            "Vectors of document",
+            source_document_id,
         ));
     }
 
-    // Calculate document centroid 
+    // Calculate document centroid
     let centroid_embedding = calculate_document_centroid(&source_vectors)?;
 
     // Perform similarity search using the centroid
@@ -404,7 +434,7 @@ pub fn compute_similarity_batch(
     query_embeddings: &[Vec<f32>],
     collection_id: &str,
     config: &SimilarityConfig,
-) -> Result<Vec<Vec<VectorMatch>>, String> {
+) -> Result<Vec<Vec<VectorMatch>>, BluebandError> {
     let mut results = Vec::new();
 
     for query_embedding in query_embeddings {
@@ -421,10 +451,14 @@ pub fn similarity_search_filtered(
     collection_id: &str,
     document_filter: Option<&[String]>,
     config: &SimilarityConfig,
-) -> Result<Vec<VectorMatch>, String> {
+) -> Result<Vec<VectorMatch>, BluebandError> {
     validate_embedding(query_embedding)?;
 
-    let vectors = super::cache::get_cached_vectors(collection_id);
+    let vectors = exclude_partial_documents(
+        collection_id,
+        super::cache::get_cached_vectors(collection_id),
+        config,
+    );
 
     if vectors.is_empty() {
         return Ok(Vec::new());
@@ -454,12 +488,15 @@ pub fn similarity_search_filtered(
     let mut matches = Vec::new();
 
     for (score, vector) in scored_vectors {
+        let chunk = storage::documents::get_chunk(&vector.document_id, &vector.chunk_id);
         let vector_match = VectorMatch {
             score,
             document_id: vector.document_id.clone(),
             chunk_id: vector.chunk_id.clone(),
             document_title: storage::get_document_title(collection_id, &vector.document_id),
-            chunk_text: storage::get_chunk_text(&vector.document_id, &vector.chunk_id),
+            chunk_text: chunk.as_ref().map(|c| c.text.clone()),
+            heading_path: chunk.as_ref().and_then(|c| c.heading_path.clone()),
+            page: chunk.and_then(|c| c.page),
         };
 
         matches.push(vector_match);
@@ -468,9 +505,9 @@ pub fn similarity_search_filtered(
     Ok(matches)
 }
 
-fn calculate_document_centroid(vectors: &[Vector]) -> Result<Vec<f32>, String> {
+fn calculate_document_centroid(vectors: &[Vector]) -> Result<Vec<f32>, BluebandError> {
     if vectors.is_empty() {
-        return Err("Cannot calculate centroid of empty vector set".to_string());
+        return Err("Cannot calculate centroid of empty vector set".into());
     }
 
     let dimension = vectors[0].embedding.len();
@@ -478,11 +515,10 @@ fn calculate_document_centroid(vectors: &[Vector]) -> Result<Vec<f32>, String> {
     // Verify all vectors have same dimension
     for vector in vectors {
         if vector.embedding.len() != dimension {
-            return Err(format!(
-                "Dimension mismatch: expected {}, got {}",
-                dimension,
-                vector.embedding.len()
-            ));
+            return Err(BluebandError::DimensionMismatch {
+                expected: dimension as u64,
+                actual: vector.embedding.len() as u64,
+            });
         }
     }
 
@@ -534,10 +570,10 @@ impl MemoryVectorIndex {
         text: String,
         model: EmbeddingModel,
         proxy_url: String,
-    ) -> Result<(), String> {
+    ) -> Result<(), BluebandError> {
         // Generate embedding for the text
         let (embedding, norm) =
-            super::embeddings::embed_query_text(&text, model, proxy_url).await?;
+            super::embeddings::embed_query_text(&text, model, proxy_url, None).await?;
 
         let item = MemoryVectorItem {
             id: id.clone(),
@@ -556,7 +592,7 @@ impl MemoryVectorIndex {
         query_norm: f32,
         max_results: usize,
         min_score: Option<f64>,
-    ) -> Result<Vec<MemorySearchResult>, String> {
+    ) -> Result<Vec<MemorySearchResult>, BluebandError> {
         let mut scored_items = Vec::new();
 
         for item in &self.items {
@@ -603,9 +639,9 @@ impl MemoryVectorIndex {
         proxy_url: String,
         max_results: usize,
         min_score: Option<f64>,
-    ) -> Result<Vec<MemorySearchResult>, String> {
+    ) -> Result<Vec<MemorySearchResult>, BluebandError> {
         let (query_embedding, query_norm) =
-            super::embeddings::embed_query_text(query_text, model, proxy_url).await?;
+            super::embeddings::embed_query_text(query_text, model, proxy_url, None).await?;
         self.search_similar(&query_embedding, query_norm, max_results, min_score)
     }
 }
@@ -625,7 +661,7 @@ pub async fn create_and_search_memory_index(
     proxy_url: String,
     max_results: usize,
     min_score: Option<f64>,
-) -> Result<Vec<MemorySearchResult>, String> {
+) -> Result<Vec<MemorySearchResult>, BluebandError> {
     let mut index = MemoryVectorIndex::new();
 
     for (i, item_text) in items.iter().enumerate() {
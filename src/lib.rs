@@ -4,11 +4,13 @@ use crate::storage::collections;
 use crate::storage::documents;
 use crate::storage::vectors;
 use candid::CandidType;
-use ic_cdk::api::caller;
+use crate::auth::{caller, Permission};
 use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 
+mod auth;
 mod compute;
 mod storage;
 mod types;
@@ -22,6 +24,7 @@ pub use types::*;
 #[init]
 #[candid::candid_method(init)]
 fn init() {
+    compute::start_job_watchdog();
     ic_cdk::println!("Vector Database canister initialized");
 }
 
@@ -32,6 +35,16 @@ fn pre_upgrade() {
 
 #[post_upgrade]
 fn post_upgrade() {
+    let opened = storage::billing::open_legacy_accounts();
+    if opened > 0 {
+        ic_cdk::println!(
+            "Opened {} collection accounts with the grace balance",
+            opened
+        );
+    }
+    // Timers are not preserved across upgrades: resume any queued embedding jobs
+    compute::start_job_watchdog();
+    compute::schedule_job_processing();
     ic_cdk::println!("Canister upgrade completed");
 }
 
@@ -42,6 +55,12 @@ fn post_upgrade() {
 #[update]
 #[candid::candid_method(update)]
 fn create_collection(request: CreateCollectionRequest) -> Result<Collection, String> {
+    create_collection_v2(request).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn create_collection_v2(request: CreateCollectionRequest) -> Result<Collection, BluebandError> {
     let caller = caller().to_string();
     storage::create_collection(request, caller)
 }
@@ -49,25 +68,31 @@ fn create_collection(request: CreateCollectionRequest) -> Result<Collection, Str
 #[query]
 #[candid::candid_method(query)]
 fn get_collection(collection_id: String) -> Option<Collection> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
     storage::get_collection(&collection_id)
 }
 
 #[query]
 #[candid::candid_method(query)]
 fn list_collections() -> Vec<Collection> {
-    storage::list_collections()
+    collections::list_visible_collections(&caller().to_string())
 }
 
 #[query]
 #[candid::candid_method(query)]
 fn get_collection_with_stats(collection_id: String) -> Option<CollectionWithStats> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
     storage::get_collection_with_stats(&collection_id)
 }
 
 #[query]
 #[candid::candid_method(query)]
 fn list_collections_with_stats() -> Vec<CollectionWithStats> {
-    storage::list_collections_with_stats()
+    storage::list_collections_with_stats(&caller().to_string())
 }
 
 #[update]
@@ -76,8 +101,34 @@ fn update_collection_settings(
     collection_id: String,
     settings: CollectionSettings,
 ) -> Result<(), String> {
-    let caller = ic_cdk::caller().to_string();
-    collections::update_collection_settings(&collection_id, settings, &caller)
+    update_collection_settings_v2(collection_id, settings).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn update_collection_settings_v2(
+    collection_id: String,
+    settings: CollectionSettings,
+) -> Result<(), BluebandError> {
+    audited(
+        "update_collection_settings",
+        Some(&collection_id),
+        json!({ "settings": &settings }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageSettings,
+            ))?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::UpdateSettings {
+                    settings: Box::new(settings.clone()),
+                },
+            )?;
+            let caller = caller().to_string();
+            collections::update_collection_settings(&collection_id, settings, &caller)
+        },
+    )
 }
 
 #[update]
@@ -87,48 +138,188 @@ fn update_collection_metadata(
     name: Option<String>,
     description: Option<String>,
 ) -> Result<(), String> {
-    let caller = ic_cdk::caller().to_string();
+    update_collection_metadata_v2(collection_id, name, description).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn update_collection_metadata_v2(
+    collection_id: String,
+    name: Option<String>,
+    description: Option<String>,
+) -> Result<(), BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::ManageSettings,
+    ))?;
+    let caller = caller().to_string();
     collections::update_collection_metadata(&collection_id, name, description, &caller)
 }
 
 #[update]
 #[candid::candid_method(update)]
 fn delete_collection(collection_id: String) -> Result<(), String> {
-    let caller = ic_cdk::caller().to_string();
-    collections::delete_collection(&collection_id, &caller)
+    delete_collection_v2(collection_id).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn delete_collection_v2(collection_id: String) -> Result<(), BluebandError> {
+    audited("delete_collection", Some(&collection_id), json!({}), || {
+        auth::authorize(Permission::GenesisAdmin(collection_id.clone()))?;
+        storage::proposals::require_no_approval(&collection_id, &ProposalAction::DeleteCollection)?;
+        let caller = caller().to_string();
+        collections::delete_collection(&collection_id, &caller)?;
+        // Its vectors are deleted in the background
+        compute::schedule_job_processing();
+        Ok(())
+    })
 }
 
 #[update]
 #[candid::candid_method(update)]
 fn add_collection_admin(collection_id: String, new_admin: String) -> Result<(), String> {
-    let caller = ic_cdk::caller().to_string();
-    collections::add_collection_admin(&collection_id, &new_admin, &caller)
+    add_collection_admin_v2(collection_id, new_admin).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn add_collection_admin_v2(collection_id: String, new_admin: String) -> Result<(), BluebandError> {
+    audited(
+        "add_collection_admin",
+        Some(&collection_id),
+        json!({ "new_admin": &new_admin }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageMembers,
+            ))?;
+            let caller = caller().to_string();
+            collections::add_collection_admin(&collection_id, &new_admin, &caller)
+        },
+    )
 }
 
 #[update]
 #[candid::candid_method(update)]
 fn remove_collection_admin(collection_id: String, admin_to_remove: String) -> Result<(), String> {
-    let caller = ic_cdk::caller().to_string();
-    collections::remove_collection_admin(&collection_id, &admin_to_remove, &caller)
+    remove_collection_admin_v2(collection_id, admin_to_remove).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn remove_collection_admin_v2(
+    collection_id: String,
+    admin_to_remove: String,
+) -> Result<(), BluebandError> {
+    audited(
+        "remove_collection_admin",
+        Some(&collection_id),
+        json!({ "admin": &admin_to_remove }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageMembers,
+            ))?;
+            let caller = caller().to_string();
+            collections::remove_collection_admin(&collection_id, &admin_to_remove, &caller)
+        },
+    )
 }
 
 #[update]
 #[candid::candid_method(update)]
 fn transfer_genesis_admin(collection_id: String, new_genesis_admin: String) -> Result<(), String> {
-    let caller = ic_cdk::caller().to_string();
-    collections::transfer_genesis_admin(&collection_id, &new_genesis_admin, &caller)
+    transfer_genesis_admin_v2(collection_id, new_genesis_admin).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn transfer_genesis_admin_v2(
+    collection_id: String,
+    new_genesis_admin: String,
+) -> Result<(), BluebandError> {
+    audited(
+        "transfer_genesis_admin",
+        Some(&collection_id),
+        json!({ "new_genesis_admin": &new_genesis_admin }),
+        || {
+            auth::authorize(Permission::GenesisAdmin(collection_id.clone()))?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::TransferGenesisAdmin {
+                    new_genesis_admin: new_genesis_admin.clone(),
+                },
+            )?;
+            let caller = caller().to_string();
+            collections::transfer_genesis_admin(&collection_id, &new_genesis_admin, &caller)
+        },
+    )
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn set_collection_secret_headers(
+    collection_id: String,
+    headers: Vec<SecretHeader>,
+) -> Result<String, String> {
+    set_collection_secret_headers_v2(collection_id, headers).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn set_collection_secret_headers_v2(
+    collection_id: String,
+    headers: Vec<SecretHeader>,
+) -> Result<String, BluebandError> {
+    // Header names only: values never reach the log
+    let names: Vec<&str> = headers.iter().map(|h| h.name.as_str()).collect();
+    audited(
+        "set_collection_secret_headers",
+        Some(&collection_id),
+        json!({ "headers": names }),
+        || {
+            auth::authorize(Permission::GenesisAdmin(collection_id.clone()))?;
+            let caller = caller().to_string();
+            storage::secrets::set_secret_headers(&collection_id, headers, &caller)
+        },
+    )
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn clear_collection_secret_headers(collection_id: String) -> Result<(), String> {
+    clear_collection_secret_headers_v2(collection_id).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn clear_collection_secret_headers_v2(collection_id: String) -> Result<(), BluebandError> {
+    audited(
+        "clear_collection_secret_headers",
+        Some(&collection_id),
+        json!({}),
+        || {
+            auth::authorize(Permission::GenesisAdmin(collection_id.clone()))?;
+            let caller = caller().to_string();
+            storage::secrets::clear_secret_headers(&collection_id, &caller)
+        },
+    )
 }
 
 #[query]
 #[candid::candid_method(query)]
 fn is_collection_admin(collection_id: String, principal: String) -> bool {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return false;
+    }
     storage::is_collection_admin(&collection_id, &principal)
 }
 
 #[query]
 #[candid::candid_method(query)]
 fn get_my_admin_level(collection_id: String) -> String {
-    let caller = ic_cdk::caller().to_string();
+    let caller = caller().to_string();
     match collections::get_admin_level(&collection_id, &caller) {
         collections::AdminLevel::Genesis => "genesis".to_string(),
         collections::AdminLevel::Regular => "regular".to_string(),
@@ -138,94 +329,644 @@ fn get_my_admin_level(collection_id: String) -> String {
 
 #[query]
 fn list_collection_admins(collection_id: String) -> Result<Vec<String>, String> {
-    let caller = ic_cdk::caller().to_string();
-    collections::require_admin_access(&collection_id, &caller)?;
+    list_collection_admins_v2(collection_id).map_err(String::from)
+}
+
+#[query]
+fn list_collection_admins_v2(collection_id: String) -> Result<Vec<String>, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::ViewUsage,
+    ))?;
     Ok(collections::get_collection_admins(&collection_id))
 }
 
 #[query]
 fn get_genesis_admin(collection_id: String) -> Option<String> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
     collections::get_genesis_admin(&collection_id)
 }
 
+#[update]
+#[candid::candid_method(update)]
+fn set_collection_visibility(
+    collection_id: String,
+    visibility: CollectionVisibility,
+) -> Result<(), String> {
+    set_collection_visibility_v2(collection_id, visibility).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn set_collection_visibility_v2(
+    collection_id: String,
+    visibility: CollectionVisibility,
+) -> Result<(), BluebandError> {
+    audited(
+        "set_collection_visibility",
+        Some(&collection_id),
+        json!({ "visibility": &visibility }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageSettings,
+            ))?;
+            let caller = caller().to_string();
+            collections::set_collection_visibility(&collection_id, visibility, &caller)
+        },
+    )
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn add_collection_reader(collection_id: String, reader: String) -> Result<(), String> {
+    add_collection_reader_v2(collection_id, reader).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn add_collection_reader_v2(collection_id: String, reader: String) -> Result<(), BluebandError> {
+    audited(
+        "add_collection_reader",
+        Some(&collection_id),
+        json!({ "reader": &reader }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageMembers,
+            ))?;
+            let caller = caller().to_string();
+            collections::add_collection_reader(&collection_id, &reader, &caller)
+        },
+    )
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn remove_collection_reader(collection_id: String, reader: String) -> Result<(), String> {
+    remove_collection_reader_v2(collection_id, reader).map_err(String::from)
+}
+
+#[update]
+#[candid::candid_method(update)]
+fn remove_collection_reader_v2(collection_id: String, reader: String) -> Result<(), BluebandError> {
+    audited(
+        "remove_collection_reader",
+        Some(&collection_id),
+        json!({ "reader": &reader }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageMembers,
+            ))?;
+            let caller = caller().to_string();
+            collections::remove_collection_reader(&collection_id, &reader, &caller)
+        },
+    )
+}
+
+#[query]
+fn list_collection_readers(collection_id: String) -> Result<Vec<String>, String> {
+    list_collection_readers_v2(collection_id).map_err(String::from)
+}
+
+#[query]
+fn list_collection_readers_v2(collection_id: String) -> Result<Vec<String>, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::ViewUsage,
+    ))?;
+    Ok(storage::get_collection(&collection_id)
+        .map(|collection| collection.readers)
+        .unwrap_or_default())
+}
+
+#[update]
+fn set_collection_member(
+    collection_id: String,
+    principal: String,
+    role: CollectionRole,
+) -> Result<(), String> {
+    set_collection_member_v2(collection_id, principal, role).map_err(String::from)
+}
+
+#[update]
+fn set_collection_member_v2(
+    collection_id: String,
+    principal: String,
+    role: CollectionRole,
+) -> Result<(), BluebandError> {
+    audited(
+        "set_collection_member",
+        Some(&collection_id),
+        json!({ "principal": &principal, "role": &role }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageMembers,
+            ))?;
+            let caller = caller().to_string();
+            collections::set_collection_member(&collection_id, &principal, role, &caller)
+        },
+    )
+}
+
+#[update]
+fn remove_collection_member(collection_id: String, principal: String) -> Result<(), String> {
+    remove_collection_member_v2(collection_id, principal).map_err(String::from)
+}
+
+#[update]
+fn remove_collection_member_v2(
+    collection_id: String,
+    principal: String,
+) -> Result<(), BluebandError> {
+    audited(
+        "remove_collection_member",
+        Some(&collection_id),
+        json!({ "principal": &principal }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageMembers,
+            ))?;
+            let caller = caller().to_string();
+            collections::remove_collection_member(&collection_id, &principal, &caller)
+        },
+    )
+}
+
+#[query]
+fn list_collection_members(collection_id: String) -> Result<Vec<CollectionMember>, String> {
+    list_collection_members_v2(collection_id).map_err(String::from)
+}
+
+#[query]
+fn list_collection_members_v2(
+    collection_id: String,
+) -> Result<Vec<CollectionMember>, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::ViewUsage,
+    ))?;
+    Ok(storage::get_collection(&collection_id)
+        .map(|collection| collection.members)
+        .unwrap_or_default())
+}
+
+#[query]
+fn get_my_role(collection_id: String) -> Option<CollectionRole> {
+    collections::get_role(&collection_id, &caller().to_string())
+}
+
+/// Lets canister controllers hand a collection to a new genesis admin when
+/// the current one's key is lost
+#[update]
+fn recover_collection(collection_id: String, new_genesis_admin: String) -> Result<(), String> {
+    recover_collection_v2(collection_id, new_genesis_admin).map_err(String::from)
+}
+
+#[update]
+fn recover_collection_v2(
+    collection_id: String,
+    new_genesis_admin: String,
+) -> Result<(), BluebandError> {
+    audited(
+        "recover_collection",
+        Some(&collection_id),
+        json!({ "new_genesis_admin": &new_genesis_admin }),
+        || {
+            auth::authorize(Permission::Controller)?;
+            collections::recover_collection(&collection_id, &new_genesis_admin)
+        },
+    )
+}
+
+// =============================================================================
+// APPROVALS
+// =============================================================================
+
+#[update]
+fn set_approval_policy(
+    collection_id: String,
+    policy: Option<ApprovalPolicy>,
+) -> Result<(), String> {
+    set_approval_policy_v2(collection_id, policy).map_err(String::from)
+}
+
+#[update]
+fn set_approval_policy_v2(
+    collection_id: String,
+    policy: Option<ApprovalPolicy>,
+) -> Result<(), BluebandError> {
+    audited(
+        "set_approval_policy",
+        Some(&collection_id),
+        json!({ "policy": &policy }),
+        || {
+            auth::authorize(Permission::GenesisAdmin(collection_id.clone()))?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::SetApprovalPolicy {
+                    policy: policy.clone(),
+                },
+            )?;
+            collections::set_approval_policy(&collection_id, policy)
+        },
+    )
+}
+
+/// Proposing needs the same access as calling the operation directly
+fn proposal_permission(collection_id: &str, action: &ProposalAction) -> Permission {
+    let collection_id = collection_id.to_string();
+    match action {
+        ProposalAction::DeleteDocument { document_id }
+        | ProposalAction::DeleteDocumentVectors { document_id } => Permission::Document {
+            collection_id,
+            document_id: document_id.clone(),
+            permission: CollectionPermission::WriteDocuments,
+        },
+        ProposalAction::UpdateSettings { .. } | ProposalAction::MigrateEmbeddingModel { .. } => {
+            Permission::Collection(collection_id, CollectionPermission::ManageSettings)
+        }
+        ProposalAction::DeleteCollection
+        | ProposalAction::ClearCollectionVectors
+        | ProposalAction::DeleteCollectionDocuments
+        | ProposalAction::TransferGenesisAdmin { .. }
+        | ProposalAction::SetApprovalPolicy { .. } => Permission::GenesisAdmin(collection_id),
+    }
+}
+
+#[update]
+fn propose_collection_action(
+    collection_id: String,
+    action: ProposalAction,
+) -> Result<Proposal, String> {
+    propose_collection_action_v2(collection_id, action).map_err(String::from)
+}
+
+#[update]
+fn propose_collection_action_v2(
+    collection_id: String,
+    action: ProposalAction,
+) -> Result<Proposal, BluebandError> {
+    audited(
+        "propose_collection_action",
+        Some(&collection_id),
+        json!({ "action": &action }),
+        || {
+            auth::authorize(proposal_permission(&collection_id, &action))?;
+            storage::proposals::create_proposal(&collection_id, action, &caller().to_string())
+        },
+    )
+}
+
+/// Resolves the proposal's collection for auditing and authorization
+fn proposal_collection(proposal_id: &str) -> Option<String> {
+    storage::proposals::get_proposal(proposal_id).map(|proposal| proposal.collection_id)
+}
+
+#[update]
+fn approve_proposal(proposal_id: String) -> Result<Proposal, String> {
+    approve_proposal_v2(proposal_id).map_err(String::from)
+}
+
+#[update]
+fn approve_proposal_v2(proposal_id: String) -> Result<Proposal, BluebandError> {
+    let collection_id = proposal_collection(&proposal_id);
+    audited(
+        "approve_proposal",
+        collection_id.as_deref(),
+        json!({ "proposal_id": &proposal_id }),
+        || {
+            let collection_id = collection_id
+                .clone()
+                .ok_or_else(|| BluebandError::not_found("Proposal", &proposal_id))?;
+            auth::authorize(Permission::Approver(collection_id))?;
+            storage::proposals::approve_proposal(&proposal_id, &caller().to_string())
+        },
+    )
+}
+
+#[update]
+fn execute_proposal(proposal_id: String) -> Result<Proposal, String> {
+    execute_proposal_v2(proposal_id).map_err(String::from)
+}
+
+#[update]
+fn execute_proposal_v2(proposal_id: String) -> Result<Proposal, BluebandError> {
+    let collection_id = proposal_collection(&proposal_id);
+    audited(
+        "execute_proposal",
+        collection_id.as_deref(),
+        json!({ "proposal_id": &proposal_id }),
+        || {
+            let collection_id = collection_id
+                .clone()
+                .ok_or_else(|| BluebandError::not_found("Proposal", &proposal_id))?;
+            auth::authorize(Permission::Approver(collection_id))?;
+            let proposal = storage::proposals::execute_proposal(&proposal_id)?;
+            // Migrations queue a job, and deletions leave vectors to collect
+            compute::schedule_job_processing();
+            Ok(proposal)
+        },
+    )
+}
+
+#[update]
+fn cancel_proposal(proposal_id: String) -> Result<Proposal, String> {
+    cancel_proposal_v2(proposal_id).map_err(String::from)
+}
+
+#[update]
+fn cancel_proposal_v2(proposal_id: String) -> Result<Proposal, BluebandError> {
+    let collection_id = proposal_collection(&proposal_id);
+    audited(
+        "cancel_proposal",
+        collection_id.as_deref(),
+        json!({ "proposal_id": &proposal_id }),
+        || {
+            let collection_id = collection_id
+                .clone()
+                .ok_or_else(|| BluebandError::not_found("Proposal", &proposal_id))?;
+            auth::authorize(Permission::Approver(collection_id))?;
+            storage::proposals::cancel_proposal(&proposal_id, &caller().to_string())
+        },
+    )
+}
+
+#[query]
+fn get_proposal(proposal_id: String) -> Result<Proposal, String> {
+    get_proposal_v2(proposal_id).map_err(String::from)
+}
+
+#[query]
+fn get_proposal_v2(proposal_id: String) -> Result<Proposal, BluebandError> {
+    let proposal = storage::proposals::get_proposal(&proposal_id)
+        .ok_or_else(|| BluebandError::not_found("Proposal", &proposal_id))?;
+    auth::authorize(Permission::Collection(
+        proposal.collection_id.clone(),
+        CollectionPermission::ViewUsage,
+    ))?;
+    Ok(proposal)
+}
+
+#[query]
+fn list_proposals(collection_id: String) -> Result<Vec<Proposal>, String> {
+    list_proposals_v2(collection_id).map_err(String::from)
+}
+
+#[query]
+fn list_proposals_v2(collection_id: String) -> Result<Vec<Proposal>, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::ViewUsage,
+    ))?;
+    Ok(storage::proposals::list_collection_proposals(
+        &collection_id,
+    ))
+}
+
 // =============================================================================
 // DOCUMENT MANAGEMENT
 // =============================================================================
 
 #[update]
 async fn add_document(request: AddDocumentRequest) -> Result<DocumentMetadata, String> {
-    let caller = caller().to_string();
-    if !storage::is_collection_admin(&request.collection_id, &caller) {
-        return Err("Only collection admins can add documents".to_string());
-    }
+    add_document_v2(request).await.map_err(String::from)
+}
+
+#[update]
+async fn add_document_v2(request: AddDocumentRequest) -> Result<DocumentMetadata, BluebandError> {
+    auth::authorize(Permission::Collection(
+        request.collection_id.clone(),
+        CollectionPermission::WriteDocuments,
+    ))?;
+    enforce_limits(
+        Some(&request.collection_id),
+        &[
+            (LimitMetric::Documents, 1),
+            (LimitMetric::Bytes, request.content.len() as u64),
+        ],
+    )?;
 
     let document = storage::add_document(request)?;
     compute::invalidate_collection_cache(&document.collection_id);
     Ok(document)
 }
 
+/// Adds a PDF, or a text document too large for one message, from bytes
+#[update]
+async fn add_blob_document(request: AddBlobDocumentRequest) -> Result<DocumentMetadata, String> {
+    add_blob_document_v2(request).await.map_err(String::from)
+}
+
+#[update]
+async fn add_blob_document_v2(
+    mut request: AddBlobDocumentRequest,
+) -> Result<DocumentMetadata, BluebandError> {
+    auth::authorize(Permission::Collection(
+        request.collection_id.clone(),
+        CollectionPermission::WriteDocuments,
+    ))?;
+    let owner = caller().to_string();
+
+    // Uploaded bytes were charged part by part
+    let size = match (&request.content, &request.upload_id) {
+        (Some(content), None) => content.len() as u64,
+        (None, Some(upload_id)) => {
+            storage::uploads::get_upload(upload_id, &request.collection_id, &owner)?;
+            0
+        }
+        _ => return Err("Set exactly one of content and upload_id".into()),
+    };
+    enforce_limits(
+        Some(&request.collection_id),
+        &[(LimitMetric::Documents, 1), (LimitMetric::Bytes, size)],
+    )?;
+
+    let upload_id = request.upload_id.take();
+    let bytes = match (request.content.take(), &upload_id) {
+        (Some(content), _) => content,
+        (None, Some(id)) => storage::uploads::read_upload(id, &request.collection_id, &owner)?,
+        (None, None) => Vec::new(),
+    };
+
+    let document = storage::documents::add_blob_document(request, bytes)?;
+    if let Some(id) = upload_id {
+        storage::uploads::discard_upload(&id, &document.collection_id, &owner)?;
+    }
+    compute::invalidate_collection_cache(&document.collection_id);
+    Ok(document)
+}
+
+/// Sends one part of a blob for `add_blob_document`. Leave `upload_id` empty
+/// to start an upload, then pass the returned id with every following part.
+#[update]
+fn upload_document_part(
+    collection_id: String,
+    upload_id: Option<String>,
+    bytes: Vec<u8>,
+) -> Result<UploadStatus, String> {
+    upload_document_part_v2(collection_id, upload_id, bytes).map_err(String::from)
+}
+
+#[update]
+fn upload_document_part_v2(
+    collection_id: String,
+    upload_id: Option<String>,
+    bytes: Vec<u8>,
+) -> Result<UploadStatus, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::WriteDocuments,
+    ))?;
+    enforce_limits(
+        Some(&collection_id),
+        &[(LimitMetric::Bytes, bytes.len() as u64)],
+    )?;
+    storage::uploads::append_part(
+        &collection_id,
+        upload_id.as_deref(),
+        &caller().to_string(),
+        bytes,
+    )
+}
+
+#[update]
+fn cancel_upload(collection_id: String, upload_id: String) -> Result<(), String> {
+    cancel_upload_v2(collection_id, upload_id).map_err(String::from)
+}
+
+#[update]
+fn cancel_upload_v2(collection_id: String, upload_id: String) -> Result<(), BluebandError> {
+    storage::uploads::discard_upload(&upload_id, &collection_id, &caller().to_string())
+}
+
 #[update]
 async fn add_document_and_embed(
     request: AddDocumentRequest,
     proxy_url: String,
 ) -> Result<DocumentMetadata, String> {
-    let caller = caller().to_string();
-    if !storage::is_collection_admin(&request.collection_id, &caller) {
-        return Err("Only collection admins can add documents".to_string());
-    }
+    add_document_and_embed_v2(request, proxy_url)
+        .await
+        .map_err(String::from)
+}
+
+#[update]
+async fn add_document_and_embed_v2(
+    request: AddDocumentRequest,
+    proxy_url: String,
+) -> Result<DocumentMetadata, BluebandError> {
+    auth::authorize(Permission::Collection(
+        request.collection_id.clone(),
+        CollectionPermission::WriteDocuments,
+    ))?;
+    auth::authorize(Permission::Collection(
+        request.collection_id.clone(),
+        CollectionPermission::TriggerEmbeddings,
+    ))?;
+    storage::config::check_proxy_url(&proxy_url)?;
 
     // Get collection settings for embedding
     let collection = storage::get_collection(&request.collection_id)
-        .ok_or_else(|| format!("Collection '{}' not found", request.collection_id))?;
+        .ok_or_else(|| BluebandError::collection_not_found(&request.collection_id))?;
 
     // Step 1: Add document to storage
+    let bytes = request.content.len() as u64;
     let document = storage::add_document(request)?;
 
-    // Step 2: Generate embeddings for document chunks
-    let chunks = storage::get_document_chunks(&document.id);
-
-    match compute::embed_document_chunks(&chunks, &collection.settings, proxy_url).await {
-        Ok(vectors) => {
-            // Step 3: Store vectors in stable memory
-            match storage::store_vectors_batch(vectors) {
-                Ok(_) => {
-                    storage::mark_document_embedded(&document.collection_id, &document.id)?;
-                    compute::invalidate_collection_cache(&document.collection_id);
-
-                    Ok(document)
-                }
-                Err(e) => {
-                    let _ = storage::delete_document(&document.collection_id, &document.id);
-                    Err(format!("Failed to store vectors: {}", e))
-                }
-            }
-        }
-        Err(e) => {
-            // Compensation: Remove document if embedding fails
+    // Outcalls depend on the chunk count, known only once the document is chunked
+    if let Err(e) = enforce_limits(
+        Some(&document.collection_id),
+        &[
+            (LimitMetric::Documents, 1),
+            (LimitMetric::Bytes, bytes),
+            (
+                LimitMetric::Outcalls,
+                embedding_outcalls(document.total_chunks),
+            ),
+        ],
+    ) {
+        let _ = storage::delete_document(&document.collection_id, &document.id);
+        return Err(e);
+    }
+
+    // Step 2: Embed and store chunks batch by batch
+    let report = compute::embed_pending_chunks(&collection, &document.id, proxy_url, None).await?;
+
+    match report.error {
+        // Compensation: Remove document if nothing could be embedded
+        Some(e) if report.embedded_chunks == 0 => {
             let _ = storage::delete_document(&document.collection_id, &document.id);
-            Err(format!("Failed to generate embeddings: {}", e))
+            Err(e.with_context("Failed to generate embeddings"))
         }
+        // Keep partial progress; embed_existing_document resumes the failed chunks
+        _ => storage::get_document(&document.collection_id, &document.id)
+            .ok_or_else(|| BluebandError::not_found("Document", &document.id)),
     }
 }
 
 #[query]
 fn get_document(collection_id: String, document_id: String) -> Option<DocumentMetadata> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
     storage::get_document(&collection_id, &document_id)
 }
 
 #[query]
 fn list_documents(collection_id: String) -> Vec<DocumentMetadata> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return Vec::new();
+    }
     storage::list_documents(&collection_id)
 }
 
 #[query]
 fn get_document_content(collection_id: String, document_id: String) -> Option<String> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
     storage::get_document_content(&collection_id, &document_id)
 }
 
+#[query]
+fn get_document_source(collection_id: String, document_id: String) -> Option<DocumentSource> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
+    storage::documents::get_document_source(&collection_id, &document_id)
+}
+
 #[update]
 fn delete_document(collection_id: String, document_id: String) -> Result<(), String> {
-    documents::delete_document(&collection_id, &document_id)
+    delete_document_v2(collection_id, document_id).map_err(String::from)
+}
+
+#[update]
+fn delete_document_v2(collection_id: String, document_id: String) -> Result<(), BluebandError> {
+    audited(
+        "delete_document",
+        Some(&collection_id),
+        json!({ "document_id": &document_id }),
+        || {
+            auth::authorize(Permission::Document {
+                collection_id: collection_id.clone(),
+                document_id: document_id.clone(),
+                permission: CollectionPermission::WriteDocuments,
+            })?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::DeleteDocument {
+                    document_id: document_id.clone(),
+                },
+            )?;
+            documents::delete_document(&collection_id, &document_id)
+        },
+    )
 }
 
 // =============================================================================
@@ -234,66 +975,212 @@ fn delete_document(collection_id: String, document_id: String) -> Result<(), Str
 
 #[update]
 pub async fn search(request: SearchRequest) -> Result<Vec<MemorySearchResult>, String> {
+    search_v2(request).await.map_err(String::from)
+}
+
+#[update]
+pub async fn search_v2(request: SearchRequest) -> Result<Vec<MemorySearchResult>, BluebandError> {
+    collections::require_read_access(&request.collection_id, &caller().to_string())?;
     let collection = storage::get_collection(&request.collection_id)
-        .ok_or_else(|| format!("Collection '{}' not found", request.collection_id))?;
-    let proxy_url = collection.settings.proxy_url;
-    let model = parse_embedding_model(&collection.settings.embedding_model)?;
-    let (query_embedding, _) = compute::embed_query_text(&request.query, model, proxy_url).await?;
-
-    // Use request.use_approximate or default to true for backward compatibility
-    let matches = cosine_similarity_search(
-        &query_embedding,
-        &request.collection_id,
-        &SimilarityConfig {
-            min_score: request.min_score,
-            max_results: request.limit.unwrap_or(10),
-            use_approximate: request.use_approximate.unwrap_or(true),
-            candidate_factor: 3.0,
-        },
+        .ok_or_else(|| BluebandError::collection_not_found(&request.collection_id))?;
+    let rerank = compute::resolve_rerank(&collection.settings, &request)?;
+    enforce_limits(
+        Some(&request.collection_id),
+        &[
+            (LimitMetric::Searches, 1),
+            (LimitMetric::Outcalls, 1 + rerank.is_some() as u64),
+        ],
     )?;
 
-    Ok(matches
-        .into_iter()
-        .map(|m| MemorySearchResult {
-            document_id: m.document_id,
-            chunk_id: m.chunk_id,
-            score: m.score,
-            text: m.chunk_text.unwrap_or_default(),
-        })
-        .collect())
+    let matches = retrieve_matches(&request, &collection, rerank, false).await?;
+    Ok(into_search_results(matches, request.context_window))
 }
 
 #[update]
 pub async fn search_filtered(request: SearchRequest) -> Result<Vec<MemorySearchResult>, String> {
+    search_filtered_v2(request).await.map_err(String::from)
+}
+
+#[update]
+pub async fn search_filtered_v2(
+    request: SearchRequest,
+) -> Result<Vec<MemorySearchResult>, BluebandError> {
+    collections::require_read_access(&request.collection_id, &caller().to_string())?;
     let collection = storage::get_collection(&request.collection_id)
-        .ok_or_else(|| format!("Collection '{}' not found", request.collection_id))?;
-    let proxy_url = collection.settings.proxy_url.clone();
+        .ok_or_else(|| BluebandError::collection_not_found(&request.collection_id))?;
+    let rerank = compute::resolve_rerank(&collection.settings, &request)?;
+    enforce_limits(
+        Some(&request.collection_id),
+        &[
+            (LimitMetric::Searches, 1),
+            (LimitMetric::Outcalls, 1 + rerank.is_some() as u64),
+        ],
+    )?;
+
+    let matches = retrieve_matches(&request, &collection, rerank, true).await?;
+    Ok(into_search_results(matches, request.context_window))
+}
+
+/// Embeds the query, finds the closest chunks and reranks them if asked to.
+/// `filtered` restricts matches to `request.filter` and always searches approximately.
+async fn retrieve_matches(
+    request: &SearchRequest,
+    collection: &Collection,
+    rerank: Option<(RerankConfig, u32)>,
+    filtered: bool,
+) -> Result<Vec<VectorMatch>, BluebandError> {
     let model = parse_embedding_model(&collection.settings.embedding_model)?;
+    let (query_embedding, _) = compute::embed_query_text(
+        &request.query,
+        model,
+        collection.settings.proxy_url.clone(),
+        Some(&collection.id),
+    )
+    .await?;
 
-    let (query_embedding, _) = compute::embed_query_text(&request.query, model, proxy_url).await?;
-
-    // FIXED: Include new fields in SimilarityConfig
-    let matches = similarity_search_filtered(
-        &query_embedding,
-        &request.collection_id,
-        request.filter.as_ref().map(|f| vec![f.clone()]).as_deref(),
-        &SimilarityConfig {
-            min_score: request.min_score,
-            max_results: request.limit.unwrap_or(10),
-            use_approximate: true, // Enable fast search by default
-            candidate_factor: 3.0, // Search 3x more candidates for accuracy
-        },
+    let limit = request.limit.unwrap_or(10);
+    // Reranking needs its `top_n` candidates even when fewer results are returned
+    let max_results = match &rerank {
+        Some((_, top_n)) => limit.max(*top_n),
+        None => limit,
+    };
+
+    let matches = if filtered {
+        similarity_search_filtered(
+            &query_embedding,
+            &collection.id,
+            request.filter.as_ref().map(|f| vec![f.clone()]).as_deref(),
+            &SimilarityConfig {
+                min_score: request.min_score,
+                max_results,
+                use_approximate: true, // Enable fast search by default
+                candidate_factor: 3.0, // Search 3x more candidates for accuracy
+                include_partial: request.include_partial.unwrap_or(true),
+            },
+        )?
+    } else {
+        // Use request.use_approximate or default to true for backward compatibility
+        cosine_similarity_search(
+            &query_embedding,
+            &collection.id,
+            &SimilarityConfig {
+                min_score: request.min_score,
+                max_results,
+                use_approximate: request.use_approximate.unwrap_or(true),
+                candidate_factor: 3.0,
+                include_partial: request.include_partial.unwrap_or(true),
+            },
+        )?
+    };
+
+    match rerank {
+        Some((config, top_n)) => {
+            compute::rerank_matches(
+                &request.query,
+                matches,
+                &config,
+                top_n,
+                limit as usize,
+                Some(&collection.id),
+            )
+            .await
+        }
+        None => Ok(matches),
+    }
+}
+
+/// With a context window, matches are widened with their neighbouring chunks
+/// and matches of the same passage are returned once
+fn into_search_results(
+    matches: Vec<VectorMatch>,
+    context_window: Option<u32>,
+) -> Vec<MemorySearchResult> {
+    match context_window {
+        Some(window) if window > 0 => compute::expand_matches(matches, window)
+            .into_iter()
+            .map(|p| MemorySearchResult {
+                document_id: p.document_id,
+                chunk_id: p.chunk_id,
+                score: p.score,
+                text: p.text,
+                char_start: Some(p.char_start),
+                char_end: Some(p.char_end),
+                context_chunk_ids: Some(p.chunk_ids),
+                heading_path: p.heading_path,
+                page: p.page,
+            })
+            .collect(),
+        _ => matches
+            .into_iter()
+            .map(|m| MemorySearchResult {
+                document_id: m.document_id,
+                chunk_id: m.chunk_id,
+                score: m.score,
+                text: m.chunk_text.unwrap_or_default(),
+                char_start: None,
+                char_end: None,
+                context_chunk_ids: None,
+                heading_path: m.heading_path,
+                page: m.page,
+            })
+            .collect(),
+    }
+}
+
+#[update]
+async fn ask(
+    collection_id: String,
+    question: String,
+    options: Option<AskOptions>,
+) -> Result<AskResponse, String> {
+    ask_v2(collection_id, question, options)
+        .await
+        .map_err(String::from)
+}
+
+/// Answers a question from the collection's chunks with its chat model, citing
+/// the passages it was given
+#[update]
+async fn ask_v2(
+    collection_id: String,
+    question: String,
+    options: Option<AskOptions>,
+) -> Result<AskResponse, BluebandError> {
+    collections::require_read_access(&collection_id, &caller().to_string())?;
+    let collection = storage::get_collection(&collection_id)
+        .ok_or_else(|| BluebandError::collection_not_found(&collection_id))?;
+    let config = collection
+        .settings
+        .ask
+        .clone()
+        .ok_or("Collection has no ask configuration")?;
+    let options = options.unwrap_or_default();
+
+    let request = SearchRequest {
+        collection_id: collection_id.clone(),
+        query: question,
+        limit: Some(options.limit.unwrap_or(5)),
+        min_score: options.min_score,
+        filter: options.filter.clone(),
+        use_approximate: None,
+        include_partial: None,
+        rerank: options.rerank,
+        rerank_top_n: None,
+        context_window: options.context_window,
+    };
+    let rerank = compute::resolve_rerank(&collection.settings, &request)?;
+    enforce_limits(
+        Some(&collection_id),
+        &[
+            (LimitMetric::Searches, 1),
+            (LimitMetric::Outcalls, 2 + rerank.is_some() as u64),
+        ],
     )?;
 
-    Ok(matches
-        .into_iter()
-        .map(|m| MemorySearchResult {
-            document_id: m.document_id,
-            chunk_id: m.chunk_id,
-            score: m.score,
-            text: m.chunk_text.unwrap_or_default(),
-        })
-        .collect())
+    let matches = retrieve_matches(&request, &collection, rerank, request.filter.is_some()).await?;
+    // Widened passages reach the prompt in place of their single chunks
+    let matches = compute::widen_matches(matches, options.context_window.unwrap_or(0));
+    compute::answer_question(&collection, &request.query, matches, &config, &options).await
 }
 
 #[query]
@@ -303,11 +1190,25 @@ fn find_similar_documents(
     limit: Option<u32>,
     min_score: Option<f64>,
 ) -> Result<Vec<VectorMatch>, String> {
+    find_similar_documents_v2(source_document_id, collection_id, limit, min_score)
+        .map_err(String::from)
+}
+
+#[query]
+fn find_similar_documents_v2(
+    source_document_id: String,
+    collection_id: String,
+    limit: Option<u32>,
+    min_score: Option<f64>,
+) -> Result<Vec<VectorMatch>, BluebandError> {
+    collections::require_read_access(&collection_id, &caller().to_string())?;
+
     let config = compute::SimilarityConfig {
         min_score,
         max_results: limit.unwrap_or(10),
         use_approximate: true,
         candidate_factor: 3.0,
+        include_partial: true,
     };
 
     compute::find_similar_documents(&source_document_id, &collection_id, &config)
@@ -320,16 +1221,43 @@ async fn batch_similarity_search(
     limit: Option<u32>,
     min_score: Option<f64>,
 ) -> Result<Vec<Vec<VectorMatch>>, String> {
+    batch_similarity_search_v2(queries, collection_id, limit, min_score)
+        .await
+        .map_err(String::from)
+}
+
+#[update]
+async fn batch_similarity_search_v2(
+    queries: Vec<String>,
+    collection_id: String,
+    limit: Option<u32>,
+    min_score: Option<f64>,
+) -> Result<Vec<Vec<VectorMatch>>, BluebandError> {
+    collections::require_read_access(&collection_id, &caller().to_string())?;
+    let query_count = queries.len() as u64;
+    enforce_limits(
+        Some(&collection_id),
+        &[
+            (LimitMetric::Searches, query_count),
+            (LimitMetric::Outcalls, query_count),
+        ],
+    )?;
+
     let collection = storage::get_collection(&collection_id)
-        .ok_or_else(|| format!("Collection '{}' not found", collection_id))?;
+        .ok_or_else(|| BluebandError::collection_not_found(&collection_id))?;
 
     let model = parse_embedding_model(&collection.settings.embedding_model)?;
     let proxy_url = collection.settings.proxy_url;
 
     let mut query_embeddings = Vec::new();
     for query in queries {
-        let (embedding, _) =
-            compute::embed_query_text(&query, model.clone(), proxy_url.clone()).await?;
+        let (embedding, _) = compute::embed_query_text(
+            &query,
+            model.clone(),
+            proxy_url.clone(),
+            Some(&collection_id),
+        )
+        .await?;
         query_embeddings.push(embedding);
     }
 
@@ -338,6 +1266,7 @@ async fn batch_similarity_search(
         max_results: limit.unwrap_or(10),
         use_approximate: true,
         candidate_factor: 3.0,
+        include_partial: true,
     };
 
     compute::compute_similarity_batch(&query_embeddings, &collection_id, &config)
@@ -355,6 +1284,28 @@ async fn demo_vector_similarity(
     max_results: Option<u32>,
     min_score: Option<f64>,
 ) -> Result<Vec<compute::MemorySearchResult>, String> {
+    demo_vector_similarity_v2(items, query, proxy_url, max_results, min_score)
+        .await
+        .map_err(String::from)
+}
+
+#[update]
+async fn demo_vector_similarity_v2(
+    items: Vec<String>,
+    query: String,
+    proxy_url: String,
+    max_results: Option<u32>,
+    min_score: Option<f64>,
+) -> Result<Vec<compute::MemorySearchResult>, BluebandError> {
+    storage::config::check_proxy_url(&proxy_url)?;
+    enforce_limits(
+        None,
+        &[
+            (LimitMetric::Searches, 1),
+            (LimitMetric::Outcalls, items.len() as u64 + 1),
+        ],
+    )?;
+
     compute::create_and_search_memory_index(
         items,
         query,
@@ -375,44 +1326,69 @@ async fn embed_existing_document(
     collection_id: String,
     document_id: String,
 ) -> Result<u32, String> {
-    let caller = caller().to_string();
-    if !storage::is_collection_admin(&collection_id, &caller) {
-        return Err("Only collection admins can embed documents".to_string());
-    }
+    embed_existing_document_v2(collection_id, document_id)
+        .await
+        .map_err(String::from)
+}
+
+#[update]
+async fn embed_existing_document_v2(
+    collection_id: String,
+    document_id: String,
+) -> Result<u32, BluebandError> {
+    auth::authorize(Permission::Document {
+        collection_id: collection_id.clone(),
+        document_id: document_id.clone(),
+        permission: CollectionPermission::TriggerEmbeddings,
+    })?;
 
     let collection = storage::get_collection(&collection_id)
-        .ok_or_else(|| format!("Collection '{}' not found", collection_id))?;
+        .ok_or_else(|| BluebandError::collection_not_found(&collection_id))?;
 
     let document = storage::get_document(&collection_id, &document_id)
-        .ok_or_else(|| format!("Document '{}' not found", document_id))?;
+        .ok_or_else(|| BluebandError::not_found("Document", &document_id))?;
 
     if document.is_embedded {
-        return Err("Document is already embedded".to_string());
+        return Err("Document is already embedded".into());
     }
 
-    let chunks = storage::get_document_chunks(&document_id);
-    if chunks.is_empty() {
-        return Err("No chunks found for document".to_string());
+    if document.total_chunks == 0 {
+        return Err("No chunks found for document".into());
     }
+    enforce_limits(
+        Some(&collection_id),
+        &[(
+            LimitMetric::Outcalls,
+            embedding_outcalls(document.total_chunks - document.embedded_chunks),
+        )],
+    )?;
 
+    // Only chunks that are pending or previously failed are embedded
     let proxy_url = collection.settings.proxy_url.clone();
-    let vectors = compute::embed_document_chunks(&chunks, &collection.settings, proxy_url).await?;
-    let vector_count = vectors.len() as u32;
-
-    storage::store_vectors_batch(vectors)?;
-    storage::mark_document_embedded(&collection_id, &document_id)?;
-
-    compute::invalidate_collection_cache(&collection_id);
-
-    Ok(vector_count)
+    let report = compute::embed_pending_chunks(&collection, &document_id, proxy_url, None).await?;
+
+    match report.error {
+        Some(e) => Err(e.with_context(&format!(
+            "Embedded {} chunk(s), {} still missing",
+            report.embedded_chunks, report.pending_chunks
+        ))),
+        None => Ok(report.embedded_chunks),
+    }
 }
 
 #[update]
 async fn bulk_embed_collection(collection_id: String) -> Result<BulkEmbedResult, String> {
-    let caller = caller().to_string();
-    if !storage::is_collection_admin(&collection_id, &caller) {
-        return Err("Only collection admins can bulk embed collections".to_string());
-    }
+    bulk_embed_collection_v2(collection_id)
+        .await
+        .map_err(String::from)
+}
+
+#[update]
+async fn bulk_embed_collection_v2(collection_id: String) -> Result<BulkEmbedResult, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::TriggerEmbeddings,
+    ))?;
 
     let documents = storage::list_documents(&collection_id);
     let mut result = BulkEmbedResult {
@@ -442,6 +1418,137 @@ async fn bulk_embed_collection(collection_id: String) -> Result<BulkEmbedResult,
     Ok(result)
 }
 
+// =============================================================================
+// BACKGROUND EMBEDDING JOBS
+// =============================================================================
+
+#[update]
+fn enqueue_embedding_job(
+    collection_id: String,
+    document_ids: Option<Vec<String>>,
+) -> Result<EmbeddingJob, String> {
+    enqueue_embedding_job_v2(collection_id, document_ids).map_err(String::from)
+}
+
+#[update]
+fn enqueue_embedding_job_v2(
+    collection_id: String,
+    document_ids: Option<Vec<String>>,
+) -> Result<EmbeddingJob, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::TriggerEmbeddings,
+    ))?;
+
+    let job = storage::jobs::plan_job(&collection_id, document_ids, &caller().to_string())?;
+    // Charged up front: the worker's outcalls run outside any caller's context
+    enforce_limits(
+        Some(&collection_id),
+        &[(
+            LimitMetric::Outcalls,
+            embedding_outcalls(storage::jobs::pending_chunks(&job)),
+        )],
+    )?;
+    storage::jobs::save_job(job.clone());
+    compute::schedule_job_processing();
+    Ok(job)
+}
+
+/// Re-embeds the whole collection with another model in the background.
+/// Search keeps using the current model until every chunk is re-embedded.
+#[update]
+fn migrate_embedding_model(
+    collection_id: String,
+    embedding_model: String,
+) -> Result<EmbeddingJob, String> {
+    migrate_embedding_model_v2(collection_id, embedding_model).map_err(String::from)
+}
+
+#[update]
+fn migrate_embedding_model_v2(
+    collection_id: String,
+    embedding_model: String,
+) -> Result<EmbeddingJob, BluebandError> {
+    audited(
+        "migrate_embedding_model",
+        Some(&collection_id),
+        json!({ "embedding_model": &embedding_model }),
+        || {
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::ManageSettings,
+            ))?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::MigrateEmbeddingModel {
+                    embedding_model: embedding_model.clone(),
+                },
+            )?;
+            let job = storage::jobs::plan_migration_job(
+                &collection_id,
+                &embedding_model,
+                &caller().to_string(),
+            )?;
+            enforce_limits(
+                Some(&collection_id),
+                &[(
+                    LimitMetric::Outcalls,
+                    embedding_outcalls(storage::jobs::pending_chunks(&job)),
+                )],
+            )?;
+            storage::jobs::save_job(job.clone());
+            compute::schedule_job_processing();
+            Ok(job)
+        },
+    )
+}
+
+#[query]
+fn get_job_status(job_id: String) -> Result<EmbeddingJob, String> {
+    get_job_status_v2(job_id).map_err(String::from)
+}
+
+#[query]
+fn get_job_status_v2(job_id: String) -> Result<EmbeddingJob, BluebandError> {
+    let job =
+        storage::jobs::get_job(&job_id).ok_or_else(|| BluebandError::not_found("Job", &job_id))?;
+    auth::authorize(Permission::Collection(
+        job.collection_id.clone(),
+        CollectionPermission::TriggerEmbeddings,
+    ))?;
+    Ok(job)
+}
+
+#[query]
+fn list_embedding_jobs(collection_id: String) -> Result<Vec<EmbeddingJob>, String> {
+    list_embedding_jobs_v2(collection_id).map_err(String::from)
+}
+
+#[query]
+fn list_embedding_jobs_v2(collection_id: String) -> Result<Vec<EmbeddingJob>, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::TriggerEmbeddings,
+    ))?;
+    Ok(storage::jobs::list_collection_jobs(&collection_id))
+}
+
+#[update]
+fn cancel_job(job_id: String) -> Result<EmbeddingJob, String> {
+    cancel_job_v2(job_id).map_err(String::from)
+}
+
+#[update]
+fn cancel_job_v2(job_id: String) -> Result<EmbeddingJob, BluebandError> {
+    let job =
+        storage::jobs::get_job(&job_id).ok_or_else(|| BluebandError::not_found("Job", &job_id))?;
+    auth::authorize(Permission::Collection(
+        job.collection_id.clone(),
+        CollectionPermission::TriggerEmbeddings,
+    ))?;
+    storage::jobs::cancel_job(&job_id)
+}
+
 // =============================================================================
 // SYSTEM FUNCTIONS
 // =============================================================================
@@ -457,8 +1564,15 @@ fn get_memory_stats() -> storage::MemoryStats {
 }
 
 #[update]
-fn clear_cache() {
+fn clear_cache() -> Result<(), String> {
+    clear_cache_v2().map_err(String::from)
+}
+
+#[update]
+fn clear_cache_v2() -> Result<(), BluebandError> {
+    auth::authorize(Permission::Controller)?;
     compute::clear_cache();
+    Ok(())
 }
 
 #[query]
@@ -466,36 +1580,141 @@ fn get_cache_stats() -> CacheStats {
     compute::get_cache_stats()
 }
 
+/// Endpoint URLs and errors span every collection, so health is for controllers
+#[query]
+fn get_provider_health() -> Result<Vec<ProviderHealth>, String> {
+    get_provider_health_v2().map_err(String::from)
+}
+
+#[query]
+fn get_provider_health_v2() -> Result<Vec<ProviderHealth>, BluebandError> {
+    auth::authorize(Permission::Controller)?;
+    Ok(compute::get_provider_health())
+}
+
 #[update]
 fn cleanup_cache() -> u32 {
     compute::cleanup_cache()
 }
 #[update]
-fn invalidate_collection_cache(collection_id: String) {
+fn invalidate_collection_cache(collection_id: String) -> Result<(), String> {
+    invalidate_collection_cache_v2(collection_id).map_err(String::from)
+}
+
+#[update]
+fn invalidate_collection_cache_v2(collection_id: String) -> Result<(), BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::WriteDocuments,
+    ))?;
     compute::invalidate_collection_cache(&collection_id);
+    Ok(())
+}
+
+#[update]
+fn set_canister_config(config: CanisterConfig) -> Result<CanisterConfig, String> {
+    set_canister_config_v2(config).map_err(String::from)
 }
 
 #[update]
-fn set_config(key: String, value: String) -> Result<(), String> {
-    storage::set_config(&key, value)
+fn set_canister_config_v2(config: CanisterConfig) -> Result<CanisterConfig, BluebandError> {
+    audited(
+        "set_canister_config",
+        None,
+        json!({ "config": &config }),
+        || {
+            auth::authorize(Permission::Controller)?;
+            let config = storage::config::set_canister_config(config)?;
+            compute::configure_cache(&config.cache);
+            Ok(config)
+        },
+    )
+}
+
+#[query]
+fn get_canister_config() -> CanisterConfig {
+    storage::config::get_canister_config()
+}
+
+// =============================================================================
+// AUDIT LOG
+// =============================================================================
+
+/// Runs an administrative or destructive endpoint and appends the call and
+/// its outcome to the audit log, including calls that were refused
+fn audited<T>(
+    action: &str,
+    collection_id: Option<&str>,
+    args: serde_json::Value,
+    call: impl FnOnce() -> Result<T, BluebandError>,
+) -> Result<T, BluebandError> {
+    let result = call();
+    let caller = caller();
+    let refused = caller == candid::Principal::anonymous()
+        || matches!(
+            result,
+            Err(BluebandError::Unauthorized(_) | BluebandError::QuotaExceeded(_))
+        );
+    let error = result.as_ref().err().map(ToString::to_string);
+    storage::audit::record(
+        &caller.to_string(),
+        action,
+        collection_id,
+        &args,
+        error.as_ref(),
+        refused,
+    );
+    result
+}
+
+/// Collection admins with the ViewUsage permission may read their collection's
+/// entries; the whole log is for controllers
+#[query]
+fn query_audit_log(query: AuditQuery) -> Result<AuditPage, String> {
+    query_audit_log_v2(query).map_err(String::from)
 }
 
 #[query]
-fn get_config(key: String) -> Option<String> {
-    storage::get_config(&key)
+fn query_audit_log_v2(query: AuditQuery) -> Result<AuditPage, BluebandError> {
+    match &query.collection_id {
+        Some(collection_id) => auth::authorize(Permission::Collection(
+            collection_id.clone(),
+            CollectionPermission::ViewUsage,
+        ))?,
+        None => auth::authorize(Permission::Controller)?,
+    }
+    Ok(storage::audit::query(&query))
 }
 
 // =============================================================================
 // HELPER FUNCTIONS
 // =============================================================================
 
-fn parse_embedding_model(model_str: &str) -> Result<compute::EmbeddingModel, String> {
-    match model_str {
-        "text-embedding-ada-002" => Ok(compute::EmbeddingModel::OpenAIAda002),
-        "text-embedding-3-small" => Ok(compute::EmbeddingModel::OpenAISmall),
-        "text-embedding-3-large" => Ok(compute::EmbeddingModel::OpenAILarge),
-        custom => Ok(compute::EmbeddingModel::Custom(custom.to_string())),
+/// Charges the caller's and the collection's rate limits; controllers are exempt.
+/// Calls on a missing collection are refused before anything is charged.
+fn enforce_limits(
+    collection_id: Option<&str>,
+    charges: &[(LimitMetric, u64)],
+) -> Result<(), BluebandError> {
+    if let Some(collection_id) = collection_id {
+        if !collections::collection_exists(collection_id) {
+            return Err(BluebandError::collection_not_found(collection_id));
+        }
+    }
+    let caller = caller();
+    if auth::is_controller(&caller) {
+        return Ok(());
     }
+    storage::limits::consume(&caller.to_string(), collection_id, charges)
+}
+
+/// Outcalls needed to embed `chunks` chunks, three per request
+fn embedding_outcalls(chunks: u32) -> u64 {
+    chunks.div_ceil(3) as u64
+}
+
+fn parse_embedding_model(model_str: &str) -> Result<compute::EmbeddingModel, BluebandError> {
+    Ok(compute::EmbeddingModel::from_name(model_str))
 }
 
 // =============================================================================
@@ -510,6 +1729,33 @@ pub struct BulkEmbedResult {
     pub errors: Vec<String>,
 }
 
+// =============================================================================
+// RATE LIMITS
+// =============================================================================
+
+#[query]
+fn get_rate_limit_config() -> RateLimitConfig {
+    storage::limits::get_limits_config()
+}
+
+#[update]
+fn set_rate_limit_config(config: RateLimitConfig) -> Result<(), String> {
+    set_rate_limit_config_v2(config).map_err(String::from)
+}
+
+#[update]
+fn set_rate_limit_config_v2(config: RateLimitConfig) -> Result<(), BluebandError> {
+    audited(
+        "set_rate_limit_config",
+        None,
+        json!({ "config": &config }),
+        || {
+            auth::authorize(Permission::Controller)?;
+            storage::limits::set_limits_config(config.clone())
+        },
+    )
+}
+
 // =============================================================================
 // CYCLE MANAGEMENT
 // =============================================================================
@@ -526,44 +1772,227 @@ fn get_canister_cycles() -> u64 {
     ic_cdk::api::canister_balance128() as u64
 }
 
+/// Accepts the attached cycles as prepaid balance for one collection's outcalls
+#[update]
+fn wallet_receive_for_collection(collection_id: String) -> Result<CollectionAccount, String> {
+    wallet_receive_for_collection_v2(collection_id).map_err(String::from)
+}
+
+#[update]
+fn wallet_receive_for_collection_v2(
+    collection_id: String,
+) -> Result<CollectionAccount, BluebandError> {
+    if !storage::collections::collection_exists(&collection_id) {
+        return Err(BluebandError::collection_not_found(&collection_id));
+    }
+
+    let available = ic_cdk::api::call::msg_cycles_available128().min(u64::MAX as u128);
+    let accepted = ic_cdk::api::call::msg_cycles_accept128(available);
+    storage::billing::deposit(&collection_id, accepted as u64)
+}
+
+/// Balance and usage of a collection between `from` and `to` (nanoseconds).
+/// Defaults to the last 24 hours.
+#[query]
+fn get_collection_usage(
+    collection_id: String,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<CollectionUsage, String> {
+    get_collection_usage_v2(collection_id, from, to).map_err(String::from)
+}
+
+#[query]
+fn get_collection_usage_v2(
+    collection_id: String,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<CollectionUsage, BluebandError> {
+    auth::authorize(Permission::Collection(
+        collection_id.clone(),
+        CollectionPermission::ViewUsage,
+    ))?;
+
+    let to = to.unwrap_or_else(ic_cdk::api::time);
+    let from = from.unwrap_or_else(|| to.saturating_sub(24 * 3_600 * 1_000_000_000));
+    if from > to {
+        return Err("Usage range start must not be after its end".into());
+    }
+
+    Ok(storage::billing::get_usage(&collection_id, from, to))
+}
+
+#[update]
+fn set_token_price(cycles_per_token: u64) -> Result<(), String> {
+    set_token_price_v2(cycles_per_token).map_err(String::from)
+}
+
+#[update]
+fn set_token_price_v2(cycles_per_token: u64) -> Result<(), BluebandError> {
+    audited(
+        "set_token_price",
+        None,
+        json!({ "cycles_per_token": cycles_per_token }),
+        || {
+            auth::authorize(Permission::Controller)?;
+            storage::billing::set_token_price(cycles_per_token)
+        },
+    )
+}
+
 // =============================================================================
 // VECTOR MANAGEMENT
 // =============================================================================
 
 #[query]
 fn get_vector(vector_id: String) -> Option<Vector> {
-    vectors::get_vector(&vector_id)
+    let vector = vectors::get_vector(&vector_id)?;
+    let collection_id =
+        vectors::extract_collection_id_from_document_id(&vector.document_id).ok()?;
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
+    Some(vector)
 }
 
 #[query]
 fn get_vector_count(collection_id: String) -> u64 {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return 0;
+    }
     vectors::get_vector_count(&collection_id)
 }
 
 #[query]
 fn get_collection_embedding_dimensions(collection_id: String) -> Option<u32> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return None;
+    }
     vectors::get_collection_embedding_dimensions(&collection_id)
 }
 
 #[update]
 fn delete_vector(vector_id: String) -> Result<(), String> {
-    vectors::delete_vector(&vector_id)
+    delete_vector_v2(vector_id).map_err(String::from)
+}
+
+#[update]
+fn delete_vector_v2(vector_id: String) -> Result<(), BluebandError> {
+    let vector = vectors::get_vector(&vector_id);
+    let collection_id = vector.as_ref().and_then(|vector| {
+        vectors::extract_collection_id_from_document_id(&vector.document_id).ok()
+    });
+
+    audited(
+        "delete_vector",
+        collection_id.as_deref(),
+        json!({ "vector_id": &vector_id }),
+        || {
+            let (collection_id, vector) = collection_id
+                .clone()
+                .zip(vector.clone())
+                .ok_or_else(|| BluebandError::not_found("Vector", &vector_id))?;
+            auth::authorize(Permission::Collection(
+                collection_id.clone(),
+                CollectionPermission::WriteDocuments,
+            ))?;
+            // Covered by the policy for removing its document's vectors
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::DeleteDocumentVectors {
+                    document_id: vector.document_id.clone(),
+                },
+            )?;
+            vectors::delete_vector(&vector_id)?;
+            documents::mark_chunks_pending(
+                &collection_id,
+                &vector.document_id,
+                Some(&[vector.chunk_id]),
+            )
+            .map(|_| ())
+        },
+    )
+}
+
+#[update]
+fn delete_document_vectors(collection_id: String, document_id: String) -> Result<(), String> {
+    delete_document_vectors_v2(collection_id, document_id).map_err(String::from)
 }
 
 #[update]
-fn delete_document_vectors(_collection_id: String, document_id: String) -> Result<(), String> {
-    vectors::delete_document_vectors(&document_id)
+fn delete_document_vectors_v2(
+    collection_id: String,
+    document_id: String,
+) -> Result<(), BluebandError> {
+    audited(
+        "delete_document_vectors",
+        Some(&collection_id),
+        json!({ "document_id": &document_id }),
+        || {
+            auth::authorize(Permission::Document {
+                collection_id: collection_id.clone(),
+                document_id: document_id.clone(),
+                permission: CollectionPermission::WriteDocuments,
+            })?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::DeleteDocumentVectors {
+                    document_id: document_id.clone(),
+                },
+            )?;
+            vectors::unembed_document(&collection_id, &document_id)
+        },
+    )
 }
 
 #[update]
 fn clear_collection_vectors(collection_id: String) -> Result<(), String> {
-    vectors::cleanup_collection_index(&collection_id);
-    Ok(())
+    clear_collection_vectors_v2(collection_id).map_err(String::from)
+}
+
+#[update]
+fn clear_collection_vectors_v2(collection_id: String) -> Result<(), BluebandError> {
+    audited(
+        "clear_collection_vectors",
+        Some(&collection_id),
+        json!({}),
+        || {
+            auth::authorize(Permission::GenesisAdmin(collection_id.clone()))?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::ClearCollectionVectors,
+            )?;
+            vectors::unembed_collection(&collection_id)?;
+            compute::schedule_job_processing();
+            Ok(())
+        },
+    )
+}
+
+/// Reports vector inconsistencies; repairing them requires admin access
+#[update]
+fn validate_collection_vectors(
+    collection_id: String,
+    should_repair: Option<bool>,
+) -> Result<Vec<String>, String> {
+    validate_collection_vectors_v2(collection_id, should_repair).map_err(String::from)
 }
 
 #[update]
-fn validate_collection_vectors(collection_id: String, should_repair: Option<bool>) -> Vec<String> {
-    vectors::validate_vectors(&collection_id, should_repair.unwrap_or(false))
+fn validate_collection_vectors_v2(
+    collection_id: String,
+    should_repair: Option<bool>,
+) -> Result<Vec<String>, BluebandError> {
+    let should_repair = should_repair.unwrap_or(false);
+    if should_repair {
+        auth::authorize(Permission::Collection(
+            collection_id.clone(),
+            CollectionPermission::WriteDocuments,
+        ))?;
+    } else {
+        collections::require_read_access(&collection_id, &caller().to_string())?;
+    }
+    Ok(vectors::validate_vectors(&collection_id, should_repair))
 }
 
 // =============================================================================
@@ -572,28 +2001,72 @@ fn validate_collection_vectors(collection_id: String, should_repair: Option<bool
 
 #[query]
 fn get_document_chunks(document_id: String) -> Vec<SemanticChunk> {
-    documents::get_document_chunks_by_document_id(&document_id)
+    match vectors::extract_collection_id_from_document_id(&document_id) {
+        Ok(collection_id) if collections::can_read(&collection_id, &caller().to_string()) => {
+            documents::get_document_chunks_by_document_id(&document_id)
+        }
+        _ => Vec::new(),
+    }
 }
 
 #[update]
 fn store_document_chunks(document_id: String, chunks: Vec<SemanticChunk>) -> Result<(), String> {
-    documents::store_document_chunks(&document_id, chunks);
-    Ok(())
+    store_document_chunks_v2(document_id, chunks).map_err(String::from)
+}
+
+#[update]
+fn store_document_chunks_v2(
+    document_id: String,
+    chunks: Vec<SemanticChunk>,
+) -> Result<(), BluebandError> {
+    let collection_id = vectors::extract_collection_id_from_document_id(&document_id)?;
+    auth::authorize(Permission::Document {
+        collection_id,
+        document_id: document_id.clone(),
+        permission: CollectionPermission::WriteDocuments,
+    })?;
+    if chunks.iter().any(|chunk| chunk.document_id != document_id) {
+        return Err("All chunks must belong to the document".into());
+    }
+    documents::store_document_chunks(&document_id, chunks)
 }
 
 #[query]
 fn get_document_count(collection_id: String) -> u64 {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return 0;
+    }
     documents::get_document_count(&collection_id)
 }
 
 #[query]
 fn get_collection_documents(collection_id: String) -> Vec<DocumentMetadata> {
+    if !collections::can_read(&collection_id, &caller().to_string()) {
+        return Vec::new();
+    }
     documents::get_collection_documents(&collection_id)
 }
 
 #[update]
 fn delete_collection_documents(collection_id: String) -> Result<(), String> {
-    documents::delete_collection_documents(&collection_id)
+    delete_collection_documents_v2(collection_id).map_err(String::from)
+}
+
+#[update]
+fn delete_collection_documents_v2(collection_id: String) -> Result<(), BluebandError> {
+    audited(
+        "delete_collection_documents",
+        Some(&collection_id),
+        json!({}),
+        || {
+            auth::authorize(Permission::GenesisAdmin(collection_id.clone()))?;
+            storage::proposals::require_no_approval(
+                &collection_id,
+                &ProposalAction::DeleteCollectionDocuments,
+            )?;
+            documents::delete_collection_documents(&collection_id)
+        },
+    )
 }
 
 
@@ -0,0 +1,348 @@
+// storage/audit.rs
+use ic_stable_structures::{StableBTreeMap, Storable};
+use std::cell::{Cell, RefCell};
+
+use super::memory::{get_memory, MemoryType, AUDIT_LOG_MEMORY_ID};
+use crate::types::*;
+
+// =============================================================================
+// GLOBAL STORAGE
+// =============================================================================
+
+thread_local! {
+    // Audit log: sequential id -> AuditEntry. Entries are never updated, and
+    // the oldest are removed beyond MAX_AUDIT_ENTRIES.
+    static AUDIT_LOG: RefCell<StableBTreeMap<u64, AuditEntry, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(AUDIT_LOG_MEMORY_ID))
+    );
+
+    // Minute and number of refused calls recorded in it; restarts after upgrades
+    static REFUSED_WINDOW: Cell<(u64, u32)> = const { Cell::new((0, 0)) };
+}
+
+pub const MAX_AUDIT_ARGS_BYTES: usize = 2048;
+const MAX_AUDIT_ERROR_BYTES: usize = 512;
+// Longer ids name no collection, but are recorded as passed up to this length
+const MAX_AUDIT_COLLECTION_ID_BYTES: usize = 64;
+
+// Entries kept; each write removes at most PRUNE_BATCH of the oldest beyond it
+const MAX_AUDIT_ENTRIES: u64 = 50_000;
+const PRUNE_BATCH: usize = 10;
+// Refused calls recorded per minute across all callers; the rest are dropped
+const MAX_REFUSED_PER_MINUTE: u32 = 60;
+const MINUTE_NS: u64 = 60 * 1_000_000_000;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+// Bounds the work of one query when filters match few entries
+const MAX_SCANNED_ENTRIES: usize = 10_000;
+
+// =============================================================================
+// RECORDING
+// =============================================================================
+
+/// Appends a call to the log. `error` is the endpoint's error, if it failed.
+/// `refused` calls (denied, over quota or anonymous) cost their callers
+/// nothing, so they are recorded without arguments and only up to
+/// `MAX_REFUSED_PER_MINUTE`.
+pub fn record(
+    caller: &str,
+    action: &str,
+    collection_id: Option<&str>,
+    args: &serde_json::Value,
+    error: Option<&String>,
+    refused: bool,
+) {
+    if refused && !take_refused_slot(current_time() / MINUTE_NS) {
+        return;
+    }
+    let args = if refused {
+        String::new()
+    } else {
+        truncate(&args.to_string(), MAX_AUDIT_ARGS_BYTES)
+    };
+    let outcome = match error {
+        Some(error) => AuditOutcome::Failure(truncate(error, MAX_AUDIT_ERROR_BYTES)),
+        None => AuditOutcome::Success,
+    };
+
+    AUDIT_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        let id = log.last_key_value().map(|(id, _)| id + 1).unwrap_or(0);
+        let mut entry = AuditEntry {
+            id,
+            timestamp: current_time(),
+            caller: caller.to_string(),
+            action: action.to_string(),
+            collection_id: collection_id.map(|id| truncate(id, MAX_AUDIT_COLLECTION_ID_BYTES)),
+            args,
+            outcome,
+        };
+        fit_to_bound(&mut entry);
+        log.insert(id, entry);
+
+        for _ in 0..PRUNE_BATCH {
+            if log.len() <= MAX_AUDIT_ENTRIES {
+                break;
+            }
+            if let Some((oldest, _)) = log.first_key_value() {
+                log.remove(&oldest);
+            }
+        }
+    });
+}
+
+fn take_refused_slot(minute: u64) -> bool {
+    REFUSED_WINDOW.with(|window| {
+        let (current, count) = window.get();
+        let count = if current == minute { count } else { 0 };
+        if count >= MAX_REFUSED_PER_MINUTE {
+            return false;
+        }
+        window.set((minute, count + 1));
+        true
+    })
+}
+
+/// Shortens the arguments, then the error, until the encoded entry fits its
+/// storage bound. Escaping grows quote- or backslash-heavy text, so the raw
+/// byte limits alone do not guarantee it.
+fn fit_to_bound(entry: &mut AuditEntry) {
+    let max_size = AuditEntry::BOUND.max_size() as usize;
+    loop {
+        let size = entry.to_bytes().len();
+        if size <= max_size {
+            return;
+        }
+        // Room for the ellipsis that marks the cut
+        let excess = size - max_size + '…'.len_utf8();
+
+        if !entry.args.is_empty() {
+            entry.args = truncate(&entry.args, entry.args.len().saturating_sub(excess));
+        } else if let AuditOutcome::Failure(error) = &entry.outcome {
+            entry.outcome =
+                AuditOutcome::Failure(truncate(error, error.len().saturating_sub(excess)));
+        } else {
+            return;
+        }
+    }
+}
+
+fn truncate(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    // Nothing but the ellipsis would be left
+    if max_bytes < '…'.len_utf8() {
+        return String::new();
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &text[..end])
+}
+
+// =============================================================================
+// QUERIES
+// =============================================================================
+
+/// Newest matching entries first. A page may hold fewer than `limit` entries
+/// when the scan budget runs out; `next_cursor` then continues the scan.
+pub fn query(query: &AuditQuery) -> AuditPage {
+    let limit = query
+        .limit
+        .map(|limit| (limit as usize).clamp(1, MAX_PAGE_SIZE))
+        .unwrap_or(DEFAULT_PAGE_SIZE);
+
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let mut entries = Vec::new();
+        let mut next_cursor = None;
+
+        let range = match query.before {
+            Some(before) => log.range(..before),
+            None => log.range(..),
+        };
+
+        for (scanned, (id, entry)) in range.rev().enumerate() {
+            if scanned >= MAX_SCANNED_ENTRIES || entries.len() >= limit {
+                next_cursor = Some(id + 1);
+                break;
+            }
+            // Ids grow with time, so nothing older can match
+            if query.from.is_some_and(|from| entry.timestamp < from) {
+                break;
+            }
+            if matches(query, &entry) {
+                entries.push(entry);
+            }
+        }
+
+        AuditPage {
+            entries,
+            next_cursor,
+        }
+    })
+}
+
+fn matches(query: &AuditQuery, entry: &AuditEntry) -> bool {
+    query
+        .collection_id
+        .as_ref()
+        .is_none_or(|id| entry.collection_id.as_ref() == Some(id))
+        && query.caller.as_ref().is_none_or(|c| *c == entry.caller)
+        && query.action.as_ref().is_none_or(|a| *a == entry.action)
+        && query.to.is_none_or(|to| entry.timestamp <= to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_filters_and_pages_newest_first() {
+        let args = serde_json::json!({});
+        for i in 0..5 {
+            let collection = if i % 2 == 0 { "even" } else { "odd" };
+            record(
+                "alice",
+                "delete_document",
+                Some(collection),
+                &args,
+                None,
+                false,
+            );
+        }
+        record(
+            "bob",
+            "set_canister_config",
+            None,
+            &serde_json::json!({ "key": "k" }),
+            Some(&"Canister controller access required".to_string()),
+            false,
+        );
+
+        let even = AuditQuery {
+            collection_id: Some("even".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page = query(&even);
+        let ids: Vec<u64> = page.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![4, 2]);
+
+        let page = query(&AuditQuery {
+            before: page.next_cursor,
+            ..even
+        });
+        let ids: Vec<u64> = page.entries.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![0]);
+        assert_eq!(page.next_cursor, None);
+
+        let page = query(&AuditQuery {
+            caller: Some("bob".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(
+            page.entries[0].outcome,
+            AuditOutcome::Failure("Canister controller access required".to_string())
+        );
+        assert_eq!(page.entries[0].args, r#"{"key":"k"}"#);
+    }
+
+    #[test]
+    fn escaped_arguments_are_cut_to_fit_the_entry_bound() {
+        let document_id = "\"".repeat(4_000);
+        let collection_id = "\\".repeat(100);
+        record(
+            "carol",
+            "delete_document",
+            Some(&collection_id),
+            &serde_json::json!({ "document_id": &document_id }),
+            Some(&"Document not found".to_string()),
+            false,
+        );
+
+        let entry = query(&AuditQuery::default()).entries.remove(0);
+        assert_eq!(entry.action, "delete_document");
+        assert!(matches!(entry.outcome, AuditOutcome::Failure(_)));
+        assert!(entry.to_bytes().len() <= AuditEntry::BOUND.max_size() as usize);
+
+        let kept = entry.args.trim_end_matches('…');
+        assert!(kept.len() > 100);
+        assert!(serde_json::json!({ "document_id": document_id })
+            .to_string()
+            .starts_with(kept));
+        assert!(collection_id.starts_with(entry.collection_id.unwrap().trim_end_matches('…')));
+    }
+
+    #[test]
+    fn refused_calls_are_recorded_without_arguments_up_to_a_rate() {
+        crate::auth::set_test_caller(candid::Principal::from_slice(&[4]));
+        for _ in 0..MAX_REFUSED_PER_MINUTE + 5 {
+            assert!(crate::delete_document("c".to_string(), "d".repeat(1_000)).is_err());
+        }
+        let all = AuditQuery {
+            limit: Some(MAX_PAGE_SIZE as u32),
+            ..Default::default()
+        };
+        let page = query(&all);
+        assert_eq!(page.entries.len(), MAX_REFUSED_PER_MINUTE as usize);
+        assert!(page.entries.iter().all(|entry| entry.args.is_empty()));
+
+        advance_test_clock(MINUTE_NS);
+        assert!(crate::delete_document("c".to_string(), "d".to_string()).is_err());
+        assert_eq!(
+            query(&all).entries.len(),
+            MAX_REFUSED_PER_MINUTE as usize + 1
+        );
+    }
+
+    #[test]
+    fn access_control_changes_are_audited() {
+        let owner = candid::Principal::from_slice(&[2]);
+        let reader = candid::Principal::from_slice(&[3]).to_text();
+        crate::auth::set_test_caller(owner);
+        let c = "audited".to_string();
+        crate::create_collection(CreateCollectionRequest {
+            id: c.clone(),
+            name: "Audited".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+
+        crate::set_collection_visibility(c.clone(), CollectionVisibility::Private).unwrap();
+        crate::add_collection_reader(c.clone(), reader.clone()).unwrap();
+        crate::remove_collection_reader(c.clone(), reader).unwrap();
+        crate::set_collection_secret_headers(
+            c.clone(),
+            vec![SecretHeader {
+                name: "Authorization".to_string(),
+                value: "Bearer secret".to_string(),
+            }],
+        )
+        .unwrap();
+        crate::clear_collection_secret_headers(c.clone()).unwrap();
+
+        let page = query(&AuditQuery {
+            collection_id: Some(c),
+            ..Default::default()
+        });
+        let actions: Vec<&str> = page.entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(
+            actions,
+            [
+                "clear_collection_secret_headers",
+                "set_collection_secret_headers",
+                "remove_collection_reader",
+                "add_collection_reader",
+                "set_collection_visibility",
+            ]
+        );
+        assert_eq!(page.entries[1].args, r#"{"headers":["Authorization"]}"#);
+    }
+}
@@ -0,0 +1,311 @@
+// storage/billing.rs
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+use super::memory::{get_memory, MemoryType, ACCOUNTS_MEMORY_ID, USAGE_MEMORY_ID};
+use crate::types::*;
+
+// =============================================================================
+// GLOBAL STORAGE
+// =============================================================================
+
+thread_local! {
+    // Accounts: collection_id -> CollectionAccount
+    static ACCOUNTS: RefCell<StableBTreeMap<String, CollectionAccount, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(ACCOUNTS_MEMORY_ID))
+    );
+
+    // Usage ledger: collection_id::window_start (zero-padded) -> UsageWindow
+    static USAGE: RefCell<StableBTreeMap<String, UsageWindow, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(USAGE_MEMORY_ID))
+    );
+}
+
+const USAGE_WINDOW_NS: u64 = 3_600 * 1_000_000_000;
+const USAGE_RETENTION_NS: u64 = 400 * 24 * USAGE_WINDOW_NS;
+
+const TOKEN_PRICE_CONFIG_KEY: &str = "billing.cycles_per_token";
+
+/// Credited once to each collection created before outcalls were metered, so
+/// its searches and embeddings keep working until someone tops it up
+pub const LEGACY_GRACE_CYCLES: u64 = 100_000_000_000;
+
+fn usage_key(collection_id: &str, window_start: u64) -> String {
+    format!("{}::{:020}", collection_id, window_start)
+}
+
+// =============================================================================
+// ACCOUNT OPERATIONS
+// =============================================================================
+
+pub fn get_account(collection_id: &str) -> CollectionAccount {
+    ACCOUNTS
+        .with(|a| a.borrow().get(&collection_id.to_string()))
+        .unwrap_or_else(|| CollectionAccount {
+            collection_id: collection_id.to_string(),
+            ..Default::default()
+        })
+}
+
+fn save_account(mut account: CollectionAccount) -> CollectionAccount {
+    account.updated_at = current_time();
+    ACCOUNTS.with(|a| {
+        a.borrow_mut()
+            .insert(account.collection_id.clone(), account.clone())
+    });
+    account
+}
+
+/// Opens the empty account of a new collection. A collection without an
+/// account predates metering and receives the grace balance on upgrade.
+pub fn open_account(collection_id: &str) {
+    if ACCOUNTS.with(|a| !a.borrow().contains_key(&collection_id.to_string())) {
+        save_account(get_account(collection_id));
+    }
+}
+
+/// Opens an account holding `LEGACY_GRACE_CYCLES` for every collection that has
+/// none. Runs after each upgrade; collections with an account are untouched.
+pub fn open_legacy_accounts() -> u32 {
+    let mut opened = 0;
+    for collection in super::collections::list_collections() {
+        if ACCOUNTS.with(|a| a.borrow().contains_key(&collection.id)) {
+            continue;
+        }
+        save_account(CollectionAccount {
+            collection_id: collection.id,
+            balance: LEGACY_GRACE_CYCLES,
+            total_deposited: LEGACY_GRACE_CYCLES,
+            ..Default::default()
+        });
+        opened += 1;
+    }
+    opened
+}
+
+pub fn deposit(collection_id: &str, amount: u64) -> Result<CollectionAccount, BluebandError> {
+    if !super::collections::collection_exists(collection_id) {
+        return Err(BluebandError::collection_not_found(collection_id));
+    }
+
+    let mut account = get_account(collection_id);
+    account.balance = account.balance.saturating_add(amount);
+    account.total_deposited = account.total_deposited.saturating_add(amount);
+    Ok(save_account(account))
+}
+
+/// Sets aside the cycles attached to an outcall before it is sent, so that
+/// concurrent calls cannot overdraw the balance
+pub fn reserve_cycles(collection_id: &str, amount: u64) -> Result<(), BluebandError> {
+    let mut account = get_account(collection_id);
+    if account.balance < amount {
+        return Err(BluebandError::InsufficientBalance {
+            collection_id: collection_id.to_string(),
+            available: account.balance,
+            required: amount,
+        });
+    }
+
+    account.balance -= amount;
+    save_account(account);
+    Ok(())
+}
+
+/// Returns the unspent part of a reservation and charges the tokens the
+/// provider reported. Token charges may drain the balance to zero.
+pub fn settle_outcall(collection_id: &str, reserved: u64, spent: u64, tokens: u64) {
+    // The collection may have been deleted while the outcall was in flight
+    if !super::collections::collection_exists(collection_id) {
+        return;
+    }
+
+    let spent = spent.min(reserved);
+    let token_cycles = tokens.saturating_mul(get_token_price());
+
+    let mut account = get_account(collection_id);
+    account.balance = account
+        .balance
+        .saturating_add(reserved - spent)
+        .saturating_sub(token_cycles);
+    account.total_cycles = account
+        .total_cycles
+        .saturating_add(spent)
+        .saturating_add(token_cycles);
+    account.total_tokens = account.total_tokens.saturating_add(tokens);
+    account.total_outcalls += 1;
+    save_account(account);
+
+    record_usage(collection_id, spent.saturating_add(token_cycles), tokens);
+}
+
+/// Cycles charged per provider-reported token, on top of the outcall cost
+pub fn get_token_price() -> u64 {
+    super::get_config(TOKEN_PRICE_CONFIG_KEY)
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0)
+}
+
+pub fn set_token_price(cycles_per_token: u64) -> Result<(), BluebandError> {
+    super::set_config(TOKEN_PRICE_CONFIG_KEY, cycles_per_token.to_string())
+}
+
+// =============================================================================
+// USAGE LEDGER
+// =============================================================================
+
+fn record_usage(collection_id: &str, cycles: u64, tokens: u64) {
+    let now = current_time();
+    let window_start = now - now % USAGE_WINDOW_NS;
+    let key = usage_key(collection_id, window_start);
+
+    USAGE.with(|u| {
+        let mut usage = u.borrow_mut();
+        let mut window = usage.get(&key).unwrap_or(UsageWindow {
+            start: window_start,
+            end: window_start + USAGE_WINDOW_NS,
+            ..Default::default()
+        });
+        window.outcalls += 1;
+        window.cycles = window.cycles.saturating_add(cycles);
+        window.tokens = window.tokens.saturating_add(tokens);
+        usage.insert(key, window);
+    });
+
+    prune_usage(collection_id, now.saturating_sub(USAGE_RETENTION_NS));
+}
+
+fn prune_usage(collection_id: &str, before: u64) {
+    let expired: Vec<String> = USAGE.with(|u| {
+        u.borrow()
+            .range(usage_key(collection_id, 0)..usage_key(collection_id, before))
+            .map(|(key, _)| key)
+            .collect()
+    });
+
+    USAGE.with(|u| {
+        let mut usage = u.borrow_mut();
+        for key in expired {
+            usage.remove(&key);
+        }
+    });
+}
+
+/// Usage of a collection between `from` and `to` (nanoseconds), by hourly window
+pub fn get_usage(collection_id: &str, from: u64, to: u64) -> CollectionUsage {
+    let account = get_account(collection_id);
+    let first_window = from - from % USAGE_WINDOW_NS;
+
+    let windows: Vec<UsageWindow> = USAGE.with(|u| {
+        u.borrow()
+            .range(usage_key(collection_id, first_window)..=usage_key(collection_id, to))
+            .map(|(_, window)| window)
+            .filter(|window| window.start <= to)
+            .collect()
+    });
+
+    CollectionUsage {
+        collection_id: collection_id.to_string(),
+        balance: account.balance,
+        total_deposited: account.total_deposited,
+        from,
+        to,
+        outcalls: windows.iter().map(|w| w.outcalls).sum(),
+        cycles: windows.iter().map(|w| w.cycles).sum(),
+        tokens: windows.iter().map(|w| w.tokens).sum(),
+        windows,
+    }
+}
+
+/// Drops the account and ledger of a deleted collection; any remaining
+/// balance stays with the canister
+pub fn delete_collection_billing(collection_id: &str) {
+    ACCOUNTS.with(|a| a.borrow_mut().remove(&collection_id.to_string()));
+    prune_usage(collection_id, u64::MAX);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::set_test_caller;
+    use candid::Principal;
+
+    fn setup(collection_id: &str) {
+        set_test_caller(Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: collection_id.to_string(),
+            name: "Billing".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn reservations_are_refused_beyond_the_balance() {
+        setup("billing");
+        assert_eq!(get_account("billing").balance, 0);
+        assert_eq!(
+            reserve_cycles("billing", 1).unwrap_err(),
+            BluebandError::InsufficientBalance {
+                collection_id: "billing".to_string(),
+                available: 0,
+                required: 1,
+            }
+        );
+
+        deposit("billing", 1_000).unwrap();
+        reserve_cycles("billing", 600).unwrap();
+        assert_eq!(get_account("billing").balance, 400);
+        assert!(matches!(
+            reserve_cycles("billing", 401),
+            Err(BluebandError::InsufficientBalance {
+                available: 400,
+                required: 401,
+                ..
+            })
+        ));
+        assert!(deposit("missing", 1).is_err());
+    }
+
+    #[test]
+    fn settling_refunds_the_unspent_reservation_and_charges_tokens() {
+        setup("billing");
+        set_token_price(2).unwrap();
+        deposit("billing", 1_000).unwrap();
+
+        reserve_cycles("billing", 600).unwrap();
+        settle_outcall("billing", 600, 250, 10);
+        let account = get_account("billing");
+        assert_eq!(account.balance, 1_000 - 250 - 20);
+        assert_eq!(account.total_cycles, 270);
+        assert_eq!(account.total_tokens, 10);
+        assert_eq!(account.total_outcalls, 1);
+
+        // Spending never exceeds the reservation, and token charges stop at zero
+        reserve_cycles("billing", 700).unwrap();
+        settle_outcall("billing", 700, 900, 1_000);
+        assert_eq!(get_account("billing").balance, 0);
+
+        let usage = get_usage("billing", 0, current_time());
+        assert_eq!(usage.outcalls, 2);
+        assert_eq!(usage.tokens, 1_010);
+        assert_eq!(usage.cycles, 270 + 700 + 2_000);
+    }
+
+    #[test]
+    fn collections_created_before_metering_get_a_grace_balance() {
+        setup("billing");
+        // A collection stored by an older version has no account
+        ACCOUNTS.with(|a| a.borrow_mut().remove(&"billing".to_string()));
+        setup("metered");
+
+        assert_eq!(open_legacy_accounts(), 1);
+        assert_eq!(get_account("billing").balance, LEGACY_GRACE_CYCLES);
+        assert_eq!(get_account("metered").balance, 0);
+
+        reserve_cycles("billing", LEGACY_GRACE_CYCLES).unwrap();
+        assert_eq!(open_legacy_accounts(), 0);
+        assert_eq!(get_account("billing").balance, 0);
+    }
+}
@@ -0,0 +1,666 @@
+// storage/chunking.rs
+//
+// Splits document content into chunk spans. Every strategy works the same way:
+// the content is cut into small contiguous units (characters, words, sentences,
+// lines or paragraphs), falling back to finer units wherever a piece is larger
+// than `chunk_size`, and consecutive units are then packed into chunks. Spans
+// are UTF-8 byte offsets into the content, whatever unit `chunk_size` counts.
+
+use super::tokens::{encoding_for_model, token_starts};
+use crate::types::*;
+
+/// Cuts `content[start..end]` into contiguous pieces that cover it exactly
+type Splitter = fn(&str, usize, usize) -> Vec<(usize, usize)>;
+
+// Byte range plus its size in the strategy's unit
+type Unit = (usize, usize, usize);
+
+/// Byte ranges of the chunks of `content`, in order. Consecutive chunks share
+/// at most `chunk_overlap` units and together cover the whole content.
+pub fn chunk_spans(content: &str, settings: &CollectionSettings) -> Vec<(usize, usize)> {
+    let splitters: &[Splitter] = match settings.chunking_strategy.clone().unwrap_or_default() {
+        ChunkingStrategy::Fixed => &[split_chars],
+        ChunkingStrategy::Sentence => &[split_sentences, split_words, split_chars],
+        ChunkingStrategy::Paragraph => {
+            &[split_paragraphs, split_sentences, split_words, split_chars]
+        }
+        ChunkingStrategy::Recursive => &[
+            split_paragraphs,
+            split_lines,
+            split_sentences,
+            split_words,
+            split_chars,
+        ],
+        ChunkingStrategy::Token => &[split_words, split_chars],
+    };
+
+    chunk_range(content, 0, content.len(), splitters, settings)
+}
+
+/// Chunks of a Markdown document with the heading path of their section.
+/// Chunks never cross a heading, and code fences are only cut when a single
+/// fence is larger than `chunk_size`. The strategy only sets the size unit.
+pub fn markdown_chunk_spans(
+    content: &str,
+    settings: &CollectionSettings,
+) -> Vec<(usize, usize, Vec<String>)> {
+    let splitters: &[Splitter] = &[
+        split_markdown_blocks,
+        split_lines,
+        split_sentences,
+        split_words,
+        split_chars,
+    ];
+
+    markdown_sections(content)
+        .into_iter()
+        .flat_map(|section| {
+            chunk_range(content, section.start, section.end, splitters, settings)
+                .into_iter()
+                .map(move |(start, end)| (start, end, section.heading_path.clone()))
+        })
+        .collect()
+}
+
+fn chunk_range(
+    content: &str,
+    start: usize,
+    end: usize,
+    splitters: &[Splitter],
+    settings: &CollectionSettings,
+) -> Vec<(usize, usize)> {
+    let max_size = (settings.chunk_size as usize).max(1);
+    // Where each token of the range ends. Splitters keep a word's trailing
+    // space while BPE tokens carry their leading one, so a token is counted in
+    // the unit it ends in.
+    let token_ends =
+        matches!(settings.chunking_strategy, Some(ChunkingStrategy::Token)).then(|| {
+            let encoding = encoding_for_model(&settings.embedding_model);
+            let mut ends: Vec<usize> = token_starts(&content[start..end], encoding)
+                .into_iter()
+                .skip(1)
+                .map(|offset| start + offset)
+                .collect();
+            ends.push(end);
+            ends
+        });
+    let measure = |from: usize, to: usize| match &token_ends {
+        Some(ends) => ends.partition_point(|&e| e <= to) - ends.partition_point(|&e| e <= from),
+        None => content[from..to].chars().count(),
+    };
+
+    let mut units = Vec::new();
+    split_units(
+        content, start, end, splitters, &measure, max_size, &mut units,
+    );
+    pack_units(&units, max_size, settings.chunk_overlap as usize)
+}
+
+/// Splits with the first splitter, handing pieces larger than `max_size` to the next one
+fn split_units(
+    content: &str,
+    start: usize,
+    end: usize,
+    splitters: &[Splitter],
+    measure: &dyn Fn(usize, usize) -> usize,
+    max_size: usize,
+    units: &mut Vec<Unit>,
+) {
+    for (piece_start, piece_end) in splitters[0](content, start, end) {
+        let size = measure(piece_start, piece_end);
+        if size <= max_size || splitters.len() == 1 {
+            units.push((piece_start, piece_end, size));
+        } else {
+            split_units(
+                content,
+                piece_start,
+                piece_end,
+                &splitters[1..],
+                measure,
+                max_size,
+                units,
+            );
+        }
+    }
+}
+
+/// Packs consecutive units into chunks of at most `max_size`. Each chunk after
+/// the first repeats the trailing units of the previous one, up to `overlap`,
+/// but always starts at least one unit further.
+fn pack_units(units: &[Unit], max_size: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut first = 0;
+
+    while first < units.len() {
+        let mut last = first;
+        let mut size = units[first].2;
+        while last + 1 < units.len() && size + units[last + 1].2 <= max_size {
+            last += 1;
+            size += units[last].2;
+        }
+        spans.push((units[first].0, units[last].1));
+
+        if last + 1 == units.len() {
+            break;
+        }
+
+        let mut next = last + 1;
+        let mut carried = 0;
+        while next > first + 1 && carried + units[next - 1].2 <= overlap {
+            next -= 1;
+            carried += units[next].2;
+        }
+        first = next;
+    }
+
+    spans
+}
+
+// =============================================================================
+// SPLITTERS
+// =============================================================================
+
+fn split_chars(content: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    content[start..end]
+        .char_indices()
+        .map(|(i, c)| (start + i, start + i + c.len_utf8()))
+        .collect()
+}
+
+/// Words with their trailing whitespace
+fn split_words(content: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    cut_where(content, start, end, |text, i| {
+        let previous = text[..i].chars().next_back();
+        let current = text[i..].chars().next();
+        matches!((previous, current), (Some(p), Some(c)) if p.is_whitespace() && !c.is_whitespace())
+    })
+}
+
+/// Lines with their newline
+fn split_lines(content: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    cut_where(content, start, end, |text, i| text[..i].ends_with('\n'))
+}
+
+/// Paragraphs with the blank lines that follow them
+fn split_paragraphs(content: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut piece_start = start;
+    let mut seen_text = false;
+    let mut seen_blank = false;
+    let mut offset = start;
+
+    for line in content[start..end].split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if !blank && seen_text && seen_blank {
+            pieces.push((piece_start, offset));
+            piece_start = offset;
+            seen_blank = false;
+        }
+        seen_text |= !blank;
+        seen_blank |= blank && seen_text;
+        offset += line.len();
+    }
+    if piece_start < end {
+        pieces.push((piece_start, end));
+    }
+
+    pieces
+}
+
+/// Sentences with their trailing whitespace. A sentence ends at `.`, `!` or `?`
+/// (plus closing quotes or brackets) followed by whitespace, or at a CJK full stop.
+fn split_sentences(content: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    cut_where(content, start, end, |text, i| {
+        let current = text[i..].chars().next();
+        if current.is_none_or(char::is_whitespace) {
+            return false;
+        }
+
+        let before = text[..i].trim_end();
+        if before.len() == i {
+            // No whitespace before: only a CJK terminator ends the sentence
+            return before
+                .trim_end_matches(is_closer)
+                .ends_with(['。', '！', '？']);
+        }
+        before
+            .trim_end_matches(is_closer)
+            .ends_with(['.', '!', '?', '。', '！', '？'])
+    })
+}
+
+fn is_closer(c: char) -> bool {
+    matches!(c, '"' | '\'' | ')' | ']' | '”' | '’' | '」' | '』')
+}
+
+/// Cuts `content[start..end]` before every character where `is_cut(text, i)` holds
+fn cut_where(
+    content: &str,
+    start: usize,
+    end: usize,
+    is_cut: impl Fn(&str, usize) -> bool,
+) -> Vec<(usize, usize)> {
+    let text = &content[start..end];
+    let mut pieces = Vec::new();
+    let mut piece_start = 0;
+
+    for (i, _) in text.char_indices().skip(1) {
+        if is_cut(text, i) {
+            pieces.push((start + piece_start, start + i));
+            piece_start = i;
+        }
+    }
+    if piece_start < text.len() {
+        pieces.push((start + piece_start, end));
+    }
+
+    pieces
+}
+
+// =============================================================================
+// MARKDOWN
+// =============================================================================
+
+struct Section {
+    start: usize,
+    end: usize,
+    heading_path: Vec<String>,
+}
+
+/// Cuts a Markdown document before every ATX (`## Title`) or setext (`Title`
+/// over `---`) heading outside code fences. A section holding nothing but its
+/// heading is joined to the subsection that follows it.
+fn markdown_sections(content: &str) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut current = Section {
+        start: 0,
+        end: 0,
+        heading_path: Vec::new(),
+    };
+    let mut body_start = 0;
+    let mut fence = None;
+    let mut paragraph_line: Option<(usize, &str)> = None;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        let text = line.trim_end_matches(['\n', '\r']);
+
+        if let Some((marker, length)) = fence {
+            if closes_fence(text, marker, length) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(opened) = opens_fence(text) {
+            fence = Some(opened);
+            paragraph_line = None;
+            continue;
+        }
+
+        let heading = atx_heading(text)
+            .map(|heading| (line_start, heading))
+            .or_else(|| {
+                let level = setext_level(text)?;
+                let (start, title) = paragraph_line?;
+                Some((start, (level, title.trim().to_string())))
+            });
+        let Some((heading_start, (level, title))) = heading else {
+            let blank = text.trim().is_empty();
+            let list_or_quote = ["- ", "* ", "+ ", ">"]
+                .iter()
+                .any(|marker| text.trim_start().starts_with(marker));
+            paragraph_line = (!blank && !list_or_quote && !text.starts_with("    "))
+                .then_some((line_start, text));
+            continue;
+        };
+        paragraph_line = None;
+
+        if heading_start > current.start {
+            current.end = heading_start;
+            let heading_only = content[body_start..heading_start].trim().is_empty();
+            let next_path = {
+                let mut path: Vec<String> = headings
+                    .iter()
+                    .filter(|(l, _)| *l < level)
+                    .map(|(_, t)| t.clone())
+                    .collect();
+                path.push(title.clone());
+                path
+            };
+            // Keep a bare parent heading with the subsection it introduces
+            let joins_next = heading_only
+                && !current.heading_path.is_empty()
+                && next_path.starts_with(&current.heading_path);
+            if !joins_next {
+                let start = current.end;
+                sections.push(std::mem::replace(
+                    &mut current,
+                    Section {
+                        start,
+                        end: 0,
+                        heading_path: Vec::new(),
+                    },
+                ));
+            }
+        }
+
+        headings.retain(|(l, _)| *l < level);
+        headings.push((level, title));
+        current.heading_path = headings.iter().map(|(_, t)| t.clone()).collect();
+        body_start = offset;
+    }
+
+    if current.start < content.len() {
+        current.end = content.len();
+        sections.push(current);
+    }
+
+    sections
+}
+
+/// Level and text of an ATX heading (`#` to `######` followed by a space)
+fn atx_heading(line: &str) -> Option<(usize, String)> {
+    let rest = strip_indent(line)?;
+    let level = rest.chars().take_while(|c| *c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+
+    let text = &rest[level..];
+    if !text.is_empty() && !text.starts_with([' ', '\t']) {
+        return None;
+    }
+    let text = text.trim();
+    // A closing run of `#` only counts when separated by a space: `# C#` keeps its `#`
+    let without_closing = text.trim_end_matches('#');
+    let text = if without_closing.is_empty() || without_closing.ends_with([' ', '\t']) {
+        without_closing.trim_end()
+    } else {
+        text
+    };
+
+    Some((level, text.to_string()))
+}
+
+/// Level of a setext underline: `===` for 1, `---` for 2
+fn setext_level(line: &str) -> Option<usize> {
+    let rest = strip_indent(line)?.trim_end();
+    if rest.len() < 2 {
+        return None;
+    }
+    if rest.chars().all(|c| c == '=') {
+        Some(1)
+    } else if rest.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Marker character and length of a fence opening (three or more backticks or tildes)
+fn opens_fence(line: &str) -> Option<(char, usize)> {
+    let rest = strip_indent(line)?;
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = rest.chars().take_while(|c| *c == marker).count();
+    // The info string of a backtick fence cannot contain backticks
+    if length < 3 || (marker == '`' && rest[length..].contains('`')) {
+        return None;
+    }
+    Some((marker, length))
+}
+
+fn closes_fence(line: &str, marker: char, length: usize) -> bool {
+    strip_indent(line).is_some_and(|rest| {
+        let rest = rest.trim_end();
+        rest.len() >= length && rest.chars().all(|c| c == marker)
+    })
+}
+
+/// Line without up to three leading spaces; `None` when indented further (code)
+fn strip_indent(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches(' ');
+    (line.len() - rest.len() <= 3).then_some(rest)
+}
+
+/// Blocks separated by blank lines, with code fences (including their blank
+/// lines) as blocks of their own
+fn split_markdown_blocks(content: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut pieces = Vec::new();
+    let mut piece_start = start;
+    let mut offset = start;
+    let mut fence = None;
+    let mut seen_text = false;
+    // Set by a blank line or a closed fence: the next text line starts a block
+    let mut block_ended = false;
+
+    for line in content[start..end].split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+
+        if let Some((marker, length)) = fence {
+            if closes_fence(text, marker, length) {
+                fence = None;
+                block_ended = true;
+            }
+        } else if !text.trim().is_empty() {
+            let opened = opens_fence(text);
+            if seen_text && (block_ended || opened.is_some()) && offset > piece_start {
+                pieces.push((piece_start, offset));
+                piece_start = offset;
+            }
+            fence = opened;
+            seen_text = true;
+            block_ended = false;
+        } else if seen_text {
+            block_ended = true;
+        }
+
+        offset += line.len();
+    }
+    if piece_start < end {
+        pieces.push((piece_start, end));
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn settings(
+        strategy: ChunkingStrategy,
+        chunk_size: u32,
+        chunk_overlap: u32,
+    ) -> CollectionSettings {
+        CollectionSettings {
+            chunk_size,
+            chunk_overlap,
+            chunking_strategy: Some(strategy),
+            ..Default::default()
+        }
+    }
+
+    fn texts(content: &str, spans: &[(usize, usize)]) -> Vec<String> {
+        spans
+            .iter()
+            .map(|&(start, end)| content[start..end].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn strategies_cut_at_their_boundaries() {
+        let content = "First point. Second one!\n\nNew paragraph here. End.";
+
+        let sentence = chunk_spans(content, &settings(ChunkingStrategy::Sentence, 30, 0));
+        assert_eq!(
+            texts(content, &sentence),
+            vec!["First point. Second one!\n\n", "New paragraph here. End."]
+        );
+
+        let paragraph = chunk_spans(content, &settings(ChunkingStrategy::Paragraph, 30, 0));
+        assert_eq!(
+            texts(content, &paragraph),
+            vec!["First point. Second one!\n\n", "New paragraph here. End."]
+        );
+
+        // Sentences pack together when they fit, and overlap by whole sentences
+        let sentence = chunk_spans(content, &settings(ChunkingStrategy::Sentence, 40, 13));
+        assert_eq!(
+            texts(content, &sentence),
+            vec![
+                "First point. Second one!\n\n",
+                "Second one!\n\nNew paragraph here. End."
+            ]
+        );
+
+        let fixed = chunk_spans("abcdefghij", &settings(ChunkingStrategy::Fixed, 4, 1));
+        assert_eq!(texts("abcdefghij", &fixed), vec!["abcd", "defg", "ghij"]);
+
+        let token = chunk_spans(
+            "alpha beta gamma delta",
+            &settings(ChunkingStrategy::Token, 2, 0),
+        );
+        assert_eq!(
+            texts("alpha beta gamma delta", &token),
+            vec!["alpha beta ", "gamma delta"]
+        );
+    }
+
+    #[test]
+    fn markdown_chunks_follow_sections_and_fences() {
+        let content = "Intro text.\n\n# Install\n\n## Linux\n\nRun the script.\n\n\
+```sh\n# not a heading\n\n./install.sh\n```\n\n### Troubleshooting\n\nCheck logs.\n\n\
+Usage\n-----\n\nCall `search`.\n";
+        let spans = markdown_chunk_spans(content, &settings(ChunkingStrategy::Fixed, 60, 10));
+        let chunks: Vec<(&str, String)> = spans
+            .iter()
+            .map(|(start, end, path)| (&content[*start..*end], path.join(" > ")))
+            .collect();
+
+        assert_eq!(
+            chunks,
+            vec![
+                ("Intro text.\n\n", "".to_string()),
+                (
+                    "# Install\n\n## Linux\n\nRun the script.\n\n",
+                    "Install > Linux".to_string()
+                ),
+                (
+                    "```sh\n# not a heading\n\n./install.sh\n```\n\n",
+                    "Install > Linux".to_string()
+                ),
+                (
+                    "### Troubleshooting\n\nCheck logs.\n\n",
+                    "Install > Linux > Troubleshooting".to_string()
+                ),
+                // A `---` underline is a level 2 heading
+                (
+                    "Usage\n-----\n\nCall `search`.\n",
+                    "Install > Usage".to_string()
+                ),
+            ]
+        );
+
+        assert_eq!(
+            atx_heading("## C# tips ##"),
+            Some((2, "C# tips".to_string()))
+        );
+        assert_eq!(atx_heading("#hashtag"), None);
+        assert_eq!(atx_heading("    # indented code"), None);
+    }
+
+    fn any_strategy() -> impl Strategy<Value = ChunkingStrategy> {
+        prop_oneof![
+            Just(ChunkingStrategy::Fixed),
+            Just(ChunkingStrategy::Sentence),
+            Just(ChunkingStrategy::Paragraph),
+            Just(ChunkingStrategy::Recursive),
+            Just(ChunkingStrategy::Token),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(300))]
+
+        #[test]
+        fn chunks_cover_the_text_within_size_and_overlap(
+            content in "([a-zé]{1,12}|[ ,]|[.!?] |\n|\n\n|。|日本語){0,120}",
+            strategy in any_strategy(),
+            chunk_size in 1u32..80,
+            overlap_percent in 0u32..=100,
+        ) {
+            let chunk_overlap = chunk_size * overlap_percent / 100;
+            let settings = settings(strategy.clone(), chunk_size, chunk_overlap);
+            let spans = chunk_spans(&content, &settings);
+            // Tokens counted the way the chunker counts them, by where they end
+            let mut token_ends: Vec<usize> =
+                token_starts(&content, encoding_for_model(&settings.embedding_model))
+                    .into_iter()
+                    .skip(1)
+                    .collect();
+            token_ends.push(content.len());
+            let measure = |start: usize, end: usize| match strategy {
+                ChunkingStrategy::Token => {
+                    token_ends.partition_point(|&e| e <= end)
+                        - token_ends.partition_point(|&e| e <= start)
+                }
+                _ => content[start..end].chars().count(),
+            };
+
+            if content.is_empty() {
+                prop_assert!(spans.is_empty());
+                return Ok(());
+            }
+            prop_assert_eq!(spans[0].0, 0);
+            prop_assert_eq!(spans[spans.len() - 1].1, content.len());
+
+            for &(start, end) in &spans {
+                prop_assert!(start < end);
+                prop_assert!(content.is_char_boundary(start) && content.is_char_boundary(end));
+                prop_assert!(measure(start, end) <= chunk_size as usize);
+            }
+            for pair in spans.windows(2) {
+                let ((start, end), (next_start, _)) = (pair[0], pair[1]);
+                // No gap, always progressing, and overlapping by at most chunk_overlap
+                prop_assert!(next_start <= end);
+                prop_assert!(next_start > start);
+                prop_assert!(measure(next_start, end) <= chunk_overlap as usize);
+            }
+        }
+
+        #[test]
+        fn markdown_chunks_cover_each_section(
+            content in "(# [a-z]{1,8}\n|## [a-z]{1,8}\n|```\n|[a-z]{1,10} |[.] |\n|\n\n|---\n){0,80}",
+            chunk_size in 1u32..60,
+            chunk_overlap in 0u32..20,
+        ) {
+            let spans = markdown_chunk_spans(
+                &content,
+                &settings(ChunkingStrategy::Recursive, chunk_size, chunk_overlap),
+            );
+            if content.is_empty() {
+                prop_assert!(spans.is_empty());
+                return Ok(());
+            }
+            prop_assert_eq!(spans[0].0, 0);
+            prop_assert_eq!(spans[spans.len() - 1].1, content.len());
+
+            for (start, end, _) in &spans {
+                prop_assert!(start < end);
+                prop_assert!(content[*start..*end].chars().count() <= chunk_size as usize);
+            }
+            for pair in spans.windows(2) {
+                let ((start, end, path), (next_start, _, next_path)) = (&pair[0], &pair[1]);
+                prop_assert!(next_start <= end && next_start > start);
+                // Overlap never reaches into another section
+                if path != next_path {
+                    prop_assert_eq!(next_start, end);
+                }
+            }
+        }
+    }
+}
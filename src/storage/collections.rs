@@ -24,13 +24,24 @@ thread_local! {
 pub fn create_collection(
     request: CreateCollectionRequest,
     creator: String,
-) -> Result<Collection, String> {
+) -> Result<Collection, BluebandError> {
     validate_collection_id(&request.id)?;
+    if let Some(settings) = &request.settings {
+        validate_collection_settings(settings, &super::config::get_canister_config().outcalls)?;
+    }
+
+    let config = super::config::get_canister_config();
+    check_creation_policy(&config.creation_policy, &creator)?;
 
     if get_collection(&request.id).is_some() {
-        return Err(format!("Collection '{}' already exists", request.id));
+        return Err(format!("Collection '{}' already exists", request.id).into());
     }
 
+    let settings = request
+        .settings
+        .unwrap_or(config.default_collection_settings);
+    super::config::check_collection_proxies(&settings)?;
+
     let collection = Collection {
         id: request.id.clone(),
         name: request.name,
@@ -38,14 +49,20 @@ pub fn create_collection(
         created_at: current_time(),
         updated_at: current_time(),
         genesis_admin: creator.clone(),
-        admins: vec![creator],
-        settings: request.settings.unwrap_or_default(),
+        created_by: creator.clone(),
+        admins: vec![creator.clone()],
+        settings,
+        credentials_fingerprint: None,
+        visibility: request.visibility.unwrap_or_default(),
+        readers: Vec::new(),
+        members: vec![CollectionMember {
+            principal: creator,
+            role: CollectionRole::Owner,
+        }],
+        approval_policy: None,
     };
 
-    COLLECTIONS.with(|c| {
-        c.borrow_mut()
-            .insert(request.id.clone(), collection.clone())
-    });
+    store_collection(collection.clone());
 
     // Initialize empty vector index for this collection in vectors module
     super::vectors::init_collection_index(&request.id);
@@ -53,22 +70,130 @@ pub fn create_collection(
     // Initialize empty document index for this collection
     super::documents::init_collection_document_index(&request.id);
 
+    super::billing::open_account(&request.id);
+
     Ok(collection)
 }
 
 pub fn get_collection(collection_id: &str) -> Option<Collection> {
-    COLLECTIONS.with(|c| c.borrow().get(&collection_id.to_string()))
+    COLLECTIONS
+        .with(|c| c.borrow().get(&collection_id.to_string()))
+        .map(load_collection)
+}
+
+fn check_creation_policy(policy: &CreationPolicy, creator: &str) -> Result<(), BluebandError> {
+    let is_controller = candid::Principal::from_text(creator)
+        .is_ok_and(|principal| crate::auth::is_controller(&principal));
+    if is_controller {
+        return Ok(());
+    }
+
+    match policy {
+        CreationPolicy::Open => Ok(()),
+        CreationPolicy::Allowlist { principals } => {
+            if principals.iter().any(|p| p == creator) {
+                Ok(())
+            } else {
+                Err(BluebandError::Unauthorized(
+                    "Caller is not allowed to create collections".to_string(),
+                ))
+            }
+        }
+        CreationPolicy::ControllersOnly => Err(BluebandError::Unauthorized(
+            "Only canister controllers can create collections".to_string(),
+        )),
+        CreationPolicy::PerPrincipalLimit { max_collections } => {
+            let created = count_created_collections(creator);
+            if created >= *max_collections as u64 {
+                Err(BluebandError::StorageFull(format!(
+                    "Collection limit reached: {} of {} collections",
+                    created, max_collections
+                )))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Number of collections `principal` created. Transferring the genesis admin
+/// role doesn't free a slot, so the limit can't be dodged by handing
+/// collections to other principals.
+pub fn count_created_collections(principal: &str) -> u64 {
+    COLLECTIONS.with(|c| {
+        c.borrow()
+            .iter()
+            .filter(|(_, collection)| {
+                let creator = if collection.created_by.is_empty() {
+                    &collection.genesis_admin
+                } else {
+                    &collection.created_by
+                };
+                creator == principal
+            })
+            .count() as u64
+    })
 }
 
 pub fn list_collections() -> Vec<Collection> {
     COLLECTIONS.with(|c| {
         c.borrow()
             .iter()
-            .map(|(_, collection)| collection)
+            .map(|(_, collection)| load_collection(collection))
             .collect()
     })
 }
 
+/// Builds the member list of collections stored before roles existed and
+/// fills the legacy `admins` and `readers` views from it
+fn load_collection(mut collection: Collection) -> Collection {
+    if collection.members.is_empty() {
+        let mut members = vec![CollectionMember {
+            principal: collection.genesis_admin.clone(),
+            role: CollectionRole::Owner,
+        }];
+        let legacy = collection
+            .admins
+            .iter()
+            .map(|admin| (admin, CollectionRole::Editor))
+            .chain(
+                collection
+                    .readers
+                    .iter()
+                    .map(|reader| (reader, CollectionRole::Searcher)),
+            );
+        for (principal, role) in legacy {
+            if !members.iter().any(|m| m.principal == *principal) {
+                members.push(CollectionMember {
+                    principal: principal.clone(),
+                    role,
+                });
+            }
+        }
+        collection.members = members;
+    }
+
+    collection.admins = members_with(&collection, CollectionRole::is_admin);
+    collection.readers = members_with(&collection, |role| *role == CollectionRole::Searcher);
+    collection
+}
+
+fn members_with(collection: &Collection, filter: impl Fn(&CollectionRole) -> bool) -> Vec<String> {
+    collection
+        .members
+        .iter()
+        .filter(|member| filter(&member.role))
+        .map(|member| member.principal.clone())
+        .collect()
+}
+
+/// `admins` and `readers` are derived from `members`, so only the latter is kept
+fn store_collection(mut collection: Collection) {
+    collection.admins.clear();
+    collection.readers.clear();
+    COLLECTIONS.with(|c| c.borrow_mut().insert(collection.id.clone(), collection));
+}
+
 pub fn get_collection_with_stats(collection_id: &str) -> Option<CollectionWithStats> {
     let collection = get_collection(collection_id)?;
 
@@ -83,106 +208,316 @@ pub fn get_collection_with_stats(collection_id: &str) -> Option<CollectionWithSt
     Some(CollectionWithStats { collection, stats })
 }
 
-pub fn list_collections_with_stats() -> Vec<CollectionWithStats> {
-    list_collections()
+pub fn list_collections_with_stats(caller: &str) -> Vec<CollectionWithStats> {
+    list_visible_collections(caller)
         .into_iter()
         .filter_map(|collection| get_collection_with_stats(&collection.id))
         .collect()
 }
 
+/// Collections the caller may discover: public ones plus any they administer or read
+pub fn list_visible_collections(caller: &str) -> Vec<Collection> {
+    list_collections()
+        .into_iter()
+        .filter(|collection| {
+            collection.visibility == CollectionVisibility::Public
+                || role_of(collection, caller).is_some()
+        })
+        .collect()
+}
+
 // =============================================================================
-// ADMIN MANAGEMENT (Fixed to match Motoko logic)
+// ROLES AND READ ACCESS
 // =============================================================================
 
-pub fn is_collection_admin(collection_id: &str, caller: &str) -> bool {
-    COLLECTIONS.with(|c| {
-        if let Some(collection) = c.borrow().get(&collection_id.to_string()) {
-            collection.genesis_admin == caller || collection.admins.contains(&caller.to_string())
-        } else {
-            false
+const MAX_MEMBERS: usize = 50;
+const MAX_PROPOSAL_EXPIRY_HOURS: u32 = 30 * 24;
+
+/// Role of a principal in the collection; the genesis admin is always an Owner
+pub fn role_of(collection: &Collection, principal: &str) -> Option<CollectionRole> {
+    if collection.genesis_admin == principal {
+        return Some(CollectionRole::Owner);
+    }
+    collection
+        .members
+        .iter()
+        .find(|member| member.principal == principal)
+        .map(|member| member.role.clone())
+}
+
+pub fn get_role(collection_id: &str, principal: &str) -> Option<CollectionRole> {
+    get_collection(collection_id).and_then(|collection| role_of(&collection, principal))
+}
+
+pub fn has_permission(collection_id: &str, caller: &str, permission: CollectionPermission) -> bool {
+    get_role(collection_id, caller).is_some_and(|role| role.grants(permission))
+}
+
+pub fn require_permission(
+    collection_id: &str,
+    caller: &str,
+    permission: CollectionPermission,
+) -> Result<(), BluebandError> {
+    if !collection_exists(collection_id) {
+        return Err(BluebandError::collection_not_found(collection_id));
+    }
+    if !has_permission(collection_id, caller, permission) {
+        return Err(BluebandError::Unauthorized(format!(
+            "{:?} permission required",
+            permission
+        )));
+    }
+    Ok(())
+}
+
+pub fn can_read(collection_id: &str, caller: &str) -> bool {
+    match get_collection(collection_id) {
+        Some(collection) => {
+            collection.visibility != CollectionVisibility::Private
+                || role_of(&collection, caller)
+                    .is_some_and(|role| role.grants(CollectionPermission::ReadContent))
         }
-    })
+        None => false,
+    }
 }
 
-pub fn add_collection_admin(
+pub fn require_read_access(collection_id: &str, caller: &str) -> Result<(), BluebandError> {
+    if !collection_exists(collection_id) {
+        return Err(BluebandError::collection_not_found(collection_id));
+    }
+    if !can_read(collection_id, caller) {
+        return Err(BluebandError::Unauthorized(
+            "Read access required".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+pub fn set_collection_visibility(
     collection_id: &str,
-    new_admin: &str,
+    visibility: CollectionVisibility,
     caller: &str,
-) -> Result<(), String> {
-    COLLECTIONS.with(|c| {
-        if let Some(mut collection) = c.borrow().get(&collection_id.to_string()) {
-            if collection.genesis_admin != caller {
-                return Err("Only the genesis admin can add new admins".to_string());
-            }
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageSettings)?;
+    modify_collection(collection_id, |collection| {
+        collection.visibility = visibility;
+        Ok(())
+    })
+}
 
-            if collection.admins.contains(&new_admin.to_string()) {
-                return Err("User is already an admin".to_string());
-            }
+/// Parses a principal and returns its canonical text form
+pub fn parse_principal(principal: &str) -> Result<String, BluebandError> {
+    candid::Principal::from_text(principal)
+        .map(|p| p.to_text())
+        .map_err(|_| format!("Invalid principal '{}'", principal).into())
+}
 
-            collection.admins.push(new_admin.to_string());
-            collection.updated_at = current_time();
+/// Grants `role` to `principal`, replacing any role it already has
+pub fn set_collection_member(
+    collection_id: &str,
+    principal: &str,
+    role: CollectionRole,
+    caller: &str,
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageMembers)?;
+    let principal = parse_principal(principal)?;
+    put_member(collection_id, &principal, role)
+}
 
-            c.borrow_mut().insert(collection_id.to_string(), collection);
-            Ok(())
-        } else {
-            Err("Collection not found".to_string())
+pub fn remove_collection_member(
+    collection_id: &str,
+    principal: &str,
+    caller: &str,
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageMembers)?;
+    modify_collection(collection_id, |collection| {
+        if collection.genesis_admin == principal {
+            return Err("Cannot remove the genesis admin".into());
         }
+        if !collection.members.iter().any(|m| m.principal == principal) {
+            return Err("User is not a member".into());
+        }
+        collection.members.retain(|m| m.principal != principal);
+        Ok(())
     })
 }
 
-pub fn remove_collection_admin(
+fn put_member(
     collection_id: &str,
-    admin_to_remove: &str,
-    caller: &str,
-) -> Result<(), String> {
-    COLLECTIONS.with(|c| {
-        if let Some(mut collection) = c.borrow().get(&collection_id.to_string()) {
-            if collection.genesis_admin != caller {
-                return Err("Only the genesis admin can remove admins".to_string());
-            }
+    principal: &str,
+    role: CollectionRole,
+) -> Result<(), BluebandError> {
+    modify_collection(collection_id, |collection| {
+        if collection.genesis_admin == principal && role != CollectionRole::Owner {
+            return Err("The genesis admin is always an owner".into());
+        }
 
-            if admin_to_remove == collection.genesis_admin {
-                return Err("Cannot remove the genesis admin".to_string());
+        match collection
+            .members
+            .iter_mut()
+            .find(|m| m.principal == principal)
+        {
+            Some(member) => member.role = role,
+            None => {
+                if collection.members.len() >= MAX_MEMBERS {
+                    return Err(BluebandError::StorageFull(format!(
+                        "Collection cannot have more than {} members",
+                        MAX_MEMBERS
+                    )));
+                }
+                collection.members.push(CollectionMember {
+                    principal: principal.to_string(),
+                    role,
+                });
             }
+        }
+        Ok(())
+    })
+}
 
-            if !collection.admins.contains(&admin_to_remove.to_string()) {
-                return Err("User is not an admin".to_string());
-            }
+pub fn add_collection_reader(
+    collection_id: &str,
+    reader: &str,
+    caller: &str,
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageMembers)?;
+    let reader = parse_principal(reader)?;
+    if get_role(collection_id, &reader).is_some() {
+        return Err("User is already a member".into());
+    }
+    put_member(collection_id, &reader, CollectionRole::Searcher)
+}
 
-            collection.admins.retain(|admin| admin != admin_to_remove);
-            collection.updated_at = current_time();
+pub fn remove_collection_reader(
+    collection_id: &str,
+    reader: &str,
+    caller: &str,
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageMembers)?;
+    if get_role(collection_id, reader) != Some(CollectionRole::Searcher) {
+        return Err("User is not a reader".into());
+    }
+    remove_collection_member(collection_id, reader, caller)
+}
 
-            c.borrow_mut().insert(collection_id.to_string(), collection);
-            Ok(())
-        } else {
-            Err("Collection not found".to_string())
+fn modify_collection(
+    collection_id: &str,
+    update: impl FnOnce(&mut Collection) -> Result<(), BluebandError>,
+) -> Result<(), BluebandError> {
+    let mut collection = get_collection(collection_id)
+        .ok_or_else(|| BluebandError::collection_not_found(collection_id))?;
+    update(&mut collection)?;
+
+    // Removing admins must not leave too few to approve proposals
+    if let Some(policy) = &collection.approval_policy {
+        let admins = collection
+            .members
+            .iter()
+            .filter(|m| m.role.is_admin())
+            .count();
+        if admins < policy.required_approvals as usize {
+            return Err(format!(
+                "The approval policy needs at least {} admins",
+                policy.required_approvals
+            )
+            .into());
         }
-    })
+    }
+
+    collection.updated_at = current_time();
+    store_collection(collection);
+    Ok(())
+}
+
+// =============================================================================
+// ADMIN MANAGEMENT
+// =============================================================================
+
+// Admins are the Owner and Editor roles; these endpoints predate roles
+
+pub fn is_collection_admin(collection_id: &str, caller: &str) -> bool {
+    get_role(collection_id, caller).is_some_and(|role| role.is_admin())
+}
+
+/// Grants the Editor role
+pub fn add_collection_admin(
+    collection_id: &str,
+    new_admin: &str,
+    caller: &str,
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageMembers)?;
+    let new_admin = parse_principal(new_admin)?;
+    if is_collection_admin(collection_id, &new_admin) {
+        return Err("User is already an admin".into());
+    }
+    put_member(collection_id, &new_admin, CollectionRole::Editor)
+}
+
+pub fn remove_collection_admin(
+    collection_id: &str,
+    admin_to_remove: &str,
+    caller: &str,
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageMembers)?;
+    if !is_collection_admin(collection_id, admin_to_remove) {
+        return Err("User is not an admin".into());
+    }
+    remove_collection_member(collection_id, admin_to_remove, caller)
 }
 
 pub fn transfer_genesis_admin(
     collection_id: &str,
     new_genesis_admin: &str,
     caller: &str,
-) -> Result<(), String> {
-    COLLECTIONS.with(|c| {
-        if let Some(mut collection) = c.borrow().get(&collection_id.to_string()) {
-            if collection.genesis_admin != caller {
-                return Err("Only the current genesis admin can transfer ownership".to_string());
-            }
-
-            if !collection.admins.contains(&new_genesis_admin.to_string()) {
-                return Err("New genesis admin must be an existing admin".to_string());
-            }
+) -> Result<(), BluebandError> {
+    require_genesis_admin(collection_id, caller)?;
+    if !is_collection_admin(collection_id, new_genesis_admin) {
+        return Err("New genesis admin must be an existing admin".into());
+    }
+    set_genesis_admin(collection_id, new_genesis_admin)
+}
 
-            collection.genesis_admin = new_genesis_admin.to_string();
-            collection.updated_at = current_time();
+/// Hands a collection whose genesis admin key was lost to a new owner.
+/// Only canister controllers may call this, through `auth::authorize`.
+pub fn recover_collection(
+    collection_id: &str,
+    new_genesis_admin: &str,
+) -> Result<(), BluebandError> {
+    let new_genesis_admin = parse_principal(new_genesis_admin)?;
+    set_genesis_admin(collection_id, &new_genesis_admin)
+}
 
-            c.borrow_mut().insert(collection_id.to_string(), collection);
-            Ok(())
-        } else {
-            Err("Collection not found".to_string())
+/// Makes `principal` the genesis admin; the previous one stays an Owner
+pub fn set_genesis_admin(collection_id: &str, principal: &str) -> Result<(), BluebandError> {
+    modify_collection(collection_id, |collection| {
+        let previous = std::mem::replace(&mut collection.genesis_admin, principal.to_string());
+        collection
+            .members
+            .retain(|m| m.principal != principal && m.principal != previous);
+        if previous != principal {
+            collection.members.insert(
+                0,
+                CollectionMember {
+                    principal: previous,
+                    role: CollectionRole::Owner,
+                },
+            );
+        }
+        collection.members.insert(
+            0,
+            CollectionMember {
+                principal: principal.to_string(),
+                role: CollectionRole::Owner,
+            },
+        );
+
+        if collection.members.len() > MAX_MEMBERS {
+            return Err(BluebandError::StorageFull(format!(
+                "Collection cannot have more than {} members",
+                MAX_MEMBERS
+            )));
         }
+        Ok(())
     })
 }
 
@@ -190,20 +525,101 @@ pub fn update_collection_settings(
     collection_id: &str,
     settings: CollectionSettings,
     caller: &str,
-) -> Result<(), String> {
-    // Any admin can update settings (unchanged behavior)
-    require_admin_access(collection_id, caller)?;
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageSettings)?;
+    require_outcall_url_owner(collection_id, &settings, caller)?;
+    replace_settings(collection_id, settings)
+}
 
-    COLLECTIONS.with(|c| {
-        let mut collections = c.borrow_mut();
-        if let Some(mut collection) = collections.get(&collection_id.to_string()) {
-            collection.settings = settings;
-            collection.updated_at = current_time();
-            collections.insert(collection_id.to_string(), collection);
-            Ok(())
-        } else {
-            Err(format!("Collection '{}' not found", collection_id))
+/// While a collection has secret headers, only its genesis admin may point
+/// its outcalls at other URLs
+pub fn require_outcall_url_owner(
+    collection_id: &str,
+    settings: &CollectionSettings,
+    caller: &str,
+) -> Result<(), BluebandError> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| BluebandError::collection_not_found(collection_id))?;
+    if collection.credentials_fingerprint.is_none()
+        || outcall_urls(&collection.settings) == outcall_urls(settings)
+    {
+        return Ok(());
+    }
+    require_genesis_admin(collection_id, caller).map_err(|_| {
+        BluebandError::Unauthorized(
+            "Only the genesis admin can change outcall URLs while secret headers are set"
+                .to_string(),
+        )
+    })
+}
+
+pub fn outcall_urls(settings: &CollectionSettings) -> Vec<Option<&str>> {
+    let mut urls = vec![
+        Some(settings.proxy_url.as_str()),
+        settings.rerank.as_ref().map(|r| r.url.as_str()),
+        settings.ask.as_ref().map(|a| a.chat_url.as_str()),
+    ];
+    urls.extend(
+        settings
+            .fallback_proxy_urls
+            .iter()
+            .flatten()
+            .map(|url| Some(url.as_str())),
+    );
+    urls
+}
+
+pub fn replace_settings(
+    collection_id: &str,
+    settings: CollectionSettings,
+) -> Result<(), BluebandError> {
+    validate_collection_settings(&settings, &super::config::get_canister_config().outcalls)?;
+    super::config::check_collection_proxies(&settings)?;
+    modify_collection(collection_id, |collection| {
+        // Vectors of the old model would no longer match embedded queries
+        if settings.embedding_model != collection.settings.embedding_model
+            && super::vectors::get_vector_count(collection_id) > 0
+        {
+            return Err(
+                "Collection has vectors; change its embedding model with migrate_embedding_model"
+                    .into(),
+            );
         }
+        collection.settings = settings;
+        Ok(())
+    })
+}
+
+/// Switches the model a migration has re-embedded the collection with
+pub fn set_embedding_model(
+    collection_id: &str,
+    embedding_model: &str,
+) -> Result<(), BluebandError> {
+    modify_collection(collection_id, |collection| {
+        collection.settings.embedding_model = embedding_model.to_string();
+        Ok(())
+    })
+}
+
+pub fn set_approval_policy(
+    collection_id: &str,
+    policy: Option<ApprovalPolicy>,
+) -> Result<(), BluebandError> {
+    if let Some(policy) = &policy {
+        if policy.required_approvals == 0 {
+            return Err("An approval policy needs at least one approval".into());
+        }
+        if policy.expiry_hours == 0 || policy.expiry_hours > MAX_PROPOSAL_EXPIRY_HOURS {
+            return Err(format!(
+                "Proposal expiry must be 1-{} hours",
+                MAX_PROPOSAL_EXPIRY_HOURS
+            )
+            .into());
+        }
+    }
+    modify_collection(collection_id, |collection| {
+        collection.approval_policy = policy;
+        Ok(())
     })
 }
 
@@ -212,46 +628,58 @@ pub fn update_collection_metadata(
     name: Option<String>,
     description: Option<String>,
     caller: &str,
-) -> Result<(), String> {
-    require_admin_access(collection_id, caller)?;
+) -> Result<(), BluebandError> {
+    require_permission(collection_id, caller, CollectionPermission::ManageSettings)?;
 
-    COLLECTIONS.with(|c| {
-        let mut collections = c.borrow_mut();
-        if let Some(mut collection) = collections.get(&collection_id.to_string()) {
-            if let Some(new_name) = name {
-                if new_name.trim().is_empty() || new_name.len() > 100 {
-                    return Err("Collection name must be 1-100 characters".to_string());
-                }
-                collection.name = new_name;
+    modify_collection(collection_id, |collection| {
+        if let Some(new_name) = name {
+            if new_name.trim().is_empty() || new_name.len() > 100 {
+                return Err("Collection name must be 1-100 characters".into());
             }
+            collection.name = new_name;
+        }
 
-            if let Some(new_description) = description {
-                if new_description.len() > 500 {
-                    return Err("Collection description must be 500 characters or less".to_string());
-                }
-                collection.description = Some(new_description);
+        if let Some(new_description) = description {
+            if new_description.len() > 500 {
+                return Err("Collection description must be 500 characters or less".into());
             }
-
-            collection.updated_at = current_time();
-            collections.insert(collection_id.to_string(), collection);
-            Ok(())
-        } else {
-            Err(format!("Collection '{}' not found", collection_id))
+            collection.description = Some(new_description);
         }
+        Ok(())
     })
 }
 
-pub fn delete_collection(collection_id: &str, caller: &str) -> Result<(), String> {
+/// Records the masked fingerprint of the collection's secret headers
+pub fn set_credentials_fingerprint(
+    collection_id: &str,
+    fingerprint: Option<String>,
+) -> Result<(), BluebandError> {
+    modify_collection(collection_id, |collection| {
+        collection.credentials_fingerprint = fingerprint;
+        Ok(())
+    })
+}
+
+pub fn delete_collection(collection_id: &str, caller: &str) -> Result<(), BluebandError> {
     require_genesis_admin(collection_id, caller)?;
+    remove_collection(collection_id)
+}
 
+/// Deletes the collection and everything stored for it, without access checks
+pub fn remove_collection(collection_id: &str) -> Result<(), BluebandError> {
     COLLECTIONS.with(|c| {
         if c.borrow_mut().remove(&collection_id.to_string()).is_some() {
             // Clean up associated indexes
             super::vectors::cleanup_collection_index(collection_id);
+            super::vectors::discard_shadow_vectors(collection_id);
             super::documents::cleanup_collection_document_index(collection_id);
+            super::secrets::remove_collection_secrets(collection_id);
+            super::jobs::delete_collection_jobs(collection_id);
+            super::billing::delete_collection_billing(collection_id);
+            super::proposals::delete_collection_proposals(collection_id);
             Ok(())
         } else {
-            Err("Collection not found".to_string())
+            Err(BluebandError::collection_not_found(collection_id))
         }
     })
 }
@@ -269,36 +697,23 @@ pub fn collection_exists(collection_id: &str) -> bool {
 
 
 pub fn get_collection_admins(collection_id: &str) -> Vec<String> {
-    COLLECTIONS.with(|c| {
-        c.borrow()
-            .get(&collection_id.to_string())
-            .map(|collection| collection.admins.clone())
-            .unwrap_or_default()
-    })
+    get_collection(collection_id)
+        .map(|collection| collection.admins)
+        .unwrap_or_default()
 }
 
 pub fn get_genesis_admin(collection_id: &str) -> Option<String> {
-    COLLECTIONS.with(|c| {
-        c.borrow()
-            .get(&collection_id.to_string())
-            .map(|collection| collection.genesis_admin.clone())
-    })
+    get_collection(collection_id).map(|collection| collection.genesis_admin)
 }
 
 pub fn get_admin_level(collection_id: &str, caller: &str) -> AdminLevel {
-    COLLECTIONS.with(|c| {
-        if let Some(collection) = c.borrow().get(&collection_id.to_string()) {
-            if collection.genesis_admin == caller {
-                AdminLevel::Genesis
-            } else if collection.admins.contains(&caller.to_string()) {
-                AdminLevel::Regular
-            } else {
-                AdminLevel::None
-            }
-        } else {
-            AdminLevel::None
+    match get_collection(collection_id) {
+        Some(collection) if collection.genesis_admin == caller => AdminLevel::Genesis,
+        Some(collection) if role_of(&collection, caller).is_some_and(|r| r.is_admin()) => {
+            AdminLevel::Regular
         }
-    })
+        _ => AdminLevel::None,
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -308,19 +723,246 @@ pub enum AdminLevel {
     None,
 }
 
-pub fn require_admin_access(collection_id: &str, caller: &str) -> Result<AdminLevel, String> {
+pub fn require_genesis_admin(collection_id: &str, caller: &str) -> Result<(), BluebandError> {
     let level = get_admin_level(collection_id, caller);
     match level {
-        AdminLevel::Genesis | AdminLevel::Regular => Ok(level),
-        AdminLevel::None => Err("Admin access required".to_string()),
+        AdminLevel::Genesis => Ok(()),
+        AdminLevel::Regular => Err(BluebandError::Unauthorized(
+            "Genesis admin access required".to_string(),
+        )),
+        AdminLevel::None => Err(BluebandError::Unauthorized(
+            "Admin access required".to_string(),
+        )),
     }
 }
 
-pub fn require_genesis_admin(collection_id: &str, caller: &str) -> Result<(), String> {
-    let level = get_admin_level(collection_id, caller);
-    match level {
-        AdminLevel::Genesis => Ok(()),
-        AdminLevel::Regular => Err("Genesis admin access required".to_string()),
-        AdminLevel::None => Err("Admin access required".to_string()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_admins_and_readers_become_members() {
+        let collection = load_collection(Collection {
+            id: "legacy".to_string(),
+            genesis_admin: "genesis".to_string(),
+            admins: vec!["genesis".to_string(), "admin".to_string()],
+            readers: vec!["reader".to_string()],
+            ..Default::default()
+        });
+
+        let roles: Vec<(&str, CollectionRole)> = collection
+            .members
+            .iter()
+            .map(|m| (m.principal.as_str(), m.role.clone()))
+            .collect();
+        assert_eq!(
+            roles,
+            vec![
+                ("genesis", CollectionRole::Owner),
+                ("admin", CollectionRole::Editor),
+                ("reader", CollectionRole::Searcher),
+            ]
+        );
+        assert_eq!(collection.admins, vec!["genesis", "admin"]);
+        assert_eq!(collection.readers, vec!["reader"]);
+    }
+
+    #[test]
+    fn genesis_admin_changes_keep_one_entry_per_member_within_the_cap() {
+        let owner = candid::Principal::from_slice(&[2]).to_text();
+        crate::auth::set_test_caller(candid::Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: "recovery".to_string(),
+            name: "Recovery".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+
+        // Recovering to the current genesis admin changes nothing
+        recover_collection("recovery", &owner).unwrap();
+        let members = get_collection("recovery").unwrap().members;
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].principal, owner);
+
+        for i in 0..MAX_MEMBERS - 1 {
+            let member = candid::Principal::from_slice(&[10, i as u8]).to_text();
+            put_member("recovery", &member, CollectionRole::Searcher).unwrap();
+        }
+
+        // A new owner from outside the member list would exceed the cap
+        let outsider = candid::Principal::from_slice(&[9]).to_text();
+        assert!(matches!(
+            recover_collection("recovery", &outsider),
+            Err(BluebandError::StorageFull(_))
+        ));
+        assert_eq!(get_collection("recovery").unwrap().genesis_admin, owner);
+
+        // Promoting an existing member stays within it
+        let member = candid::Principal::from_slice(&[10, 0]).to_text();
+        recover_collection("recovery", &member).unwrap();
+        let collection = get_collection("recovery").unwrap();
+        assert_eq!(collection.members.len(), MAX_MEMBERS);
+        assert_eq!(role_of(&collection, &owner), Some(CollectionRole::Owner));
+        assert_eq!(role_of(&collection, &member), Some(CollectionRole::Owner));
+    }
+
+    #[test]
+    fn private_collections_are_hidden_from_non_readers() {
+        use crate::auth::set_test_caller;
+        use candid::Principal;
+
+        let owner = Principal::from_slice(&[2]);
+        let reader = Principal::from_slice(&[3]);
+        let stranger = Principal::from_slice(&[4]);
+
+        set_test_caller(owner);
+        for (id, visibility) in [
+            ("public", CollectionVisibility::Public),
+            ("private", CollectionVisibility::Private),
+        ] {
+            crate::create_collection(CreateCollectionRequest {
+                id: id.to_string(),
+                name: id.to_string(),
+                description: None,
+                settings: None,
+                visibility: Some(visibility),
+            })
+            .unwrap();
+        }
+        crate::add_collection_reader("private".to_string(), reader.to_text()).unwrap();
+
+        let visible = |principal: Principal| -> Vec<String> {
+            let mut ids: Vec<String> = list_visible_collections(&principal.to_text())
+                .into_iter()
+                .map(|c| c.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(visible(owner), ["private", "public"]);
+        assert_eq!(visible(reader), ["private", "public"]);
+        assert_eq!(visible(stranger), ["public"]);
+
+        set_test_caller(reader);
+        assert!(crate::get_collection("private".to_string()).is_some());
+        assert!(crate::is_collection_admin(
+            "private".to_string(),
+            owner.to_text()
+        ));
+
+        // Strangers learn neither the collection nor who administers it
+        set_test_caller(stranger);
+        assert!(crate::get_collection("private".to_string()).is_none());
+        assert!(crate::get_genesis_admin("private".to_string()).is_none());
+        assert!(!crate::is_collection_admin(
+            "private".to_string(),
+            owner.to_text()
+        ));
+        assert!(crate::list_documents("private".to_string()).is_empty());
+        assert!(matches!(
+            require_read_access("private", &stranger.to_text()),
+            Err(BluebandError::Unauthorized(_))
+        ));
+        assert!(crate::is_collection_admin(
+            "public".to_string(),
+            owner.to_text()
+        ));
+    }
+
+    #[test]
+    fn settings_that_would_overflow_the_collection_are_refused() {
+        let validate = |settings: &CollectionSettings| {
+            validate_collection_settings(settings, &OutcallConfig::default())
+        };
+        let ask = AskConfig {
+            chat_url: "https://chat.example.com/v1/chat/completions".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            system_prompt: None,
+            prompt_template: Some("Context: {context}\nQuestion: {question}".to_string()),
+            max_context_tokens: None,
+            max_answer_tokens: None,
+        };
+        let with_ask = |ask: AskConfig| CollectionSettings {
+            ask: Some(ask),
+            ..Default::default()
+        };
+        assert!(validate(&with_ask(ask.clone())).is_ok());
+
+        let err = validate(&with_ask(AskConfig {
+            system_prompt: Some("x".repeat(1_001)),
+            ..ask.clone()
+        }))
+        .unwrap_err();
+        assert!(err.contains("System prompt"));
+
+        let err = validate(&with_ask(AskConfig {
+            prompt_template: Some("Question: {question}".to_string()),
+            ..ask.clone()
+        }))
+        .unwrap_err();
+        assert!(err.contains("{context}"));
+
+        let err = validate(&with_ask(AskConfig {
+            chat_url: format!("https://{}", "a".repeat(300)),
+            ..ask.clone()
+        }))
+        .unwrap_err();
+        assert!(err.contains("Chat URL"));
+
+        let err = validate(&CollectionSettings {
+            rerank: Some(RerankConfig {
+                url: format!("https://{}", "a".repeat(300)),
+                model: None,
+                format: RerankFormat::Tei,
+                top_n: None,
+                fallback_on_error: false,
+            }),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.contains("Rerank URL"));
+
+        // Each prompt is within its limit, but escaping more than doubles them
+        let err = validate(&with_ask(AskConfig {
+            system_prompt: Some("\u{1}".repeat(1_000)),
+            ..ask
+        }))
+        .unwrap_err();
+        assert!(err.contains("once encoded"));
+    }
+
+    #[test]
+    fn token_chunks_must_fit_the_model_and_outcall_limits() {
+        let token_settings =
+            |embedding_model: &str, chunk_size, max_input_tokens| CollectionSettings {
+                embedding_model: embedding_model.to_string(),
+                chunk_size,
+                chunk_overlap: 0,
+                chunking_strategy: Some(ChunkingStrategy::Token),
+                max_input_tokens,
+                ..Default::default()
+            };
+        let outcalls = OutcallConfig::default();
+        let validate = |settings| validate_collection_settings(&settings, &outcalls);
+
+        assert!(validate(token_settings("text-embedding-3-small", 8_191, None)).is_ok());
+        let err = validate(token_settings("text-embedding-3-small", 8_192, None)).unwrap_err();
+        assert!(err.contains("limit of 8191"));
+        let err = validate(token_settings("bge-small", 600, Some(512))).unwrap_err();
+        assert!(err.contains("limit of 512"));
+
+        // Custom models without a declared limit are still held to the text limit
+        assert!(validate(token_settings("bge-small", 20_000, None)).is_ok());
+        let err = validate(token_settings("bge-small", 40_000, None)).unwrap_err();
+        assert!(err.contains("32000 characters"));
+
+        // Character chunks are cut to the text limit by size alone
+        let fixed = CollectionSettings {
+            chunking_strategy: None,
+            ..token_settings("text-embedding-3-small", 9_000, None)
+        };
+        assert!(validate(fixed).is_ok());
     }
 }
@@ -0,0 +1,264 @@
+// storage/config.rs
+use crate::types::*;
+
+const CANISTER_CONFIG_KEY: &str = "canister.config";
+
+// Outcall responses larger than this are rejected by the IC
+const IC_MAX_RESPONSE_BYTES: u64 = 2_000_000;
+const MAX_ALLOWED_PROXY_HOSTS: usize = 50;
+const MAX_ALLOWED_CREATORS: usize = 200;
+
+// =============================================================================
+// CONFIGURATION
+// =============================================================================
+
+pub fn get_canister_config() -> CanisterConfig {
+    super::get_config(CANISTER_CONFIG_KEY)
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default()
+}
+
+/// Validates and stores the configuration. Callers apply the parts that are
+/// held on the heap, such as the cache limits.
+pub fn set_canister_config(mut config: CanisterConfig) -> Result<CanisterConfig, BluebandError> {
+    let cache = &config.cache;
+    if cache.max_memory_bytes < 1024 * 1024 || cache.max_entries == 0 {
+        return Err("Cache needs at least 1 MB and one entry".into());
+    }
+    if cache.ttl_seconds < 60 {
+        return Err("Cache TTL must be at least 60 seconds".into());
+    }
+
+    let outcalls = &config.outcalls;
+    if outcalls.max_texts_per_request == 0
+        || outcalls.max_chars_per_text == 0
+        || outcalls.max_chars_per_request < outcalls.max_chars_per_text
+    {
+        return Err(
+            "Outcall limits must allow at least one text and max_chars_per_request >= max_chars_per_text"
+                .into(),
+        );
+    }
+    if outcalls.max_response_bytes == 0 || outcalls.max_response_bytes > IC_MAX_RESPONSE_BYTES {
+        return Err(format!("max_response_bytes must be 1-{}", IC_MAX_RESPONSE_BYTES).into());
+    }
+    validate_collection_settings(&config.default_collection_settings, outcalls)
+        .map_err(|e| format!("Invalid default collection settings: {}", e))?;
+
+    if config.allowed_proxy_hosts.len() > MAX_ALLOWED_PROXY_HOSTS {
+        return Err(format!("At most {} allowed proxy hosts", MAX_ALLOWED_PROXY_HOSTS).into());
+    }
+    for pattern in config.allowed_proxy_hosts.iter_mut() {
+        *pattern = pattern.trim().to_lowercase();
+        let host = pattern.strip_prefix("*.").unwrap_or(pattern);
+        if host.is_empty()
+            || !host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        {
+            return Err(format!(
+                "Invalid proxy host pattern '{}': use a host name such as api.openai.com or *.example.com",
+                pattern
+            ).into());
+        }
+    }
+    check_settings_proxies(
+        &config.default_collection_settings,
+        &config.allowed_proxy_hosts,
+    )
+    .map_err(|e| e.with_context("Invalid default collection settings"))?;
+
+    match &mut config.creation_policy {
+        CreationPolicy::Allowlist { principals } => {
+            if principals.len() > MAX_ALLOWED_CREATORS {
+                return Err(
+                    format!("At most {} allowlisted creators", MAX_ALLOWED_CREATORS).into(),
+                );
+            }
+            for principal in principals.iter_mut() {
+                *principal = super::collections::parse_principal(principal.trim())?;
+            }
+            principals.sort();
+            principals.dedup();
+        }
+        CreationPolicy::PerPrincipalLimit { max_collections: 0 } => {
+            return Err("max_collections must be at least 1".into());
+        }
+        _ => {}
+    }
+
+    let value = serde_json::to_string(&config).map_err(|e| e.to_string())?;
+    super::set_config(CANISTER_CONFIG_KEY, value)?;
+    Ok(config)
+}
+
+// =============================================================================
+// PROXY HOSTS
+// =============================================================================
+
+/// Host of an `https://` URL, lowercased and without port or path
+pub fn proxy_host(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("https://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_lowercase())
+    }
+}
+
+/// Whether `host` matches an allowlist entry. `*.example.com` matches any
+/// subdomain of example.com but not example.com itself.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+fn check_url(url: &str, allowed: &[String]) -> Result<(), BluebandError> {
+    let host = proxy_host(url).ok_or_else(|| format!("Proxy URL '{}' must use HTTPS", url))?;
+    if !allowed.is_empty() && !allowed.iter().any(|pattern| host_matches(pattern, &host)) {
+        return Err(format!(
+            "Proxy host '{}' is not in the allowlist (allowed: {})",
+            host,
+            allowed.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Checks an outcall URL against the configured host allowlist
+pub fn check_proxy_url(url: &str) -> Result<(), BluebandError> {
+    check_url(url, &get_canister_config().allowed_proxy_hosts)
+}
+
+/// Checks a collection's proxy, fallback, rerank and chat URLs against the configured allowlist
+pub fn check_collection_proxies(settings: &CollectionSettings) -> Result<(), BluebandError> {
+    check_settings_proxies(settings, &get_canister_config().allowed_proxy_hosts)
+}
+
+fn check_settings_proxies(
+    settings: &CollectionSettings,
+    allowed: &[String],
+) -> Result<(), BluebandError> {
+    check_url(&settings.proxy_url, allowed).map_err(|e| e.with_context("proxy_url"))?;
+    for url in settings.fallback_proxy_urls.iter().flatten() {
+        check_url(url, allowed).map_err(|e| e.with_context("fallback_proxy_urls"))?;
+    }
+    if let Some(rerank) = &settings.rerank {
+        check_url(&rerank.url, allowed).map_err(|e| e.with_context("rerank.url"))?;
+    }
+    if let Some(ask) = &settings.ask {
+        check_url(&ask.chat_url, allowed).map_err(|e| e.with_context("ask.chat_url"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist_and_creation_policy_apply_at_runtime() {
+        assert_eq!(
+            proxy_host("https://user@API.example.com:8443/v1?x=1"),
+            Some("api.example.com".to_string())
+        );
+        assert_eq!(proxy_host("http://api.example.com"), None);
+
+        let invalid = CanisterConfig {
+            allowed_proxy_hosts: vec!["https://api.example.com/v1".to_string()],
+            ..Default::default()
+        };
+        assert!(set_canister_config(invalid).is_err());
+
+        // The default settings point at api.openai.com
+        let without_default = CanisterConfig {
+            allowed_proxy_hosts: vec!["*.example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(set_canister_config(without_default).is_err());
+
+        let config = set_canister_config(CanisterConfig {
+            allowed_proxy_hosts: vec!["api.openai.com".to_string(), "*.Example.com".to_string()],
+            creation_policy: CreationPolicy::ControllersOnly,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(config.allowed_proxy_hosts[1], "*.example.com");
+        assert!(check_proxy_url("https://api.example.com/v1/embeddings").is_ok());
+        assert!(check_proxy_url("https://example.com/v1").is_err());
+        assert!(check_proxy_url("https://evilexample.com/v1").is_err());
+        let err = check_proxy_url("https://evil.example.net/v1")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'evil.example.net' is not in the allowlist"));
+
+        let settings = CollectionSettings {
+            fallback_proxy_urls: Some(vec!["https://evil.example.net".to_string()]),
+            ..Default::default()
+        };
+        let err = check_collection_proxies(&settings).unwrap_err().to_string();
+        assert!(err.starts_with("fallback_proxy_urls:"));
+
+        let request = || CreateCollectionRequest {
+            id: "configured".to_string(),
+            name: "Configured".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        };
+        let stranger = candid::Principal::from_slice(&[3]).to_text();
+        let controller = candid::Principal::from_slice(&[1]).to_text();
+        assert!(
+            crate::storage::collections::create_collection(request(), stranger.clone()).is_err()
+        );
+        assert!(crate::storage::collections::create_collection(request(), controller).is_ok());
+
+        let limited = CanisterConfig {
+            creation_policy: CreationPolicy::PerPrincipalLimit { max_collections: 1 },
+            ..Default::default()
+        };
+        set_canister_config(limited).unwrap();
+        let create = |id: &str, creator: &String| {
+            let request = CreateCollectionRequest {
+                id: id.to_string(),
+                ..request()
+            };
+            crate::storage::collections::create_collection(request, creator.clone())
+        };
+        assert!(create("first", &stranger).is_ok());
+        let err = create("second", &stranger).unwrap_err();
+        assert_eq!(
+            err,
+            BluebandError::StorageFull("Collection limit reached: 1 of 1 collections".to_string())
+        );
+
+        // Handing the collection to someone else doesn't free the slot
+        let other = candid::Principal::from_slice(&[4]).to_text();
+        crate::storage::collections::set_genesis_admin("first", &other).unwrap();
+        assert!(create("second", &stranger).is_err());
+
+        let allowlisted = CanisterConfig {
+            creation_policy: CreationPolicy::Allowlist {
+                principals: vec![stranger.clone(), "not a principal".to_string()],
+            },
+            ..Default::default()
+        };
+        assert!(set_canister_config(allowlisted).is_err());
+        set_canister_config(CanisterConfig {
+            creation_policy: CreationPolicy::Allowlist {
+                principals: vec![stranger.clone()],
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(create("second", &stranger).is_ok());
+        assert!(create("third", &other).is_err());
+    }
+}
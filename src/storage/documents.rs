@@ -1,9 +1,10 @@
 // storage/documents.rs
-use ic_stable_structures::StableBTreeMap;
+use ic_stable_structures::{StableBTreeMap, Storable};
 use std::cell::RefCell;
 
 use super::memory::{
     get_memory, MemoryType, CHUNKS_MEMORY_ID, DOCUMENTS_MEMORY_ID, DOCUMENT_INDEX_MEMORY_ID,
+    SOURCES_MEMORY_ID,
 };
 use crate::types::*;
 
@@ -26,6 +27,11 @@ thread_local! {
     static DOCUMENT_INDEX: RefCell<StableBTreeMap<String, StringList, MemoryType>> = RefCell::new(
         StableBTreeMap::init(get_memory(DOCUMENT_INDEX_MEMORY_ID))
     );
+
+    // Document Sources: document_id -> original content, kept only for HTML
+    static DOCUMENT_SOURCES: RefCell<StableBTreeMap<String, String, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(SOURCES_MEMORY_ID))
+    );
 }
 
 // =============================================================================
@@ -52,40 +58,76 @@ fn validate_document_metadata(document: &DocumentMetadata) -> Result<(), String>
     Ok(())
 }
 
-pub fn add_document(request: AddDocumentRequest) -> Result<DocumentMetadata, String> {
-    validate_document_content(&request.content)?;
-
-    let collection = super::collections::get_collection(&request.collection_id)
-        .ok_or_else(|| format!("Collection '{}' not found", request.collection_id))?;
+// Collection that can take one more document
+fn collection_with_room(collection_id: &str) -> Result<Collection, BluebandError> {
+    let collection = super::collections::get_collection(collection_id)
+        .ok_or_else(|| BluebandError::collection_not_found(collection_id))?;
 
     if let Some(max_docs) = collection.settings.max_documents {
-        let current_count = count_collection_documents(&request.collection_id);
+        let current_count = count_collection_documents(collection_id);
         if current_count >= max_docs as u64 {
-            return Err(format!(
+            return Err(BluebandError::StorageFull(format!(
                 "Collection has reached maximum of {} documents",
                 max_docs
-            ));
+            )));
         }
     }
+    Ok(collection)
+}
+
+fn content_checksum(content: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    format!("{:x}", hasher.finalize())
+}
+
+// A file that names itself fills in an empty title
+fn choose_title(requested: String, found: Option<&String>) -> String {
+    match found {
+        Some(found) if requested.trim().is_empty() => found.chars().take(200).collect(),
+        _ => requested,
+    }
+}
+
+pub fn add_document(request: AddDocumentRequest) -> Result<DocumentMetadata, BluebandError> {
+    validate_document_content(&request.content)?;
+    let collection = collection_with_room(&request.collection_id)?;
 
     let document_id = generate_id("doc", &request.title);
-    let storage_key = format!("{}::{}", request.collection_id, document_id);
+    let checksum = content_checksum(request.content.as_bytes());
 
-    let checksum = {
-        use sha2::{Digest, Sha256};
-        let mut hasher = Sha256::new();
-        hasher.update(request.content.as_bytes());
-        format!("{:x}", hasher.finalize())
+    // HTML is chunked as the text extracted from it; the markup is kept as the source
+    let extracted = match request.content_type {
+        Some(ContentType::Html) => Some(super::html::extract_html(&request.content)),
+        _ => None,
     };
+    let text = extracted
+        .as_ref()
+        .map_or(request.content.as_str(), |e| e.text.as_str());
+    if text.trim().is_empty() {
+        return Err(BluebandError::Validation(
+            "Document has no text content".to_string(),
+        ));
+    }
+
+    let chunks = create_semantic_chunks(
+        text,
+        &document_id,
+        request.content_type.as_ref(),
+        &collection.settings,
+    );
 
-    let chunks = create_semantic_chunks(&request.content, &document_id, &collection.settings);
+    let source_title = extracted.as_ref().and_then(|e| e.title.clone());
+    let canonical_url = extracted.as_ref().and_then(|e| e.canonical_url.clone());
+    let title = choose_title(request.title, source_title.as_ref());
 
     let document = DocumentMetadata {
         id: document_id.clone(),
         collection_id: request.collection_id.clone(),
-        title: request.title,
+        title,
         content_type: request.content_type.unwrap_or_default(),
-        source_url: request.source_url,
+        source_url: request.source_url.or_else(|| canonical_url.clone()),
         timestamp: current_time(),
         total_chunks: chunks.len() as u32,
         size: request.content.len() as u64,
@@ -93,21 +135,112 @@ pub fn add_document(request: AddDocumentRequest) -> Result<DocumentMetadata, Str
         checksum,
         author: request.author,
         tags: request.tags,
+        embedding_state: DocumentEmbeddingState::NotEmbedded,
+        embedded_chunks: 0,
+        source_title,
+        canonical_url,
+    };
+
+    let source = extracted.is_some().then_some(request.content);
+    store_document(document, chunks, source)
+}
+
+/// Adds a document given as bytes. PDFs are chunked page by page; text types
+/// are decoded as UTF-8 and added like `add_document`.
+pub fn add_blob_document(
+    request: AddBlobDocumentRequest,
+    bytes: Vec<u8>,
+) -> Result<DocumentMetadata, BluebandError> {
+    match request.content_type {
+        ContentType::Pdf => add_pdf_document(request, &bytes),
+        ContentType::Other(kind) => Err(BluebandError::Validation(format!(
+            "Unsupported content type for a blob: {}",
+            kind
+        ))),
+        content_type => {
+            let content = String::from_utf8(bytes)
+                .map_err(|_| BluebandError::Validation("Content is not valid UTF-8".to_string()))?;
+            add_document(AddDocumentRequest {
+                collection_id: request.collection_id,
+                title: request.title,
+                content,
+                content_type: Some(content_type),
+                source_url: request.source_url,
+                author: request.author,
+                tags: request.tags,
+            })
+        }
+    }
+}
+
+fn add_pdf_document(
+    request: AddBlobDocumentRequest,
+    bytes: &[u8],
+) -> Result<DocumentMetadata, BluebandError> {
+    if bytes.is_empty() {
+        return Err("Document content cannot be empty".into());
+    }
+    if bytes.len() as u64 > super::uploads::MAX_UPLOAD_SIZE {
+        return Err("Document content exceeds 10MB limit".into());
+    }
+    let collection = collection_with_room(&request.collection_id)?;
+
+    let pdf = super::pdf::extract_pdf(bytes)?;
+    let document_id = generate_id("doc", &request.title);
+    let chunks = create_page_chunks(&pdf.pages, &document_id, &collection.settings);
+    if chunks.is_empty() {
+        return Err(BluebandError::Validation(
+            "PDF has no text layer; scanned pages need OCR before they can be added".to_string(),
+        ));
+    }
+
+    let document = DocumentMetadata {
+        id: document_id,
+        collection_id: request.collection_id,
+        title: choose_title(request.title, pdf.title.as_ref()),
+        content_type: ContentType::Pdf,
+        source_url: request.source_url,
+        timestamp: current_time(),
+        total_chunks: chunks.len() as u32,
+        size: bytes.len() as u64,
+        is_embedded: false,
+        checksum: content_checksum(bytes),
+        author: request.author,
+        tags: request.tags,
+        embedding_state: DocumentEmbeddingState::NotEmbedded,
+        embedded_chunks: 0,
+        source_title: pdf.title,
+        canonical_url: None,
     };
 
+    store_document(document, chunks, None)
+}
+
+// Stores a new document with its chunks and, for extracted formats, its source
+fn store_document(
+    document: DocumentMetadata,
+    chunks: Vec<SemanticChunk>,
+    source: Option<String>,
+) -> Result<DocumentMetadata, BluebandError> {
     validate_document_metadata(&document)?;
+    check_chunk_list_size(&chunks)?;
 
     // Store document metadata
+    let storage_key = format!("{}::{}", document.collection_id, document.id);
     DOCUMENTS.with(|d| d.borrow_mut().insert(storage_key, document.clone()));
 
     // Store all chunks for this document
     DOCUMENT_CHUNKS.with(|c| {
         c.borrow_mut()
-            .insert(document_id.clone(), ChunkList(chunks))
+            .insert(document.id.clone(), ChunkList(chunks))
     });
 
+    if let Some(source) = source {
+        DOCUMENT_SOURCES.with(|s| s.borrow_mut().insert(document.id.clone(), source));
+    }
+
     // Update document index for collection lookups
-    add_to_document_index(&request.collection_id, &document_id);
+    add_to_document_index(&document.collection_id, &document.id);
 
     Ok(document)
 }
@@ -117,7 +250,9 @@ pub fn get_document(collection_id: &str, document_id: &str) -> Option<DocumentMe
         if let Some(doc_ids) = idx.borrow().get(&collection_id.to_string()) {
             if doc_ids.0.contains(&document_id.to_string()) {
                 let storage_key = format!("{}::{}", collection_id, document_id);
-                DOCUMENTS.with(|d| d.borrow().get(&storage_key))
+                DOCUMENTS
+                    .with(|d| d.borrow().get(&storage_key))
+                    .map(normalize_embedding_state)
             } else {
                 None
             }
@@ -142,9 +277,10 @@ pub fn list_documents(collection_id: &str) -> Vec<DocumentMetadata> {
     })
 }
 
-pub fn delete_document(collection_id: &str, document_id: &str) -> Result<(), String> {
+pub fn delete_document(collection_id: &str, document_id: &str) -> Result<(), BluebandError> {
     // Delete document chunks
     DOCUMENT_CHUNKS.with(|c| c.borrow_mut().remove(&document_id.to_string()));
+    DOCUMENT_SOURCES.with(|s| s.borrow_mut().remove(&document_id.to_string()));
 
     // Delete associated vectors
     let _ = super::vectors::delete_document_vectors(document_id);
@@ -157,28 +293,150 @@ pub fn delete_document(collection_id: &str, document_id: &str) -> Result<(), Str
             index.insert(collection_id.to_string(), doc_ids);
             Ok(())
         } else {
-            Err("Collection not found".to_string())
+            Err(BluebandError::collection_not_found(collection_id))
         }
     })
 }
 
-pub fn mark_document_embedded(collection_id: &str, document_id: &str) -> Result<(), String> {
-    let storage_key = format!("{}::{}", collection_id, document_id);
-    DOCUMENTS.with(|d| {
-        let mut docs = d.borrow_mut();
-        if let Some(mut doc) = docs.get(&storage_key) {
-            doc.is_embedded = true;
-            docs.insert(storage_key, doc);
-            Ok(())
-        } else {
-            Err(format!(
-                "Document '{}' not found in collection '{}'",
-                document_id, collection_id
-            ))
+// =============================================================================
+// EMBEDDING STATE
+// =============================================================================
+
+/// Documents stored before per-chunk tracking only carry `is_embedded`
+fn normalize_embedding_state(mut document: DocumentMetadata) -> DocumentMetadata {
+    if document.is_embedded && document.embedding_state != DocumentEmbeddingState::Embedded {
+        document.embedding_state = DocumentEmbeddingState::Embedded;
+        document.embedded_chunks = document.total_chunks;
+    }
+    document
+}
+
+/// Status of a chunk, resolving legacy chunks from their document's state
+fn effective_chunk_status(chunk: &SemanticChunk, document_embedded: bool) -> ChunkEmbeddingStatus {
+    match &chunk.embedding_status {
+        Some(status) => status.clone(),
+        None if document_embedded => ChunkEmbeddingStatus::Embedded,
+        None => ChunkEmbeddingStatus::Pending,
+    }
+}
+
+/// Chunks that still need a vector: pending or failed, in document order
+pub fn get_unembedded_chunks(collection_id: &str, document_id: &str) -> Vec<SemanticChunk> {
+    let document_embedded = get_document(collection_id, document_id)
+        .map(|doc| doc.is_embedded)
+        .unwrap_or(false);
+
+    get_document_chunks(document_id)
+        .into_iter()
+        .filter(|chunk| {
+            effective_chunk_status(chunk, document_embedded) != ChunkEmbeddingStatus::Embedded
+        })
+        .collect()
+}
+
+pub fn set_chunk_status(
+    document_id: &str,
+    chunk_ids: &[String],
+    status: ChunkEmbeddingStatus,
+) -> Result<(), BluebandError> {
+    DOCUMENT_CHUNKS.with(|c| {
+        let mut chunks_map = c.borrow_mut();
+        let mut chunks = chunks_map
+            .get(&document_id.to_string())
+            .ok_or_else(|| BluebandError::not_found("Chunks of document", document_id))?;
+
+        for chunk in chunks.0.iter_mut() {
+            if chunk_ids.contains(&chunk.id) {
+                chunk.embedding_status = Some(status.clone());
+            }
+        }
+
+        // Room was reserved for an empty failure on every chunk, not for the message
+        if chunks.to_bytes().len() > ChunkList::BOUND.max_size() as usize {
+            for chunk in chunks.0.iter_mut() {
+                if chunk_ids.contains(&chunk.id) {
+                    if let Some(ChunkEmbeddingStatus::Failed(error)) = &mut chunk.embedding_status {
+                        error.clear();
+                    }
+                }
+            }
         }
+
+        chunks_map.insert(document_id.to_string(), chunks);
+        Ok(())
     })
 }
 
+/// Marks the given chunks, or all of them, pending again after their vectors
+/// were deleted, and recomputes the document's embedding state
+pub fn mark_chunks_pending(
+    collection_id: &str,
+    document_id: &str,
+    chunk_ids: Option<&[String]>,
+) -> Result<DocumentMetadata, BluebandError> {
+    let document = get_document(collection_id, document_id)
+        .ok_or_else(|| BluebandError::not_found("Document", document_id))?;
+
+    DOCUMENT_CHUNKS.with(|c| {
+        let mut chunks_map = c.borrow_mut();
+        if let Some(mut chunks) = chunks_map.get(&document_id.to_string()) {
+            for chunk in chunks.0.iter_mut() {
+                // Legacy chunks read their status from the document, which is about to change
+                let status = if chunk_ids.is_none_or(|ids| ids.contains(&chunk.id)) {
+                    ChunkEmbeddingStatus::Pending
+                } else {
+                    effective_chunk_status(chunk, document.is_embedded)
+                };
+                chunk.embedding_status = Some(status);
+            }
+            chunks_map.insert(document_id.to_string(), chunks);
+        }
+    });
+
+    refresh_document_embedding_state(collection_id, document_id)
+}
+
+/// Recomputes a document's embedding state from its chunks.
+/// `is_embedded` is only set once every chunk has a vector.
+pub fn refresh_document_embedding_state(
+    collection_id: &str,
+    document_id: &str,
+) -> Result<DocumentMetadata, BluebandError> {
+    let mut document = get_document(collection_id, document_id)
+        .ok_or_else(|| BluebandError::not_found("Document", document_id))?;
+
+    let chunks = get_document_chunks(document_id);
+    let embedded = chunks
+        .iter()
+        .filter(|chunk| {
+            effective_chunk_status(chunk, document.is_embedded) == ChunkEmbeddingStatus::Embedded
+        })
+        .count() as u32;
+
+    document.embedded_chunks = embedded;
+    document.is_embedded = !chunks.is_empty() && embedded as usize == chunks.len();
+    document.embedding_state = if document.is_embedded {
+        DocumentEmbeddingState::Embedded
+    } else if embedded > 0 {
+        DocumentEmbeddingState::PartiallyEmbedded
+    } else {
+        DocumentEmbeddingState::NotEmbedded
+    };
+
+    let storage_key = format!("{}::{}", collection_id, document_id);
+    DOCUMENTS.with(|d| d.borrow_mut().insert(storage_key, document.clone()));
+    Ok(document)
+}
+
+/// Ids of the documents of a collection that have only some of their chunks embedded
+pub fn partially_embedded_document_ids(collection_id: &str) -> Vec<DocumentId> {
+    list_documents(collection_id)
+        .into_iter()
+        .filter(|doc| doc.embedding_state == DocumentEmbeddingState::PartiallyEmbedded)
+        .map(|doc| doc.id)
+        .collect()
+}
+
 // =============================================================================
 // DOCUMENT INDEX OPERATIONS ( O(1))
 // =============================================================================
@@ -227,9 +485,49 @@ pub fn get_chunk(document_id: &str, chunk_id: &str) -> Option<SemanticChunk> {
     })
 }
 
-pub fn get_chunk_text(document_id: &str, chunk_id: &str) -> Option<String> {
-    // get specific chunk text
-    get_chunk(document_id, chunk_id).map(|chunk| chunk.text)
+/// Joins consecutive chunks (in position order) into one passage, keeping the
+/// `chunk_overlap` region only once. Returns the text with its `char_start`/`char_end`.
+pub fn merge_chunks(chunks: &[SemanticChunk]) -> Option<(String, u64, u64)> {
+    let first = chunks.first()?;
+    let mut text = first.text.clone();
+    let mut end = first.char_end;
+
+    for chunk in &chunks[1..] {
+        if chunk.char_end <= end {
+            continue;
+        }
+        if chunk.char_start < end {
+            // Offsets fall on character boundaries of the same content
+            let overlap = (end - chunk.char_start) as usize;
+            text.push_str(chunk.text.get(overlap..).unwrap_or_default());
+        } else {
+            // Whitespace-only stretches are not stored as chunks
+            if chunk.char_start > end {
+                text.push(' ');
+            }
+            text.push_str(&chunk.text);
+        }
+        end = chunk.char_end;
+    }
+
+    Some((text, first.char_start, end))
+}
+
+/// Original content of a document, with the text its chunk offsets refer to.
+/// Only HTML documents keep their source; `None` for everything else.
+pub fn get_document_source(collection_id: &str, document_id: &str) -> Option<DocumentSource> {
+    let document = get_document(collection_id, document_id)?;
+    let content = DOCUMENT_SOURCES.with(|s| s.borrow().get(&document_id.to_string()))?;
+    // Extraction is deterministic, so the text is rebuilt rather than stored twice
+    let extracted_text = match document.content_type {
+        ContentType::Html => Some(super::html::extract_html(&content).text),
+        _ => None,
+    };
+    Some(DocumentSource {
+        content_type: document.content_type,
+        content,
+        extracted_text,
+    })
 }
 
 pub fn get_document_content(_collection_id: &str, document_id: &str) -> Option<String> {
@@ -247,63 +545,85 @@ pub fn get_document_content(_collection_id: &str, document_id: &str) -> Option<S
 fn create_semantic_chunks(
     content: &str,
     document_id: &str,
+    content_type: Option<&ContentType>,
     settings: &CollectionSettings,
 ) -> Vec<SemanticChunk> {
-    let chunk_size = settings.chunk_size as usize;
-    let overlap = settings.chunk_overlap as usize;
-    let mut chunks = Vec::new();
-    let mut position = 0u32;
+    let spans: Vec<ChunkSpan> = match content_type {
+        // Extracted HTML is written as Markdown
+        Some(ContentType::Markdown | ContentType::Html) => {
+            super::chunking::markdown_chunk_spans(content, settings)
+                .into_iter()
+                .map(|(start, end, heading_path)| (start, end, heading_path, None))
+                .collect()
+        }
+        _ => super::chunking::chunk_spans(content, settings)
+            .into_iter()
+            .map(|(start, end)| (start, end, Vec::new(), None))
+            .collect(),
+    };
 
-    // Convert content to char iterator with positions
-    let mut char_indices = content.char_indices().peekable();
+    chunks_from_spans(content, document_id, spans, settings)
+}
 
-    
-    while let Some((start_byte, _)) = char_indices.next() {
-        let mut char_count = 0;
-        let mut end_byte = start_byte;
+/// Pages are joined with a blank line; chunk offsets point into that text
+const PAGE_SEPARATOR: &str = "\n\n";
 
-        // Count characters until we reach chunk_size or end of content
-        while char_count < chunk_size {
-            if let Some((next_byte, _)) = char_indices.peek() {
-                end_byte = *next_byte;
-                char_indices.next();
-                char_count += 1;
-            } else {
-                end_byte = content.len();
-                break;
-            }
+// Chunks each page on its own, so every chunk has a single page number
+fn create_page_chunks(
+    pages: &[String],
+    document_id: &str,
+    settings: &CollectionSettings,
+) -> Vec<SemanticChunk> {
+    let mut spans: Vec<ChunkSpan> = Vec::new();
+    let mut offset = 0;
+    for (index, page) in pages.iter().enumerate() {
+        for (start, end) in super::chunking::chunk_spans(page, settings) {
+            spans.push((
+                offset + start,
+                offset + end,
+                Vec::new(),
+                Some(index as u32 + 1),
+            ));
         }
+        offset += page.len() + PAGE_SEPARATOR.len();
+    }
 
-        // Extract the chunk text
-        let chunk_text = content[start_byte..end_byte].to_string();
-        let token_count = estimate_tokens(&chunk_text);
-
-        if !chunk_text.trim().is_empty() {
-            let chunk_id = format!("chunk_{}", position);
-            chunks.push(SemanticChunk {
-                id: chunk_id,
-                document_id: document_id.to_string(),
-                text: chunk_text,
-                position,
-                char_start: start_byte as u64,
-                char_end: end_byte as u64,
-                token_count: Some(token_count),
-            });
-            position += 1;
-        }
+    chunks_from_spans(&pages.join(PAGE_SEPARATOR), document_id, spans, settings)
+}
 
-        // Move back for overlap
-        if end_byte < content.len() {
-            // Reset char_indices to the overlap position
-            char_indices = content.char_indices().peekable();
-            while let Some((pos, _)) = char_indices.next() {
-                if pos >= end_byte - overlap {
-                    break;
-                }
-            }
-        } else {
-            break;
+// Byte range, heading path and page of one chunk
+type ChunkSpan = (usize, usize, Vec<String>, Option<u32>);
+
+fn chunks_from_spans(
+    content: &str,
+    document_id: &str,
+    spans: Vec<ChunkSpan>,
+    settings: &CollectionSettings,
+) -> Vec<SemanticChunk> {
+    // Counted with the embedding model's tokenizer
+    let encoding = super::tokens::encoding_for_model(&settings.embedding_model);
+    let mut chunks = Vec::new();
+    let mut position = 0u32;
+
+    for (start_byte, end_byte, heading_path, page) in spans {
+        let chunk_text = &content[start_byte..end_byte];
+        if chunk_text.trim().is_empty() {
+            continue;
         }
+
+        chunks.push(SemanticChunk {
+            id: format!("chunk_{}", position),
+            document_id: document_id.to_string(),
+            text: chunk_text.to_string(),
+            position,
+            char_start: start_byte as u64,
+            char_end: end_byte as u64,
+            token_count: Some(super::tokens::count_tokens(chunk_text, encoding)),
+            embedding_status: Some(ChunkEmbeddingStatus::Pending),
+            heading_path: (!heading_path.is_empty()).then_some(heading_path),
+            page,
+        });
+        position += 1;
     }
 
     chunks
@@ -342,12 +662,6 @@ pub fn get_document_title(collection_id: &str, document_id: &str) -> Option<Stri
     get_document(collection_id, document_id).map(|doc| doc.title)
 }
 
-// Simple token estimation
-fn estimate_tokens(text: &str) -> u32 {
-    // Rough estimation: ~4 characters per token for English
-    (text.len() as f32 / 4.0).ceil() as u32
-}
-
 pub fn get_document_count(collection_id: &str) -> u64 {
     DOCUMENT_INDEX.with(|idx| {
         idx.borrow()
@@ -366,6 +680,7 @@ pub fn get_collection_documents(collection_id: &str) -> Vec<DocumentMetadata> {
                     .0
                     .iter()
                     .filter_map(|id| documents.get(&id.to_string()))
+                    .map(normalize_embedding_state)
                     .collect()
             })
         } else {
@@ -374,7 +689,7 @@ pub fn get_collection_documents(collection_id: &str) -> Vec<DocumentMetadata> {
     })
 }
 
-pub fn delete_collection_documents(collection_id: &str) -> Result<(), String> {
+pub fn delete_collection_documents(collection_id: &str) -> Result<(), BluebandError> {
     DOCUMENT_INDEX.with(|idx| {
         if let Some(doc_ids) = idx.borrow().get(&collection_id.to_string()) {
             DOCUMENTS.with(|d| {
@@ -389,6 +704,12 @@ pub fn delete_collection_documents(collection_id: &str) -> Result<(), String> {
                     chunks.remove(&doc_id.to_string());
                 }
             });
+            DOCUMENT_SOURCES.with(|s| {
+                let mut sources = s.borrow_mut();
+                for doc_id in doc_ids.0.iter() {
+                    sources.remove(&doc_id.to_string());
+                }
+            });
         }
     });
     DOCUMENT_INDEX.with(|idx| idx.borrow_mut().remove(&collection_id.to_string()));
@@ -404,9 +725,232 @@ pub fn get_document_chunks_by_document_id(document_id: &str) -> Vec<SemanticChun
     })
 }
 
-pub fn store_document_chunks(document_id: &str, chunks: Vec<SemanticChunk>) {
+pub fn store_document_chunks(
+    document_id: &str,
+    chunks: Vec<SemanticChunk>,
+) -> Result<(), BluebandError> {
+    check_chunk_list_size(&chunks)?;
     DOCUMENT_CHUNKS.with(|c| {
         c.borrow_mut()
             .insert(document_id.to_string(), ChunkList(chunks))
     });
+    Ok(())
+}
+
+/// All of a document's chunks are stored as one entry. Refuses chunks that
+/// would not fit its bound once every chunk is marked as failed.
+fn check_chunk_list_size(chunks: &[SemanticChunk]) -> Result<(), BluebandError> {
+    let status_reserve = serde_json::to_vec(&ChunkEmbeddingStatus::Failed(String::new()))
+        .map_or(0, |bytes| bytes.len());
+    let size =
+        serde_json::to_vec(chunks).map_or(0, |bytes| bytes.len()) + chunks.len() * status_reserve;
+    let max_size = ChunkList::BOUND.max_size() as usize;
+    if size > max_size {
+        return Err(BluebandError::Validation(format!(
+            "Document chunks need {} bytes of storage, more than the {} byte limit; split the document",
+            size, max_size
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_embedded_documents_resolve_to_embedded_chunks() {
+        let document = normalize_embedding_state(DocumentMetadata {
+            total_chunks: 4,
+            is_embedded: true,
+            ..Default::default()
+        });
+        assert_eq!(document.embedding_state, DocumentEmbeddingState::Embedded);
+        assert_eq!(document.embedded_chunks, 4);
+
+        let legacy_chunk = SemanticChunk::default();
+        assert_eq!(
+            effective_chunk_status(&legacy_chunk, true),
+            ChunkEmbeddingStatus::Embedded
+        );
+        assert_eq!(
+            effective_chunk_status(&legacy_chunk, false),
+            ChunkEmbeddingStatus::Pending
+        );
+    }
+
+    #[test]
+    fn merged_chunks_match_the_original_content() {
+        let content = "Größere Pizzen – mit Käse und Tomaten. ".repeat(12);
+        let settings = CollectionSettings {
+            chunk_size: 40,
+            chunk_overlap: 9,
+            ..Default::default()
+        };
+        let chunks = create_semantic_chunks(&content, "doc", None, &settings);
+        assert!(chunks.len() > 5);
+
+        for start in 0..chunks.len() {
+            for end in start + 1..=chunks.len() {
+                let (text, char_start, char_end) = merge_chunks(&chunks[start..end]).unwrap();
+                assert_eq!(text, content[char_start as usize..char_end as usize]);
+                assert_eq!(char_start, chunks[start].char_start);
+                assert_eq!(char_end, chunks[end - 1].char_end);
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_chunks_stay_on_their_page() {
+        let pages = vec![
+            "First page text that runs past one chunk.".to_string(),
+            String::new(),
+            "Third page.".to_string(),
+        ];
+        let settings = CollectionSettings {
+            chunk_size: 30,
+            chunk_overlap: 0,
+            ..Default::default()
+        };
+        let chunks = create_page_chunks(&pages, "doc", &settings);
+        let joined = pages.join(PAGE_SEPARATOR);
+
+        let located: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.page, &joined[c.char_start as usize..c.char_end as usize]))
+            .collect();
+        assert_eq!(
+            located,
+            vec![
+                (Some(1), "First page text that runs past"),
+                (Some(1), " one chunk."),
+                (Some(3), "Third page."),
+            ]
+        );
+    }
+
+    #[test]
+    fn documents_whose_chunks_overflow_their_entry_are_refused() {
+        crate::auth::set_test_caller(candid::Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: "large".to_string(),
+            name: "Large".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+
+        let err = add_document(AddDocumentRequest {
+            collection_id: "large".to_string(),
+            title: "Large".to_string(),
+            content: "word ".repeat(300_000),
+            content_type: None,
+            source_url: None,
+            author: None,
+            tags: None,
+        })
+        .unwrap_err();
+        assert!(matches!(err, BluebandError::Validation(_)));
+        assert!(err.to_string().contains("split the document"));
+        assert_eq!(get_document_count("large"), 0);
+        assert_eq!(count_chunks(), 0);
+
+        let chunks = vec![
+            SemanticChunk {
+                text: "x".repeat(600_000),
+                ..Default::default()
+            };
+            2
+        ];
+        assert!(store_document_chunks("doc_large", chunks).is_err());
+        assert!(get_document_chunks("doc_large").is_empty());
+    }
+
+    #[test]
+    fn documents_whose_vectors_were_deleted_can_be_embedded_again() {
+        let c = "unembed";
+        crate::auth::set_test_caller(candid::Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: c.to_string(),
+            name: "Unembed".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        let document = add_document(AddDocumentRequest {
+            collection_id: c.to_string(),
+            title: "Doc".to_string(),
+            content: "Alpha beta gamma. ".repeat(200),
+            content_type: None,
+            source_url: None,
+            author: None,
+            tags: None,
+        })
+        .unwrap();
+
+        let embed = || {
+            let chunks = get_document_chunks(&document.id);
+            let ids: Vec<String> = chunks.iter().map(|chunk| chunk.id.clone()).collect();
+            set_chunk_status(&document.id, &ids, ChunkEmbeddingStatus::Embedded).unwrap();
+            refresh_document_embedding_state(c, &document.id).unwrap();
+            let vectors = chunks
+                .iter()
+                .map(|chunk| Vector {
+                    id: format!("{}_vec", chunk.id),
+                    document_id: document.id.clone(),
+                    chunk_id: chunk.id.clone(),
+                    embedding: vec![0.6, 0.8],
+                    norm: 1.0,
+                    model: "text-embedding-3-small".to_string(),
+                    created_at: 0,
+                })
+                .collect();
+            super::super::vectors::store_vectors_batch(vectors).unwrap();
+        };
+        let pending = || get_unembedded_chunks(c, &document.id).len();
+        let total = document.total_chunks as usize;
+        assert!(total > 1);
+
+        embed();
+        crate::delete_document_vectors(c.to_string(), document.id.clone()).unwrap();
+        assert!(!get_document(c, &document.id).unwrap().is_embedded);
+        assert_eq!(pending(), total);
+        let job = super::super::jobs::plan_job(c, None, "owner").unwrap();
+        assert_eq!(job.documents[0].status, JobDocumentStatus::Pending);
+
+        embed();
+        let chunk_id = get_document_chunks(&document.id)[0].id.clone();
+        crate::delete_vector(format!("{}_vec", chunk_id)).unwrap();
+        let state = get_document(c, &document.id).unwrap();
+        assert_eq!(
+            state.embedding_state,
+            DocumentEmbeddingState::PartiallyEmbedded
+        );
+        assert_eq!(pending(), 1);
+
+        crate::clear_collection_vectors(c.to_string()).unwrap();
+        assert_eq!(pending(), total);
+        assert_eq!(super::super::vectors::get_vector_count(c), 0);
+    }
+
+    #[test]
+    fn missing_documents_are_reported_as_not_found() {
+        let not_found = |err: BluebandError| matches!(err, BluebandError::NotFound { .. });
+        assert!(not_found(
+            super::super::vectors::extract_collection_id_from_document_id("nowhere").unwrap_err()
+        ));
+        assert!(not_found(
+            set_chunk_status("nowhere", &[], ChunkEmbeddingStatus::Pending).unwrap_err()
+        ));
+        assert!(not_found(
+            crate::compute::find_similar_documents(
+                "nowhere",
+                "nowhere",
+                &crate::compute::SimilarityConfig::default(),
+            )
+            .unwrap_err()
+        ));
+    }
 }
@@ -0,0 +1,621 @@
+// storage/html.rs
+//
+// Turns an HTML page into the text that gets chunked and embedded. Scripts,
+// styles, navigation, page headers and footers, sidebars and forms are dropped.
+// Headings, list items, table rows and preformatted blocks are written as
+// Markdown, so the extracted text is chunked by section like a Markdown
+// document. The parser is a forgiving tag scanner, not a full HTML5 tree
+// builder: unclosed elements simply run until their parent closes.
+
+/// Text of an HTML document plus the metadata found in its markup
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExtractedHtml {
+    pub text: String,
+    /// `<title>`, or the first `<h1>` when there is none
+    pub title: Option<String>,
+    /// `href` of `<link rel="canonical">`
+    pub canonical_url: Option<String>,
+}
+
+// Contents of these never reach the extracted text
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "title", "textarea", "xmp"];
+const BOILERPLATE_ELEMENTS: &[&str] = &[
+    "head", "noscript", "template", "svg", "math", "iframe", "object", "canvas", "nav", "footer",
+    "aside", "form", "button", "select", "dialog",
+];
+const BOILERPLATE_ROLES: &[&str] = &[
+    "navigation",
+    "banner",
+    "contentinfo",
+    "complementary",
+    "search",
+];
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+// Elements that start on a new line, and those set apart by a blank line
+const LINE_ELEMENTS: &[&str] = &[
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "li",
+    "tr",
+    "dt",
+    "dd",
+    "caption",
+    "figcaption",
+    "address",
+    "details",
+    "summary",
+];
+const PARAGRAPH_ELEMENTS: &[&str] = &[
+    "p",
+    "ul",
+    "ol",
+    "dl",
+    "table",
+    "blockquote",
+    "figure",
+    "hr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_boilerplate(&self, inside_content: bool) -> bool {
+        if VOID_ELEMENTS.contains(&self.name.as_str()) || self.self_closing {
+            return false;
+        }
+        // A <header> inside <main> or <article> usually holds the article title
+        BOILERPLATE_ELEMENTS.contains(&self.name.as_str())
+            || (self.name == "header" && !inside_content)
+            || self.attribute("hidden").is_some()
+            || self.attribute("aria-hidden") == Some("true")
+            || self
+                .attribute("role")
+                .is_some_and(|role| BOILERPLATE_ROLES.contains(&role))
+    }
+}
+
+// Builds the extracted text, collapsing whitespace outside <pre>
+#[derive(Default)]
+struct Writer {
+    text: String,
+    // Newlines owed before the next text: 1 for a line break, 2 for a blank line
+    pending_break: usize,
+    pending_space: bool,
+    // Written at the start of the next line, such as a heading's "## "
+    pending_prefix: String,
+}
+
+impl Writer {
+    fn push_text(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                self.pending_space = true;
+                continue;
+            }
+            self.flush();
+            if self.pending_space && !self.text.ends_with([' ', '\n']) && !self.text.is_empty() {
+                self.text.push(' ');
+            }
+            self.pending_space = false;
+            self.text.push(c);
+        }
+    }
+
+    fn push_raw(&mut self, text: &str) {
+        self.flush();
+        self.text.push_str(text);
+    }
+
+    fn flush(&mut self) {
+        if self.pending_break > 0 && !self.text.is_empty() {
+            let trimmed = self.text.trim_end_matches([' ', '\n']).len();
+            self.text.truncate(trimmed);
+            for _ in 0..self.pending_break {
+                self.text.push('\n');
+            }
+            self.pending_space = false;
+        }
+        self.pending_break = 0;
+        if !self.pending_prefix.is_empty() {
+            self.text.push_str(&self.pending_prefix);
+            self.pending_prefix.clear();
+            self.pending_space = false;
+        }
+    }
+
+    fn line_break(&mut self, newlines: usize) {
+        self.pending_break = self.pending_break.max(newlines);
+    }
+
+    fn separator(&mut self, separator: &str) {
+        let trimmed = self.text.trim_end_matches(' ').len();
+        self.text.truncate(trimmed);
+        self.text.push_str(separator);
+        self.pending_space = false;
+    }
+}
+
+/// Extracts the readable text, title and canonical link of an HTML document
+pub fn extract_html(html: &str) -> ExtractedHtml {
+    let mut out = Writer::default();
+    let mut extracted = ExtractedHtml::default();
+    let mut first_h1: Option<String> = None;
+
+    // Name and nesting depth of the boilerplate element being skipped
+    let mut skipping: Option<(String, usize)> = None;
+    // One entry per open list: the next item number for <ol>, None for <ul>
+    let mut lists: Vec<Option<u32>> = Vec::new();
+    let mut content_depth = 0usize;
+    let mut cells_in_row = 0usize;
+    let mut heading: Option<(usize, usize)> = None;
+    let mut in_pre = false;
+
+    let mut i = 0;
+    while i < html.len() {
+        let rest = &html[i..];
+        if !rest.starts_with('<') {
+            let end = rest.find('<').map_or(html.len(), |offset| i + offset);
+            if skipping.is_none() {
+                let text = decode_entities(&html[i..end]);
+                if in_pre {
+                    out.push_raw(&text);
+                } else {
+                    out.push_text(&text);
+                }
+            }
+            i = end;
+            continue;
+        }
+
+        if rest.starts_with("<!--") {
+            i = rest.find("-->").map_or(html.len(), |offset| i + offset + 3);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            i = rest.find('>').map_or(html.len(), |offset| i + offset + 1);
+            continue;
+        }
+
+        let Some((tag, next)) = parse_tag(html, i) else {
+            // A stray '<' is text
+            if skipping.is_none() {
+                out.push_text("<");
+            }
+            i += 1;
+            continue;
+        };
+        i = next;
+        let name = tag.name.as_str();
+
+        if let Some((skipped, depth)) = skipping.as_mut() {
+            if *skipped == tag.name && !tag.self_closing {
+                if tag.closing {
+                    *depth -= 1;
+                } else {
+                    *depth += 1;
+                }
+                if *depth == 0 {
+                    skipping = None;
+                }
+            }
+            // <head> is skipped, but its title and canonical link are kept
+            if !tag.closing && name == "link" {
+                record_canonical(&tag, &mut extracted);
+            }
+            if !tag.closing && RAW_TEXT_ELEMENTS.contains(&name) && !tag.self_closing {
+                let (contents, next) = raw_text(html, i, name);
+                // An <svg> or <math> title labels a drawing, not the page
+                if name == "title" && skipping.as_ref().is_some_and(|(s, _)| s == "head") {
+                    record_title(contents, &mut extracted);
+                }
+                i = next;
+            }
+            continue;
+        }
+
+        if tag.closing {
+            match name {
+                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    if let Some((level, start)) = heading.take() {
+                        if level == 1 && first_h1.is_none() {
+                            let text = out.text.get(start..).unwrap_or_default();
+                            let text = text.trim_start_matches('#').trim();
+                            first_h1 = (!text.is_empty()).then(|| text.to_string());
+                        }
+                    }
+                    out.pending_prefix.clear();
+                }
+                "pre" if in_pre => {
+                    in_pre = false;
+                    if !out.text.ends_with('\n') {
+                        out.text.push('\n');
+                    }
+                    out.text.push_str("```");
+                }
+                "ul" | "ol" => {
+                    lists.pop();
+                }
+                "main" | "article" => content_depth = content_depth.saturating_sub(1),
+                _ => {}
+            }
+            if (name == "ul" || name == "ol") && !lists.is_empty() {
+                out.line_break(1);
+            } else if PARAGRAPH_ELEMENTS.contains(&name) || name == "pre" {
+                out.line_break(2);
+            } else if LINE_ELEMENTS.contains(&name) {
+                out.line_break(1);
+            }
+            continue;
+        }
+
+        if RAW_TEXT_ELEMENTS.contains(&name) && !tag.self_closing {
+            let (contents, next) = raw_text(html, i, name);
+            if name == "title" {
+                record_title(contents, &mut extracted);
+            }
+            i = next;
+            continue;
+        }
+        if tag.is_boilerplate(content_depth > 0) {
+            skipping = Some((tag.name.clone(), 1));
+            continue;
+        }
+
+        // A nested list continues its parent's item on the next line
+        if (name == "ul" || name == "ol") && !lists.is_empty() {
+            out.line_break(1);
+        } else if PARAGRAPH_ELEMENTS.contains(&name) {
+            out.line_break(2);
+        } else if LINE_ELEMENTS.contains(&name) {
+            out.line_break(1);
+        }
+
+        match name {
+            "link" => record_canonical(&tag, &mut extracted),
+            "main" | "article" => content_depth += 1,
+            "br" if heading.is_some() => out.pending_space = true,
+            "br" if in_pre => out.push_raw("\n"),
+            "br" => out.line_break(1),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = (name.as_bytes()[1] - b'0') as usize;
+                out.flush();
+                heading = Some((level, out.text.len()));
+                out.pending_prefix = format!("{} ", "#".repeat(level));
+            }
+            "ul" => lists.push(None),
+            "ol" => {
+                let start = tag.attribute("start").and_then(|s| s.trim().parse().ok());
+                lists.push(Some(start.unwrap_or(1)));
+            }
+            "li" => {
+                let indent = "  ".repeat(lists.len().saturating_sub(1));
+                let marker = match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                out.pending_prefix = format!("{}{}", indent, marker);
+            }
+            "tr" => cells_in_row = 0,
+            "td" | "th" => {
+                if cells_in_row > 0 {
+                    out.separator(" | ");
+                }
+                cells_in_row += 1;
+            }
+            "pre" if !in_pre => {
+                out.line_break(2);
+                out.push_raw("```\n");
+                in_pre = true;
+                // A newline right after <pre> is not part of its content
+                if html[i..].starts_with("\r\n") {
+                    i += 2;
+                } else if html[i..].starts_with('\n') {
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if in_pre {
+        if !out.text.ends_with('\n') {
+            out.text.push('\n');
+        }
+        out.text.push_str("```");
+    }
+
+    extracted.text = out.text.trim_end().to_string();
+    if extracted.title.is_none() {
+        extracted.title = first_h1;
+    }
+    extracted
+}
+
+// Keeps the first canonical link of the page
+fn record_canonical(tag: &Tag, extracted: &mut ExtractedHtml) {
+    let is_canonical = tag.attribute("rel").is_some_and(|rel| {
+        rel.split_ascii_whitespace()
+            .any(|r| r.eq_ignore_ascii_case("canonical"))
+    });
+    if is_canonical && extracted.canonical_url.is_none() {
+        extracted.canonical_url = tag
+            .attribute("href")
+            .map(str::trim)
+            .filter(|href| !href.is_empty())
+            .map(str::to_string);
+    }
+}
+
+// Keeps the first non-empty <title> of the page
+fn record_title(contents: &str, extracted: &mut ExtractedHtml) {
+    if extracted.title.is_none() {
+        let title = collapse_whitespace(&decode_entities(contents));
+        extracted.title = (!title.is_empty()).then_some(title);
+    }
+}
+
+// Contents of the raw text element opened just before `start`, and the offset
+// just past its closing tag
+fn raw_text<'a>(html: &'a str, start: usize, name: &str) -> (&'a str, usize) {
+    let closing = format!("</{}", name);
+    let end =
+        find_ignore_case(&html[start..], &closing).map_or(html.len(), |offset| start + offset);
+    let next = html[end..]
+        .find('>')
+        .map_or(html.len(), |offset| end + offset + 1);
+    (&html[start..end], next)
+}
+
+// Parses the tag starting at `start` (a '<'). Returns None when the '<' does
+// not open a tag, such as in "a < b".
+fn parse_tag(html: &str, start: usize) -> Option<(Tag, usize)> {
+    let bytes = html.as_bytes();
+    let mut i = start + 1;
+    let closing = bytes.get(i) == Some(&b'/');
+    if closing {
+        i += 1;
+    }
+    if !bytes.get(i)?.is_ascii_alphabetic() {
+        return None;
+    }
+
+    let name_start = i;
+    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+        i += 1;
+    }
+    let mut tag = Tag {
+        name: html[name_start..i].to_ascii_lowercase(),
+        attributes: Vec::new(),
+        closing,
+        self_closing: false,
+    };
+
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        match bytes.get(i) {
+            None => return Some((tag, html.len())),
+            Some(b'>') => return Some((tag, i + 1)),
+            Some(b'/') => {
+                tag.self_closing = bytes.get(i + 1) == Some(&b'>');
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let key_start = i;
+        while i < bytes.len() && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            if bytes[i].is_ascii_whitespace() {
+                break;
+            }
+            i += 1;
+        }
+        let key = html[key_start..i].to_ascii_lowercase();
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let mut value = String::new();
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let value_start = i + 1;
+                    let value_end = html[value_start..]
+                        .find(quote as char)
+                        .map_or(html.len(), |offset| value_start + offset);
+                    value = decode_entities(&html[value_start..value_end]);
+                    i = (value_end + 1).min(html.len());
+                }
+                _ => {
+                    let value_start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value = decode_entities(&html[value_start..i]);
+                }
+            }
+        }
+
+        if !key.is_empty() {
+            tag.attributes.push((key, value));
+        } else if i < bytes.len() {
+            // Skip a character that can't start an attribute, such as a stray '='
+            i += 1;
+        }
+    }
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    let needle = needle.as_bytes();
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Decodes character references, leaving unknown named ones as written
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let reference = rest[1..]
+            .find(';')
+            .filter(|&len| len > 0 && len <= 32)
+            .map(|len| &rest[1..1 + len]);
+        match reference.and_then(decode_reference) {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[reference.map_or(0, str::len) + 2..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_reference(reference: &str) -> Option<char> {
+    if let Some(number) = reference.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code).filter(|c| *c != '\0');
+    }
+
+    let c = match reference {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bull" => '•',
+        "middot" => '·',
+        "deg" => '°',
+        "times" => '×',
+        "euro" => '€',
+        _ => return None,
+    };
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extraction_drops_boilerplate_and_keeps_structure() {
+        let html = r#"<!DOCTYPE html>
+<html><head>
+  <title>Install &amp; Setup</title>
+  <link rel="stylesheet" href="/site.css">
+  <LINK REL="canonical" HREF="https://example.com/docs/install">
+  <script>var x = "<p>not text</p>";</script>
+</head>
+<body>
+  <header><a href="/">Home</a></header>
+  <nav><ul><li>Docs</li><li>Blog</li></ul></nav>
+  <main>
+    <h1>Install</h1>
+    <p>Run   the
+       installer.<br>Then restart.</p>
+    <!-- <p>commented out</p> -->
+    <h2>Options</h2>
+    <ol><li>Fast</li><li>Full <b>(slow)</b><ul><li>Nested</li></ul></li></ol>
+    <table><tr><th>Flag</th><th>Meaning</th></tr><tr><td>-q</td><td>Quiet</td></tr></table>
+    <pre>
+if a < b {
+    go();
+}</pre>
+    <div hidden>Hidden text</div>
+  </main>
+  <footer>Copyright 2024</footer>
+</body></html>"#;
+
+        let extracted = extract_html(html);
+        assert_eq!(extracted.title.as_deref(), Some("Install & Setup"));
+        assert_eq!(
+            extracted.canonical_url.as_deref(),
+            Some("https://example.com/docs/install")
+        );
+        assert_eq!(
+            extracted.text,
+            "# Install\n\n\
+             Run the installer.\nThen restart.\n\n\
+             ## Options\n\n\
+             1. Fast\n2. Full (slow)\n  - Nested\n\n\
+             Flag | Meaning\n-q | Quiet\n\n\
+             ```\nif a < b {\n    go();\n}\n```"
+        );
+    }
+
+    #[test]
+    fn entities_decode_and_unknown_ones_stay() {
+        assert_eq!(
+            decode_entities("a &lt; b &#38; c &#x3E; d &bogus; e & f"),
+            "a < b & c > d &bogus; e & f"
+        );
+        // Without a <title>, the first <h1> names the document
+        let extracted = extract_html("<p>Intro</p><h1>Real <em>title</em></h1>");
+        assert_eq!(extracted.title.as_deref(), Some("Real title"));
+    }
+}
@@ -0,0 +1,550 @@
+// storage/jobs.rs
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+use super::memory::{get_memory, MemoryType, JOBS_MEMORY_ID};
+use crate::types::*;
+
+// =============================================================================
+// GLOBAL STORAGE
+// =============================================================================
+
+thread_local! {
+    // Jobs: job_id -> EmbeddingJob
+    static JOBS: RefCell<StableBTreeMap<String, EmbeddingJob, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(JOBS_MEMORY_ID))
+    );
+}
+
+/// Keeps a single job record under its storage bound: each document entry
+/// stays below 1.5 KB even with a stored error of `MAX_JOB_ERROR_CHARS`
+/// characters that all need escaping
+pub const MAX_JOB_DOCUMENTS: usize = 1_000;
+
+/// Per-document errors are cut to this many characters
+pub const MAX_JOB_ERROR_CHARS: usize = 200;
+
+/// Finished jobs are dropped after this long, when the next job is planned
+const FINISHED_JOB_RETENTION_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Finished jobs kept per collection within the retention period
+const MAX_FINISHED_JOBS: usize = 50;
+
+// =============================================================================
+// JOB OPERATIONS
+// =============================================================================
+
+/// Builds a queued embedding job for the given documents of a collection,
+/// without storing it. Documents that are already embedded are recorded as
+/// skipped.
+pub fn plan_job(
+    collection_id: &str,
+    document_ids: Option<Vec<String>>,
+    creator: &str,
+) -> Result<EmbeddingJob, BluebandError> {
+    if !super::collections::collection_exists(collection_id) {
+        return Err(BluebandError::collection_not_found(collection_id));
+    }
+
+    let documents = match document_ids {
+        Some(ids) => {
+            if ids.is_empty() {
+                return Err("No documents provided for embedding job".into());
+            }
+            let mut documents = Vec::new();
+            for id in ids {
+                let document = super::documents::get_document(collection_id, &id)
+                    .ok_or_else(|| BluebandError::not_found("Document", &id))?;
+                if !documents
+                    .iter()
+                    .any(|d: &DocumentMetadata| d.id == document.id)
+                {
+                    documents.push(document);
+                }
+            }
+            documents
+        }
+        None => super::documents::list_documents(collection_id),
+    };
+
+    if documents.len() > MAX_JOB_DOCUMENTS {
+        return Err(format!(
+            "Too many documents for one job ({}, max {})",
+            documents.len(),
+            MAX_JOB_DOCUMENTS
+        )
+        .into());
+    }
+
+    let mut job = new_job(collection_id, creator);
+    for document in documents {
+        let status = if document.is_embedded {
+            job.skipped += 1;
+            JobDocumentStatus::Skipped
+        } else {
+            JobDocumentStatus::Pending
+        };
+
+        job.documents.push(JobDocument {
+            document_id: document.id,
+            status,
+            chunks_embedded: 0,
+            total_chunks: document.total_chunks,
+            error: None,
+        });
+    }
+
+    if !job
+        .documents
+        .iter()
+        .any(|d| d.status == JobDocumentStatus::Pending)
+    {
+        job.status = JobStatus::Completed;
+    }
+
+    Ok(job)
+}
+
+/// Creates a queued job that re-embeds every chunk of a collection with
+/// `embedding_model`, including chunks that were never embedded
+pub fn create_migration_job(
+    collection_id: &str,
+    embedding_model: &str,
+    creator: &str,
+) -> Result<EmbeddingJob, BluebandError> {
+    let job = plan_migration_job(collection_id, embedding_model, creator)?;
+    save_job(job.clone());
+    Ok(job)
+}
+
+/// Builds the job `create_migration_job` would store, without storing it
+pub fn plan_migration_job(
+    collection_id: &str,
+    embedding_model: &str,
+    creator: &str,
+) -> Result<EmbeddingJob, BluebandError> {
+    let collection = super::collections::get_collection(collection_id)
+        .ok_or_else(|| BluebandError::collection_not_found(collection_id))?;
+
+    if embedding_model.trim().is_empty() {
+        return Err("Embedding model cannot be empty".into());
+    }
+    if embedding_model == collection.settings.embedding_model {
+        return Err(format!("Collection already uses '{}'", embedding_model).into());
+    }
+    if list_collection_jobs(collection_id)
+        .iter()
+        .any(|job| job.is_active() && job.migration.is_some())
+    {
+        return Err("An embedding model migration is already running for this collection".into());
+    }
+
+    let mut job = new_job(collection_id, creator);
+    job.migration = Some(ModelMigration {
+        from_model: collection.settings.embedding_model,
+        to_model: embedding_model.to_string(),
+    });
+    queue_new_documents(&mut job)?;
+
+    Ok(job)
+}
+
+/// Chunks a job still has to embed, counting its pending documents in full
+pub fn pending_chunks(job: &EmbeddingJob) -> u32 {
+    job.documents
+        .iter()
+        .filter(|d| d.status == JobDocumentStatus::Pending)
+        .map(|d| d.total_chunks)
+        .sum()
+}
+
+/// Adds the collection's documents that a migration job does not cover yet,
+/// such as those added while it ran. Returns how many were added.
+pub fn queue_new_documents(job: &mut EmbeddingJob) -> Result<usize, BluebandError> {
+    let documents: Vec<DocumentMetadata> = super::documents::list_documents(&job.collection_id)
+        .into_iter()
+        .filter(|document| !job.documents.iter().any(|d| d.document_id == document.id))
+        .collect();
+
+    if job.documents.len() + documents.len() > MAX_JOB_DOCUMENTS {
+        return Err(format!("Too many documents for one job (max {})", MAX_JOB_DOCUMENTS).into());
+    }
+
+    let added = documents.len();
+    for document in documents {
+        let status = if document.total_chunks == 0 {
+            job.skipped += 1;
+            JobDocumentStatus::Skipped
+        } else {
+            JobDocumentStatus::Pending
+        };
+
+        job.documents.push(JobDocument {
+            document_id: document.id,
+            status,
+            chunks_embedded: 0,
+            total_chunks: document.total_chunks,
+            error: None,
+        });
+    }
+    Ok(added)
+}
+
+fn new_job(collection_id: &str, creator: &str) -> EmbeddingJob {
+    purge_finished_jobs(collection_id);
+    let now = current_time();
+    let id = generate_unique_id("job", collection_id, |id| get_job(id).is_some());
+
+    EmbeddingJob {
+        id,
+        collection_id: collection_id.to_string(),
+        created_by: creator.to_string(),
+        created_at: now,
+        updated_at: now,
+        status: JobStatus::Queued,
+        documents: Vec::new(),
+        embedded: 0,
+        skipped: 0,
+        failed: 0,
+        migration: None,
+    }
+}
+
+pub fn get_job(job_id: &str) -> Option<EmbeddingJob> {
+    JOBS.with(|j| j.borrow().get(&job_id.to_string()))
+}
+
+pub fn save_job(mut job: EmbeddingJob) {
+    job.updated_at = current_time();
+    JOBS.with(|j| j.borrow_mut().insert(job.id.clone(), job));
+}
+
+pub fn list_collection_jobs(collection_id: &str) -> Vec<EmbeddingJob> {
+    let mut jobs: Vec<EmbeddingJob> = JOBS.with(|j| {
+        j.borrow()
+            .iter()
+            .filter(|(_, job)| job.collection_id == collection_id)
+            .map(|(_, job)| job)
+            .collect()
+    });
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+    jobs
+}
+
+/// Oldest queued or running job - jobs are processed strictly in FIFO order
+pub fn next_active_job() -> Option<EmbeddingJob> {
+    JOBS.with(|j| {
+        j.borrow()
+            .iter()
+            .filter(|(_, job)| job.is_active())
+            .map(|(_, job)| job)
+            .min_by_key(|job| job.created_at)
+    })
+}
+
+/// Cancels a queued or running job. Documents not yet embedded are marked
+/// cancelled, and a migration's shadow vectors are discarded.
+pub fn cancel_job(job_id: &str) -> Result<EmbeddingJob, BluebandError> {
+    let mut job = get_job(job_id).ok_or_else(|| BluebandError::not_found("Job", job_id))?;
+
+    if !job.is_active() {
+        return Err(format!("Job '{}' is no longer active", job_id).into());
+    }
+
+    job.status = JobStatus::Cancelled;
+    for document in job.documents.iter_mut() {
+        if matches!(
+            document.status,
+            JobDocumentStatus::Pending | JobDocumentStatus::InProgress
+        ) {
+            document.status = JobDocumentStatus::Cancelled;
+        }
+    }
+    if job.migration.is_some() {
+        super::vectors::discard_shadow_vectors(&job.collection_id);
+    }
+
+    save_job(job.clone());
+    Ok(job)
+}
+
+/// Drops finished jobs past the retention period, and the oldest finished
+/// jobs of `collection_id` beyond `MAX_FINISHED_JOBS`
+fn purge_finished_jobs(collection_id: &str) {
+    let cutoff = current_time().saturating_sub(FINISHED_JOB_RETENTION_NS);
+    let mut kept = 0;
+    let mut expired: Vec<String> = Vec::new();
+    for job in list_all_jobs_newest_first() {
+        if job.is_active() {
+            continue;
+        }
+        if job.updated_at < cutoff {
+            expired.push(job.id);
+        } else if job.collection_id == collection_id {
+            kept += 1;
+            if kept > MAX_FINISHED_JOBS {
+                expired.push(job.id);
+            }
+        }
+    }
+
+    JOBS.with(|j| {
+        let mut jobs = j.borrow_mut();
+        for id in expired {
+            jobs.remove(&id);
+        }
+    });
+}
+
+fn list_all_jobs_newest_first() -> Vec<EmbeddingJob> {
+    let mut jobs: Vec<EmbeddingJob> =
+        JOBS.with(|j| j.borrow().iter().map(|(_, job)| job).collect());
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.updated_at));
+    jobs
+}
+
+/// Drops the jobs of a deleted collection
+pub fn delete_collection_jobs(collection_id: &str) {
+    let job_ids: Vec<String> = JOBS.with(|j| {
+        j.borrow()
+            .iter()
+            .filter(|(_, job)| job.collection_id == collection_id)
+            .map(|(id, _)| id)
+            .collect()
+    });
+
+    JOBS.with(|j| {
+        let mut jobs = j.borrow_mut();
+        for id in job_ids {
+            jobs.remove(&id);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::set_test_caller;
+    use crate::storage::{collections, documents, vectors};
+    use candid::Principal;
+
+    fn vector(id: &str, document_id: &str, model: &str) -> Vector {
+        Vector {
+            id: id.to_string(),
+            document_id: document_id.to_string(),
+            chunk_id: "chunk_0".to_string(),
+            embedding: vec![0.6, 0.8],
+            norm: 1.0,
+            model: model.to_string(),
+            created_at: 0,
+        }
+    }
+
+    fn ids(vectors: Vec<Vector>) -> Vec<String> {
+        vectors.into_iter().map(|v| v.id).collect()
+    }
+
+    fn create_job(
+        collection_id: &str,
+        document_ids: Option<Vec<String>>,
+        creator: &str,
+    ) -> Result<EmbeddingJob, BluebandError> {
+        let job = plan_job(collection_id, document_ids, creator)?;
+        save_job(job.clone());
+        Ok(job)
+    }
+
+    #[test]
+    fn jobs_created_in_the_same_round_get_distinct_ids() {
+        let c = "same_round";
+        set_test_caller(Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: c.to_string(),
+            name: "Same round".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+
+        let first = create_job(c, None, "owner").unwrap();
+        let second = create_job(c, None, "owner").unwrap();
+        assert_ne!(first.id, second.id);
+        assert_eq!(list_collection_jobs(c).len(), 2);
+    }
+
+    #[test]
+    fn a_full_job_of_failed_documents_fits_its_bound() {
+        use ic_stable_structures::Storable;
+
+        let job = EmbeddingJob {
+            id: generate_id("job", "bound"),
+            collection_id: "c".repeat(64),
+            created_by: Principal::management_canister().to_text().repeat(8),
+            documents: (0..MAX_JOB_DOCUMENTS)
+                .map(|i| JobDocument {
+                    document_id: format!("doc_{:016x}", u64::MAX - i as u64),
+                    status: JobDocumentStatus::InProgress,
+                    chunks_embedded: u32::MAX,
+                    total_chunks: u32::MAX,
+                    // Control characters take six bytes each once escaped
+                    error: Some("\u{1}".repeat(MAX_JOB_ERROR_CHARS)),
+                })
+                .collect(),
+            embedded: u32::MAX,
+            skipped: u32::MAX,
+            failed: u32::MAX,
+            migration: Some(ModelMigration {
+                from_model: "m".repeat(128),
+                to_model: "m".repeat(128),
+            }),
+            ..Default::default()
+        };
+
+        assert!(job.to_bytes().len() as u32 <= EmbeddingJob::BOUND.max_size());
+    }
+
+    #[test]
+    fn migration_serves_old_vectors_until_the_swap() {
+        let c = "migration";
+        set_test_caller(Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: c.to_string(),
+            name: "Migration".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        let document = documents::add_document(AddDocumentRequest {
+            collection_id: c.to_string(),
+            title: "Doc".to_string(),
+            content: "Some content to chunk".to_string(),
+            content_type: None,
+            source_url: None,
+            author: None,
+            tags: None,
+        })
+        .unwrap();
+        vectors::store_vectors_batch(vec![vector(
+            "vec_old",
+            &document.id,
+            "text-embedding-ada-002",
+        )])
+        .unwrap();
+
+        // Switching the model in place would strand the existing vectors
+        let mut settings = collections::get_collection(c).unwrap().settings;
+        settings.embedding_model = "text-embedding-3-small".to_string();
+        assert!(collections::replace_settings(c, settings).is_err());
+
+        let job = create_migration_job(c, "text-embedding-3-small", "owner").unwrap();
+        assert_eq!(job.documents[0].status, JobDocumentStatus::Pending);
+        assert!(create_migration_job(c, "text-embedding-3-large", "owner").is_err());
+
+        vectors::store_shadow_vectors(
+            c,
+            vec![vector("vec_new", &document.id, "text-embedding-3-small")],
+        )
+        .unwrap();
+        assert_eq!(ids(vectors::get_collection_vectors(c)), ["vec_old"]);
+        assert_eq!(
+            ids(vectors::get_document_vectors(c, &document.id)),
+            ["vec_old"]
+        );
+
+        vectors::swap_in_shadow_vectors(c);
+        assert_eq!(ids(vectors::get_collection_vectors(c)), ["vec_new"]);
+        assert!(vectors::has_retired_vectors());
+        assert_eq!(vectors::delete_retired_vectors(10), 1);
+        assert!(vectors::get_vector("vec_old").is_none());
+
+        // A cancelled migration leaves its shadow vectors for collection
+        cancel_job(&job.id).unwrap();
+        let job = create_migration_job(c, "text-embedding-3-large", "owner").unwrap();
+        vectors::store_shadow_vectors(
+            c,
+            vec![vector("vec_large", &document.id, "text-embedding-3-large")],
+        )
+        .unwrap();
+        cancel_job(&job.id).unwrap();
+        assert_eq!(vectors::delete_retired_vectors(10), 1);
+        assert_eq!(ids(vectors::get_collection_vectors(c)), ["vec_new"]);
+    }
+
+    #[test]
+    fn finished_jobs_are_dropped_after_retention() {
+        let c = "retention";
+        set_test_caller(Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: c.to_string(),
+            name: "Retention".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+
+        // Without documents, jobs are finished as soon as they are created
+        let old = create_job(c, None, "owner").unwrap();
+        assert!(!old.is_active());
+        advance_test_clock(FINISHED_JOB_RETENTION_NS + 1);
+        for _ in 0..MAX_FINISHED_JOBS + 5 {
+            create_job(c, None, "owner").unwrap();
+        }
+
+        let jobs = list_collection_jobs(c);
+        assert_eq!(jobs.len(), MAX_FINISHED_JOBS + 1);
+        assert!(get_job(&old.id).is_none());
+    }
+
+    #[test]
+    fn enqueued_jobs_are_charged_for_their_outcalls() {
+        let c = "charged";
+        set_test_caller(Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: c.to_string(),
+            name: "Charged".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        let document = documents::add_document(AddDocumentRequest {
+            collection_id: c.to_string(),
+            title: "Doc".to_string(),
+            content: "Some words to chunk. ".repeat(200),
+            content_type: None,
+            source_url: None,
+            author: None,
+            tags: None,
+        })
+        .unwrap();
+        assert!(document.total_chunks > 3);
+
+        let limits = |daily| RateLimitConfig {
+            per_principal: LimitSet {
+                outcalls: Some(LimitRule {
+                    burst: 1,
+                    per_minute: 100,
+                    daily: Some(daily),
+                }),
+                ..Default::default()
+            },
+            per_collection: LimitSet::default(),
+        };
+        super::super::limits::set_limits_config(limits(1)).unwrap();
+        let err = crate::enqueue_embedding_job(c.to_string(), None).unwrap_err();
+        assert!(err.contains("Rate limit exceeded"), "{}", err);
+        assert!(crate::migrate_embedding_model(
+            c.to_string(),
+            "text-embedding-3-small".to_string()
+        )
+        .is_err());
+        assert!(list_collection_jobs(c).is_empty());
+
+        super::super::limits::set_limits_config(limits(1_000)).unwrap();
+        crate::enqueue_embedding_job(c.to_string(), None).unwrap();
+        assert_eq!(list_collection_jobs(c).len(), 1);
+    }
+}
@@ -0,0 +1,317 @@
+// storage/limits.rs
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+use super::memory::{get_memory, MemoryType, RATE_LIMITS_MEMORY_ID};
+use crate::types::*;
+
+// =============================================================================
+// GLOBAL STORAGE
+// =============================================================================
+
+thread_local! {
+    // Limit state: principal:<id>:<metric> | collection:<id>:<metric> -> LimitState
+    static LIMITS: RefCell<StableBTreeMap<String, LimitState, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(RATE_LIMITS_MEMORY_ID))
+    );
+
+    // Where the next idle-state sweep starts; restarts from the beginning after upgrades
+    static PRUNE_CURSOR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+const METRICS: [LimitMetric; 4] = [
+    LimitMetric::Documents,
+    LimitMetric::Bytes,
+    LimitMetric::Searches,
+    LimitMetric::Outcalls,
+];
+
+// Limit entries examined for eviction per charged call
+const PRUNE_BATCH: usize = 20;
+
+const DAY_NS: u64 = 86_400 * 1_000_000_000;
+const MINUTE_NS: u128 = 60 * 1_000_000_000;
+
+const LIMITS_CONFIG_KEY: &str = "limits.config";
+
+// =============================================================================
+// CONFIGURATION
+// =============================================================================
+
+pub fn get_limits_config() -> RateLimitConfig {
+    super::get_config(LIMITS_CONFIG_KEY)
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default()
+}
+
+pub fn set_limits_config(config: RateLimitConfig) -> Result<(), BluebandError> {
+    for set in [&config.per_principal, &config.per_collection] {
+        for metric in METRICS {
+            if let Some(rule) = set.rule(metric) {
+                if rule.burst > 0 && rule.per_minute == 0 {
+                    return Err(format!("{:?} limit needs a per-minute refill rate", metric).into());
+                }
+                if rule.daily.is_some_and(|daily| daily < rule.burst) {
+                    return Err(format!("{:?} daily quota is below its burst size", metric).into());
+                }
+            }
+        }
+    }
+
+    let value = serde_json::to_string(&config).map_err(|e| e.to_string())?;
+    super::set_config(LIMITS_CONFIG_KEY, value)
+}
+
+// =============================================================================
+// ENFORCEMENT
+// =============================================================================
+
+/// Charges a call against the caller's and the collection's limits. Either
+/// every charge is recorded or, when one limit is exceeded, none is.
+pub fn consume(
+    caller: &str,
+    collection_id: Option<&str>,
+    charges: &[(LimitMetric, u64)],
+) -> Result<(), BluebandError> {
+    let config = get_limits_config();
+    let now = current_time();
+
+    let mut scopes = vec![(format!("principal:{}", caller), &config.per_principal)];
+    if let Some(collection_id) = collection_id {
+        scopes.push((
+            format!("collection:{}", collection_id),
+            &config.per_collection,
+        ));
+    }
+
+    let mut updates: Vec<(String, LimitState)> = Vec::new();
+
+    for (scope, limits) in &scopes {
+        for &(metric, amount) in charges {
+            let rule = match limits.rule(metric) {
+                Some(rule) => rule,
+                None => continue,
+            };
+
+            let key = format!("{}:{:?}", scope, metric);
+            let state = match updates.iter().position(|(k, _)| *k == key) {
+                Some(idx) => Some(updates.remove(idx).1),
+                None => LIMITS.with(|l| l.borrow().get(&key)),
+            };
+
+            match apply_rule(state, rule, amount, now) {
+                Ok(state) => updates.push((key, state)),
+                Err(retry_after) => {
+                    return Err(BluebandError::QuotaExceeded(RateLimitExceeded {
+                        metric,
+                        scope: scope.clone(),
+                        retry_after_ms: retry_after.div_ceil(1_000_000),
+                        retry_at: now.saturating_add(retry_after),
+                    }))
+                }
+            }
+        }
+    }
+
+    LIMITS.with(|l| {
+        let mut limits = l.borrow_mut();
+        for (key, state) in updates {
+            limits.insert(key, state);
+        }
+    });
+    prune_idle(&config, now, PRUNE_BATCH);
+    Ok(())
+}
+
+/// Examines up to `batch` entries after the sweep cursor and removes those
+/// that no longer differ from a fresh bucket: refilled to capacity, with no
+/// usage today, or without a rule. Returns how many were removed.
+fn prune_idle(config: &RateLimitConfig, now: u64, batch: usize) -> usize {
+    let cursor = PRUNE_CURSOR.with(|c| c.borrow().clone());
+    let scanned: Vec<(String, LimitState)> = LIMITS.with(|l| {
+        let limits = l.borrow();
+        match &cursor {
+            Some(cursor) => limits
+                .range(cursor.clone()..)
+                .skip_while(|(key, _)| key == cursor)
+                .take(batch)
+                .collect(),
+            None => limits.iter().take(batch).collect(),
+        }
+    });
+
+    // Wrap around once the end is reached
+    let next_cursor = if scanned.len() < batch {
+        None
+    } else {
+        scanned.last().map(|(key, _)| key.clone())
+    };
+    PRUNE_CURSOR.with(|c| *c.borrow_mut() = next_cursor);
+
+    let idle: Vec<String> = scanned
+        .into_iter()
+        .filter(|(key, state)| match rule_for_key(config, key) {
+            Some(rule) => {
+                apply_rule(Some(state.clone()), rule, 0, now) == apply_rule(None, rule, 0, now)
+            }
+            None => true,
+        })
+        .map(|(key, _)| key)
+        .collect();
+
+    LIMITS.with(|l| {
+        let mut limits = l.borrow_mut();
+        for key in &idle {
+            limits.remove(key);
+        }
+    });
+    idle.len()
+}
+
+/// Rule that applies to a stored `principal:<id>:<metric>` or `collection:<id>:<metric>` key
+fn rule_for_key<'a>(config: &'a RateLimitConfig, key: &str) -> Option<&'a LimitRule> {
+    let limits = if key.starts_with("principal:") {
+        &config.per_principal
+    } else {
+        &config.per_collection
+    };
+    let metric_name = key.rsplit(':').next()?;
+    METRICS
+        .into_iter()
+        .find(|metric| format!("{:?}", metric) == metric_name)
+        .and_then(|metric| limits.rule(metric))
+}
+
+/// Refills the bucket and takes `amount` from it and from the daily quota.
+/// On refusal returns how many nanoseconds to wait. A charge larger than the
+/// whole bucket is let through once the bucket is full, and drains it.
+fn apply_rule(
+    state: Option<LimitState>,
+    rule: &LimitRule,
+    amount: u64,
+    now: u64,
+) -> Result<LimitState, u64> {
+    let capacity = rule.burst.saturating_mul(1000);
+    let day = now / DAY_NS;
+    let mut state = state.unwrap_or(LimitState {
+        tokens_milli: capacity,
+        refilled_at: now,
+        day,
+        used_today: 0,
+    });
+
+    if state.day != day {
+        state.day = day;
+        state.used_today = 0;
+    }
+
+    if let Some(daily) = rule.daily {
+        if state.used_today.saturating_add(amount) > daily {
+            return Err((day + 1) * DAY_NS - now);
+        }
+    }
+
+    if rule.burst > 0 {
+        let rate = rule.per_minute as u128 * 1000;
+        let elapsed = now.saturating_sub(state.refilled_at) as u128;
+        let refill = elapsed * rate / MINUTE_NS;
+        let tokens = state.tokens_milli as u128 + refill;
+
+        if tokens >= capacity as u128 {
+            state.tokens_milli = capacity;
+            state.refilled_at = now;
+        } else {
+            // Only advance by the time actually converted into tokens
+            state.tokens_milli = tokens as u64;
+            state.refilled_at += (refill * MINUTE_NS / rate) as u64;
+        }
+
+        let cost = amount.saturating_mul(1000).min(capacity);
+        if state.tokens_milli < cost {
+            let missing = (cost - state.tokens_milli) as u128;
+            return Err((missing * MINUTE_NS).div_ceil(rate) as u64);
+        }
+        state.tokens_milli -= cost;
+    }
+
+    state.used_today = state.used_today.saturating_add(amount);
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND_NS: u64 = 1_000_000_000;
+
+    #[test]
+    fn bucket_refuses_when_empty_and_refills_over_time() {
+        let rule = LimitRule {
+            burst: 2,
+            per_minute: 60,
+            daily: None,
+        };
+        let now = 10 * DAY_NS;
+
+        let state = apply_rule(None, &rule, 1, now).unwrap();
+        let state = apply_rule(Some(state), &rule, 1, now).unwrap();
+        assert_eq!(
+            apply_rule(Some(state.clone()), &rule, 1, now),
+            Err(SECOND_NS)
+        );
+
+        let state = apply_rule(Some(state), &rule, 1, now + SECOND_NS).unwrap();
+        assert_eq!(state.tokens_milli, 0);
+    }
+
+    #[test]
+    fn idle_buckets_are_evicted() {
+        let config = RateLimitConfig {
+            per_principal: LimitSet {
+                searches: Some(LimitRule {
+                    burst: 2,
+                    per_minute: 60,
+                    daily: Some(100),
+                }),
+                ..Default::default()
+            },
+            per_collection: LimitSet::default(),
+        };
+        set_limits_config(config.clone()).unwrap();
+        let stored = || LIMITS.with(|l| l.borrow().len());
+
+        for caller in ["alice", "bob", "carol"] {
+            consume(caller, Some("docs"), &[(LimitMetric::Searches, 1)]).unwrap();
+        }
+        assert_eq!(stored(), 3);
+
+        // Buckets still refilling and used today are kept
+        assert_eq!(prune_idle(&config, current_time(), PRUNE_BATCH), 0);
+
+        // A day later they are full again with nothing used: one sweep in
+        // batches of two removes them all
+        let tomorrow = current_time() + DAY_NS;
+        assert_eq!(prune_idle(&config, tomorrow, 2), 2);
+        assert_eq!(prune_idle(&config, tomorrow, 2), 1);
+        assert_eq!(stored(), 0);
+    }
+
+    #[test]
+    fn daily_quota_resets_at_day_boundary() {
+        let rule = LimitRule {
+            burst: 0,
+            per_minute: 0,
+            daily: Some(3),
+        };
+        let now = 10 * DAY_NS + 100;
+
+        let state = apply_rule(None, &rule, 3, now).unwrap();
+        assert_eq!(
+            apply_rule(Some(state.clone()), &rule, 1, now),
+            Err(DAY_NS - 100)
+        );
+
+        let state = apply_rule(Some(state), &rule, 1, 11 * DAY_NS).unwrap();
+        assert_eq!(state.used_today, 1);
+    }
+}
@@ -16,6 +16,22 @@ pub const VECTORS_MEMORY_ID: MemoryId = MemoryId::new(3);
 pub const VECTOR_INDEX_MEMORY_ID: MemoryId = MemoryId::new(4);
 pub const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(5);
 pub const DOCUMENT_INDEX_MEMORY_ID: MemoryId = MemoryId::new(6); 
+// Provider credentials live apart from collection metadata so they never leak via reads
+pub const SECRETS_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const JOBS_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const ACCOUNTS_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const USAGE_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const RATE_LIMITS_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const AUDIT_LOG_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const PROPOSALS_MEMORY_ID: MemoryId = MemoryId::new(14);
+// Original markup of documents whose chunks hold extracted text
+pub const SOURCES_MEMORY_ID: MemoryId = MemoryId::new(15);
+// Multi-part blob uploads in progress
+pub const UPLOADS_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const UPLOAD_PARTS_MEMORY_ID: MemoryId = MemoryId::new(17);
+// Vectors of embedding model migrations in progress, and vectors awaiting deletion
+pub const SHADOW_VECTOR_INDEX_MEMORY_ID: MemoryId = MemoryId::new(18);
+pub const RETIRED_VECTORS_MEMORY_ID: MemoryId = MemoryId::new(19);
 
 // Performance tracking
 // pub const METRICS_MEMORY_ID: MemoryId = MemoryId::new(10);
@@ -64,6 +80,18 @@ fn estimate_used_pages() -> u64 {
             VECTORS_MEMORY_ID,
             VECTOR_INDEX_MEMORY_ID,
             DOCUMENT_INDEX_MEMORY_ID, 
+            SECRETS_MEMORY_ID,
+            JOBS_MEMORY_ID,
+            ACCOUNTS_MEMORY_ID,
+            USAGE_MEMORY_ID,
+            RATE_LIMITS_MEMORY_ID,
+            AUDIT_LOG_MEMORY_ID,
+            PROPOSALS_MEMORY_ID,
+            SOURCES_MEMORY_ID,
+            UPLOADS_MEMORY_ID,
+            UPLOAD_PARTS_MEMORY_ID,
+            SHADOW_VECTOR_INDEX_MEMORY_ID,
+            RETIRED_VECTORS_MEMORY_ID,
         ] {
             total += manager.get(id).size();
         }
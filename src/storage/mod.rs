@@ -1,16 +1,28 @@
+pub mod audit;
+pub mod billing;
+pub mod chunking;
 pub mod collections;
+pub mod config;
 pub mod documents;
+pub mod html;
+pub mod jobs;
+pub mod limits;
 pub mod memory;
+pub mod pdf;
+pub mod proposals;
+pub mod secrets;
+pub mod tokens;
+pub mod uploads;
 pub mod vectors;
 
 pub use collections::{
     create_collection, get_collection, get_collection_with_stats, is_collection_admin,
-    list_collections, list_collections_with_stats,
+    list_collections_with_stats,
 };
 
 pub use documents::{
-    add_document, delete_document, get_chunk_text, get_document, get_document_chunks,
-    get_document_content, get_document_title, list_documents, mark_document_embedded,
+    add_document, delete_document, get_document, get_document_content, get_document_title,
+    list_documents, refresh_document_embedding_state,
 };
 
 pub use vectors::{get_collection_vectors, get_document_vectors, store_vectors_batch};
@@ -18,6 +30,7 @@ pub use vectors::{get_collection_vectors, get_document_vectors, store_vectors_ba
 pub use memory::{get_memory_stats, MemoryStats};
 
 use crate::storage::memory::MemoryType;
+use crate::types::BluebandError;
 use ic_stable_structures::StableBTreeMap;
 use memory::{get_memory, CONFIG_MEMORY_ID};
 use std::cell::RefCell;
@@ -27,7 +40,7 @@ thread_local! {
         StableBTreeMap::init(get_memory(CONFIG_MEMORY_ID))
     );
 }
-pub fn set_config(key: &str, value: String) -> Result<(), String> {
+pub fn set_config(key: &str, value: String) -> Result<(), BluebandError> {
     CONFIG.with(|c| {
         c.borrow_mut().insert(key.to_string(), value);
         Ok(())
@@ -0,0 +1,170 @@
+// storage/pdf.rs
+//
+// Pulls the text of each page out of a PDF with lopdf, in pure Rust so it runs
+// inside the canister. Text comes from the page content streams in drawing
+// order, one line per text object; scanned pages without a text layer come
+// out empty, as there is no OCR.
+
+use crate::types::BluebandError;
+use lopdf::{Document, Object};
+
+/// Text of a PDF, page by page
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExtractedPdf {
+    /// One entry per page in page order, empty for pages without text
+    pub pages: Vec<String>,
+    /// `Title` from the document information dictionary
+    pub title: Option<String>,
+}
+
+/// Extracts the text of every page. Fails on bytes that are not a PDF and on
+/// PDFs that need a password to open.
+pub fn extract_pdf(bytes: &[u8]) -> Result<ExtractedPdf, BluebandError> {
+    let mut document = Document::load_mem(bytes)
+        .map_err(|e| BluebandError::Validation(format!("Could not read PDF: {}", e)))?;
+
+    // Many PDFs are encrypted only to restrict printing or copying, with an
+    // empty user password
+    if document.is_encrypted() && document.decrypt("").is_err() {
+        return Err("Password-protected PDFs are not supported".into());
+    }
+
+    let pages = document
+        .get_pages()
+        .keys()
+        .map(|&page| page_text(&document, page))
+        .collect();
+
+    Ok(ExtractedPdf {
+        pages,
+        title: document_title(&document),
+    })
+}
+
+// A font the extractor can't decode loses its own text, not the whole page
+fn page_text(document: &Document, page: u32) -> String {
+    let raw: String = document
+        .extract_text_chunks(&[page])
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect();
+
+    raw.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn document_title(document: &Document) -> Option<String> {
+    let info = document.trailer.get(b"Info").ok()?;
+    let (_, info) = document.dereference(info).ok()?;
+    let title = info.as_dict().ok()?.get(b"Title").ok()?;
+    let (_, title) = document.dereference(title).ok()?;
+    let title = match title {
+        Object::String(..) => lopdf::decode_text_string(title).ok()?,
+        _ => return None,
+    };
+
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Stream};
+
+    // Builds a PDF with one page per entry, each drawing its lines of text
+    fn build_pdf(pages: &[&[&str]], title: &str) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let resources_id = document.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+
+        let mut kids = Vec::new();
+        for lines in pages {
+            let mut operations = Vec::new();
+            for (i, line) in lines.iter().enumerate() {
+                operations.push(Operation::new("BT", vec![]));
+                operations.push(Operation::new("Tf", vec!["F1".into(), 12.into()]));
+                operations.push(Operation::new(
+                    "Td",
+                    vec![72.into(), (700 - 20 * i as i64).into()],
+                ));
+                operations.push(Operation::new("Tj", vec![Object::string_literal(*line)]));
+                operations.push(Operation::new("ET", vec![]));
+            }
+            let content = Content { operations };
+            let content_id =
+                document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            let page_id = document.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            });
+            kids.push(page_id.into());
+        }
+
+        let count = kids.len() as i64;
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = document.add_object(dictionary! {
+            "Title" => Object::string_literal(title),
+        });
+        document.trailer.set("Root", catalog_id);
+        document.trailer.set("Info", info_id);
+
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn pages_are_extracted_in_order() {
+        let bytes = build_pdf(
+            &[
+                &["Quarterly   report", "Revenue grew."],
+                &[],
+                &["Outlook is stable."],
+            ],
+            "Q3  Report",
+        );
+
+        let extracted = extract_pdf(&bytes).unwrap();
+        assert_eq!(extracted.title.as_deref(), Some("Q3 Report"));
+        assert_eq!(
+            extracted.pages,
+            vec![
+                "Quarterly report\nRevenue grew.".to_string(),
+                String::new(),
+                "Outlook is stable.".to_string(),
+            ]
+        );
+
+        assert!(matches!(
+            extract_pdf(b"not a pdf"),
+            Err(BluebandError::Validation(_))
+        ));
+    }
+}
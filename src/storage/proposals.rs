@@ -0,0 +1,472 @@
+// storage/proposals.rs
+use ic_stable_structures::{StableBTreeMap, Storable};
+use std::cell::RefCell;
+
+use super::memory::{get_memory, MemoryType, PROPOSALS_MEMORY_ID};
+use crate::types::*;
+
+// =============================================================================
+// GLOBAL STORAGE
+// =============================================================================
+
+thread_local! {
+    // Proposals: proposal_id -> Proposal
+    static PROPOSALS: RefCell<StableBTreeMap<String, Proposal, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(PROPOSALS_MEMORY_ID))
+    );
+}
+
+const HOUR_NS: u64 = 3_600 * 1_000_000_000;
+const MAX_OPEN_PROPOSALS: usize = 20;
+// Kept free at creation and approval for the outcome written on execution
+const OUTCOME_RESERVE_BYTES: usize = 1_024;
+const MAX_FAILURE_BYTES: usize = 256;
+
+// =============================================================================
+// POLICY CHECKS
+// =============================================================================
+
+fn needs_approval(collection: &Collection, action: &ProposalAction) -> bool {
+    match action {
+        ProposalAction::UpdateSettings { settings } => {
+            let current = &collection.settings;
+            settings.embedding_model != current.embedding_model
+                || settings.max_documents != current.max_documents
+                || super::collections::outcall_urls(settings)
+                    != super::collections::outcall_urls(current)
+        }
+        _ => true,
+    }
+}
+
+/// Refuses to run `action` directly when the collection's approval policy covers it
+pub fn require_no_approval(
+    collection_id: &str,
+    action: &ProposalAction,
+) -> Result<(), BluebandError> {
+    let collection = super::collections::get_collection(collection_id)
+        .ok_or_else(|| BluebandError::collection_not_found(collection_id))?;
+
+    match &collection.approval_policy {
+        Some(policy) if needs_approval(&collection, action) => Err(format!(
+            "This operation requires {} admin approvals; submit it with propose_collection_action",
+            policy.required_approvals
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+// =============================================================================
+// PROPOSAL LIFECYCLE
+// =============================================================================
+
+pub fn create_proposal(
+    collection_id: &str,
+    action: ProposalAction,
+    proposer: &str,
+) -> Result<Proposal, BluebandError> {
+    let collection = super::collections::get_collection(collection_id)
+        .ok_or_else(|| BluebandError::collection_not_found(collection_id))?;
+    let policy = collection
+        .approval_policy
+        .clone()
+        .ok_or("Collection has no approval policy; call the operation directly")?;
+    if !needs_approval(&collection, &action) {
+        return Err("This operation does not need approval; call it directly".into());
+    }
+    if let ProposalAction::UpdateSettings { settings } = &action {
+        super::collections::require_outcall_url_owner(collection_id, settings, proposer)?;
+    }
+
+    let open = list_collection_proposals(collection_id)
+        .iter()
+        .filter(|p| p.status == ProposalStatus::Open)
+        .count();
+    if open >= MAX_OPEN_PROPOSALS {
+        return Err(BluebandError::StorageFull(format!(
+            "Collection already has {} open proposals",
+            MAX_OPEN_PROPOSALS
+        )));
+    }
+
+    let now = current_time();
+    let sequence = PROPOSALS.with(|p| p.borrow().len());
+    let proposal = Proposal {
+        id: generate_id(
+            "proposal",
+            &format!("{}:{}:{}", collection_id, proposer, sequence),
+        ),
+        collection_id: collection_id.to_string(),
+        action,
+        proposer: proposer.to_string(),
+        approvals: vec![proposer.to_string()],
+        eligible_approvers: admins_of(&collection),
+        required_approvals: policy.required_approvals,
+        created_at: now,
+        expires_at: now.saturating_add(policy.expiry_hours as u64 * HOUR_NS),
+        status: ProposalStatus::Open,
+        executed_at: None,
+    };
+
+    check_size(&proposal)?;
+    save_proposal(&proposal);
+    Ok(proposal)
+}
+
+/// Principals holding an admin role: the genesis admin, owners and editors
+fn admins_of(collection: &Collection) -> Vec<String> {
+    let mut admins = vec![collection.genesis_admin.clone()];
+    for member in &collection.members {
+        if member.role.is_admin() && !admins.contains(&member.principal) {
+            admins.push(member.principal.clone());
+        }
+    }
+    admins
+}
+
+/// Whether `approver` may approve: an admin now, and already one when the
+/// proposal was made, so admins added afterwards can't tip the count
+fn is_eligible(proposal: &Proposal, approver: &str) -> bool {
+    super::collections::is_collection_admin(&proposal.collection_id, approver)
+        && (proposal.eligible_approvers.is_empty()
+            || proposal.eligible_approvers.iter().any(|a| a == approver))
+}
+
+pub fn get_proposal(proposal_id: &str) -> Option<Proposal> {
+    PROPOSALS
+        .with(|p| p.borrow().get(&proposal_id.to_string()))
+        .map(normalize_status)
+}
+
+/// Open proposals past their expiry read as expired
+fn normalize_status(mut proposal: Proposal) -> Proposal {
+    if proposal.status == ProposalStatus::Open && current_time() > proposal.expires_at {
+        proposal.status = ProposalStatus::Expired;
+    }
+    proposal
+}
+
+/// Refuses proposals whose encoded form, with room left for the execution
+/// outcome, would not fit the storage bound
+fn check_size(proposal: &Proposal) -> Result<(), BluebandError> {
+    let max_size = Proposal::BOUND.max_size() as usize - OUTCOME_RESERVE_BYTES;
+    if proposal.to_bytes().len() > max_size {
+        return Err(format!(
+            "Proposal exceeds the maximum encoded size of {} bytes",
+            max_size
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn save_proposal(proposal: &Proposal) {
+    PROPOSALS.with(|p| p.borrow_mut().insert(proposal.id.clone(), proposal.clone()));
+}
+
+fn get_open_proposal(proposal_id: &str) -> Result<Proposal, BluebandError> {
+    let proposal = get_proposal(proposal_id)
+        .ok_or_else(|| BluebandError::not_found("Proposal", proposal_id))?;
+    if proposal.status != ProposalStatus::Open {
+        return Err(format!("Proposal is {:?}", proposal.status).into());
+    }
+    Ok(proposal)
+}
+
+pub fn approve_proposal(proposal_id: &str, approver: &str) -> Result<Proposal, BluebandError> {
+    let mut proposal = get_open_proposal(proposal_id)?;
+    if proposal.approvals.iter().any(|a| a == approver) {
+        return Err("Proposal already approved by this admin".into());
+    }
+    if !is_eligible(&proposal, approver) {
+        return Err(BluebandError::Unauthorized(
+            "Only admins who held their role when the proposal was made can approve it".into(),
+        ));
+    }
+
+    proposal.approvals.push(approver.to_string());
+    check_size(&proposal)?;
+    save_proposal(&proposal);
+    Ok(proposal)
+}
+
+pub fn cancel_proposal(proposal_id: &str, caller: &str) -> Result<Proposal, BluebandError> {
+    let mut proposal = get_open_proposal(proposal_id)?;
+    let genesis_admin = super::collections::get_genesis_admin(&proposal.collection_id);
+    if proposal.proposer != caller && genesis_admin.as_deref() != Some(caller) {
+        return Err("Only the proposer or the genesis admin can cancel a proposal".into());
+    }
+
+    proposal.status = ProposalStatus::Cancelled;
+    save_proposal(&proposal);
+    Ok(proposal)
+}
+
+/// Runs the proposed action once enough eligible admins have approved it.
+/// A proposal runs at most once; if the action fails it is marked Failed.
+pub fn execute_proposal(proposal_id: &str) -> Result<Proposal, BluebandError> {
+    let mut proposal = get_open_proposal(proposal_id)?;
+
+    // Approvals of principals who have since lost their admin role don't count
+    let approvals = proposal
+        .approvals
+        .iter()
+        .filter(|a| is_eligible(&proposal, a))
+        .count();
+    if approvals < proposal.required_approvals as usize {
+        return Err(format!(
+            "Proposal has {} of {} required approvals",
+            approvals, proposal.required_approvals
+        )
+        .into());
+    }
+
+    let result = perform(
+        &proposal.collection_id,
+        proposal.action.clone(),
+        &proposal.proposer,
+    );
+    proposal.status = match &result {
+        Ok(()) => ProposalStatus::Executed,
+        Err(e) => ProposalStatus::Failed(truncate(&e.to_string(), MAX_FAILURE_BYTES)),
+    };
+    proposal.executed_at = Some(current_time());
+
+    // Deleting the collection also removed its proposals
+    if super::collections::collection_exists(&proposal.collection_id) {
+        save_proposal(&proposal);
+    }
+
+    result.map(|_| proposal)
+}
+
+fn perform(
+    collection_id: &str,
+    action: ProposalAction,
+    proposer: &str,
+) -> Result<(), BluebandError> {
+    match action {
+        ProposalAction::DeleteCollection => super::collections::remove_collection(collection_id),
+        ProposalAction::DeleteDocument { document_id } => {
+            require_document(collection_id, &document_id)?;
+            super::documents::delete_document(collection_id, &document_id)
+        }
+        ProposalAction::DeleteDocumentVectors { document_id } => {
+            require_document(collection_id, &document_id)?;
+            super::vectors::unembed_document(collection_id, &document_id)
+        }
+        ProposalAction::ClearCollectionVectors => super::vectors::unembed_collection(collection_id),
+        ProposalAction::DeleteCollectionDocuments => {
+            super::documents::delete_collection_documents(collection_id)
+        }
+        ProposalAction::TransferGenesisAdmin { new_genesis_admin } => {
+            if !super::collections::is_collection_admin(collection_id, &new_genesis_admin) {
+                return Err("New genesis admin must be an existing admin".into());
+            }
+            super::collections::set_genesis_admin(collection_id, &new_genesis_admin)
+        }
+        ProposalAction::UpdateSettings { settings } => {
+            // Secret headers may have been set since the proposal was made
+            super::collections::require_outcall_url_owner(collection_id, &settings, proposer)?;
+            super::collections::replace_settings(collection_id, *settings)
+        }
+        ProposalAction::SetApprovalPolicy { policy } => {
+            super::collections::set_approval_policy(collection_id, policy)
+        }
+        ProposalAction::MigrateEmbeddingModel { embedding_model } => {
+            super::jobs::create_migration_job(collection_id, &embedding_model, proposer).map(|_| ())
+        }
+    }
+}
+
+fn truncate(text: &str, max_bytes: usize) -> String {
+    let mut end = text.len().min(max_bytes);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
+
+fn require_document(collection_id: &str, document_id: &str) -> Result<(), BluebandError> {
+    if !super::documents::document_exists(collection_id, document_id) {
+        return Err(BluebandError::not_found("Document", document_id));
+    }
+    Ok(())
+}
+
+// =============================================================================
+// QUERIES AND CLEANUP
+// =============================================================================
+
+pub fn list_collection_proposals(collection_id: &str) -> Vec<Proposal> {
+    let mut proposals: Vec<Proposal> = PROPOSALS.with(|p| {
+        p.borrow()
+            .iter()
+            .filter(|(_, proposal)| proposal.collection_id == collection_id)
+            .map(|(_, proposal)| normalize_status(proposal))
+            .collect()
+    });
+    proposals.sort_by_key(|proposal| std::cmp::Reverse(proposal.created_at));
+    proposals
+}
+
+pub fn delete_collection_proposals(collection_id: &str) {
+    let proposal_ids: Vec<String> = PROPOSALS.with(|p| {
+        p.borrow()
+            .iter()
+            .filter(|(_, proposal)| proposal.collection_id == collection_id)
+            .map(|(id, _)| id)
+            .collect()
+    });
+
+    PROPOSALS.with(|p| {
+        let mut proposals = p.borrow_mut();
+        for id in proposal_ids {
+            proposals.remove(&id);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::set_test_caller;
+    use candid::Principal;
+
+    #[test]
+    fn destructive_operations_need_enough_approvals() {
+        let owner = Principal::from_slice(&[2]);
+        let editor = Principal::from_slice(&[4]);
+        let stranger = Principal::from_slice(&[3]);
+        let c = "approvals".to_string();
+
+        set_test_caller(owner);
+        crate::create_collection(CreateCollectionRequest {
+            id: c.clone(),
+            name: "Approvals".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        crate::set_collection_member(c.clone(), editor.to_text(), CollectionRole::Editor).unwrap();
+        let policy = ApprovalPolicy {
+            required_approvals: 2,
+            expiry_hours: 24,
+        };
+        crate::set_approval_policy(c.clone(), Some(policy)).unwrap();
+
+        let err = crate::delete_collection(c.clone()).unwrap_err();
+        assert!(err.contains("requires 2 admin approvals"));
+        assert!(crate::remove_collection_member(c.clone(), editor.to_text()).is_err());
+
+        let proposal =
+            crate::propose_collection_action(c.clone(), ProposalAction::DeleteCollection).unwrap();
+        let err = crate::execute_proposal(proposal.id.clone()).unwrap_err();
+        assert!(err.contains("1 of 2"));
+
+        set_test_caller(stranger);
+        assert!(crate::approve_proposal(proposal.id.clone()).is_err());
+
+        // Admins added after the proposal was made don't count
+        let sockpuppet = Principal::from_slice(&[5]);
+        set_test_caller(owner);
+        crate::set_collection_member(c.clone(), sockpuppet.to_text(), CollectionRole::Editor)
+            .unwrap();
+        set_test_caller(sockpuppet);
+        let err = crate::approve_proposal(proposal.id.clone()).unwrap_err();
+        assert!(err.contains("held their role"));
+
+        set_test_caller(editor);
+        crate::approve_proposal(proposal.id.clone()).unwrap();
+        let executed = crate::execute_proposal(proposal.id).unwrap();
+        assert_eq!(executed.status, ProposalStatus::Executed);
+        assert!(!crate::storage::collections::collection_exists(&c));
+
+        let stale = normalize_status(Proposal {
+            status: ProposalStatus::Open,
+            expires_at: 0,
+            ..Default::default()
+        });
+        assert_eq!(stale.status, ProposalStatus::Expired);
+    }
+
+    #[test]
+    fn oversize_proposals_and_gated_vector_deletes_are_refused() {
+        let owner = Principal::from_slice(&[2]);
+        let c = "oversize".to_string();
+        set_test_caller(owner);
+        crate::create_collection(CreateCollectionRequest {
+            id: c.clone(),
+            name: "Oversize".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        let document = super::super::documents::add_document(AddDocumentRequest {
+            collection_id: c.clone(),
+            title: "Doc".to_string(),
+            content: "Some content".to_string(),
+            content_type: None,
+            source_url: None,
+            author: None,
+            tags: None,
+        })
+        .unwrap();
+        super::super::vectors::store_vectors_batch(vec![Vector {
+            id: "vec_gated".to_string(),
+            document_id: document.id,
+            chunk_id: "chunk_0".to_string(),
+            embedding: vec![0.6, 0.8],
+            norm: 1.0,
+            model: "text-embedding-ada-002".to_string(),
+            created_at: 0,
+        }])
+        .unwrap();
+        let editor = Principal::from_slice(&[4]).to_text();
+        crate::set_collection_member(c.clone(), editor, CollectionRole::Editor).unwrap();
+        crate::set_approval_policy(
+            c.clone(),
+            Some(ApprovalPolicy {
+                required_approvals: 2,
+                expiry_hours: 24,
+            }),
+        )
+        .unwrap();
+
+        let err = crate::delete_vector("vec_gated".to_string()).unwrap_err();
+        assert!(err.contains("requires 2 admin approvals"));
+
+        let current = super::super::collections::get_collection(&c)
+            .unwrap()
+            .settings;
+        let mut moved = current.clone();
+        moved.proxy_url = "https://proxy.example.com/".to_string();
+        let mut capped = current.clone();
+        capped.max_documents = Some(1);
+        let mut tuned = current;
+        tuned.auto_embed = !tuned.auto_embed;
+        for settings in [moved, capped] {
+            let err = crate::update_collection_settings(c.clone(), settings).unwrap_err();
+            assert!(err.contains("requires 2 admin approvals"), "{}", err);
+        }
+        crate::update_collection_settings(c.clone(), tuned).unwrap();
+
+        let mut settings = super::super::collections::get_collection(&c)
+            .unwrap()
+            .settings;
+        settings.embedding_model = "text-embedding-3-large".to_string();
+        settings.fallback_proxy_urls = Some(vec!["https://example.com/".repeat(50); 10]);
+        let err = crate::propose_collection_action(
+            c.clone(),
+            ProposalAction::UpdateSettings {
+                settings: Box::new(settings),
+            },
+        )
+        .unwrap_err();
+        assert!(err.contains("maximum encoded size"));
+        assert!(list_collection_proposals(&c).is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+// storage/secrets.rs
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+use super::memory::{get_memory, MemoryType, SECRETS_MEMORY_ID};
+use crate::types::*;
+
+// =============================================================================
+// GLOBAL STORAGE
+// =============================================================================
+
+thread_local! {
+    // Secret headers: collection_id -> Vec<SecretHeader>
+    // Kept out of COLLECTIONS so no read path can return the values
+    static SECRET_HEADERS: RefCell<StableBTreeMap<String, SecretHeaderList, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(SECRETS_MEMORY_ID))
+    );
+}
+
+const MAX_SECRET_HEADERS: usize = 10;
+
+// Headers the canister sets itself on every outcall
+const RESERVED_HEADERS: [&str; 6] = [
+    "content-type",
+    "content-length",
+    "user-agent",
+    "idempotency-key",
+    "host",
+    "transfer-encoding",
+];
+
+// =============================================================================
+// SECRET OPERATIONS
+// =============================================================================
+
+/// Replaces the secret headers of a collection (genesis admin only).
+/// Returns the masked fingerprint that `get_collection` will show.
+pub fn set_secret_headers(
+    collection_id: &str,
+    headers: Vec<SecretHeader>,
+    caller: &str,
+) -> Result<String, BluebandError> {
+    super::collections::require_genesis_admin(collection_id, caller)?;
+    validate_secret_headers(&headers)?;
+
+    let fingerprint = fingerprint_headers(&headers);
+
+    SECRET_HEADERS.with(|s| {
+        s.borrow_mut()
+            .insert(collection_id.to_string(), SecretHeaderList(headers))
+    });
+    super::collections::set_credentials_fingerprint(collection_id, Some(fingerprint.clone()))?;
+
+    Ok(fingerprint)
+}
+
+/// Removes all secret headers of a collection (genesis admin only)
+pub fn clear_secret_headers(collection_id: &str, caller: &str) -> Result<(), BluebandError> {
+    super::collections::require_genesis_admin(collection_id, caller)?;
+
+    remove_collection_secrets(collection_id);
+    super::collections::set_credentials_fingerprint(collection_id, None)
+}
+
+/// Secret headers for outcalls. Internal use only - never expose through the API.
+pub fn get_secret_headers(collection_id: &str) -> Vec<SecretHeader> {
+    SECRET_HEADERS.with(|s| {
+        s.borrow()
+            .get(&collection_id.to_string())
+            .map(|headers| headers.0)
+            .unwrap_or_default()
+    })
+}
+
+/// Secret headers for an outcall to `url`. Only the endpoints configured in
+/// the collection's settings receive them; per-call overrides are sent without.
+pub fn outcall_secret_headers(collection_id: &str, url: &str) -> Vec<SecretHeader> {
+    let configured = super::collections::get_collection(collection_id).is_some_and(|c| {
+        c.settings.proxy_url == url
+            || c.settings
+                .fallback_proxy_urls
+                .is_some_and(|urls| urls.iter().any(|u| u == url))
+    });
+    if !configured {
+        return Vec::new();
+    }
+    get_secret_headers(collection_id)
+}
+
+/// Cleans up secrets when a collection is deleted
+pub fn remove_collection_secrets(collection_id: &str) {
+    SECRET_HEADERS.with(|s| s.borrow_mut().remove(&collection_id.to_string()));
+}
+
+// =============================================================================
+// VALIDATION & MASKING
+// =============================================================================
+
+fn validate_secret_headers(headers: &[SecretHeader]) -> Result<(), String> {
+    if headers.is_empty() {
+        return Err("At least one secret header is required".to_string());
+    }
+
+    if headers.len() > MAX_SECRET_HEADERS {
+        return Err(format!(
+            "Too many secret headers (max {})",
+            MAX_SECRET_HEADERS
+        ));
+    }
+
+    let mut seen = Vec::new();
+    for header in headers {
+        let name_lower = header.name.to_lowercase();
+
+        if header.name.is_empty() || header.name.len() > 64 {
+            return Err("Header name must be 1-64 characters".to_string());
+        }
+
+        if !header
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Header name '{}' must contain only alphanumeric characters, hyphens and underscores",
+                header.name
+            ));
+        }
+
+        if RESERVED_HEADERS.contains(&name_lower.as_str()) {
+            return Err(format!("Header '{}' is set by the canister", header.name));
+        }
+
+        if seen.contains(&name_lower) {
+            return Err(format!("Duplicate header '{}'", header.name));
+        }
+
+        if header.value.is_empty() || header.value.len() > 4096 {
+            return Err(format!(
+                "Value of header '{}' must be 1-4096 characters",
+                header.name
+            ));
+        }
+
+        if header.value.contains('\r') || header.value.contains('\n') {
+            return Err(format!(
+                "Value of header '{}' cannot contain line breaks",
+                header.name
+            ));
+        }
+
+        seen.push(name_lower);
+    }
+
+    Ok(())
+}
+
+/// Header names plus a short hash of the values, e.g. `Authorization [sha256:1f2e3d4c]`
+fn fingerprint_headers(headers: &[SecretHeader]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for header in headers {
+        hasher.update(header.name.to_lowercase().as_bytes());
+        hasher.update(b":");
+        hasher.update(header.value.as_bytes());
+        hasher.update(b"|");
+    }
+    let hash = hasher.finalize();
+
+    let names: Vec<&str> = headers.iter().map(|h| h.name.as_str()).collect();
+    let digest: String = hash[..4].iter().map(|b| format!("{:02x}", b)).collect();
+
+    format!("{} [sha256:{}]", names.join(", "), digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::set_test_caller;
+    use candid::Principal;
+
+    const SECRET: &str = "Bearer sk-secret-value";
+
+    fn owner() -> Principal {
+        Principal::from_slice(&[2])
+    }
+
+    fn setup() -> String {
+        set_test_caller(owner());
+        let collection_id = "secrets_test".to_string();
+        crate::create_collection(CreateCollectionRequest {
+            id: collection_id.clone(),
+            name: "Secrets test".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        collection_id
+    }
+
+    fn headers() -> Vec<SecretHeader> {
+        vec![SecretHeader {
+            name: "Authorization".to_string(),
+            value: SECRET.to_string(),
+        }]
+    }
+
+    #[test]
+    fn only_the_genesis_admin_sets_secret_headers() {
+        let c = setup();
+        let admin = Principal::from_slice(&[3]);
+        crate::add_collection_admin(c.clone(), admin.to_text()).unwrap();
+
+        for principal in [admin, Principal::from_slice(&[4]), Principal::anonymous()] {
+            set_test_caller(principal);
+            assert!(crate::set_collection_secret_headers(c.clone(), headers()).is_err());
+            assert!(crate::clear_collection_secret_headers(c.clone()).is_err());
+        }
+        assert!(get_secret_headers(&c).is_empty());
+
+        set_test_caller(owner());
+        crate::set_collection_secret_headers(c.clone(), headers()).unwrap();
+        assert_eq!(get_secret_headers(&c)[0].value, SECRET);
+    }
+
+    #[test]
+    fn secret_values_are_never_returned_or_audited() {
+        let c = setup();
+        let fingerprint = crate::set_collection_secret_headers(c.clone(), headers()).unwrap();
+        assert!(fingerprint.starts_with("Authorization [sha256:"));
+        // A refused call with the secret in its arguments
+        set_test_caller(Principal::from_slice(&[4]));
+        assert!(crate::set_collection_secret_headers(c.clone(), headers()).is_err());
+        set_test_caller(owner());
+
+        let collection = crate::get_collection(c.clone()).unwrap();
+        assert_eq!(collection.credentials_fingerprint, Some(fingerprint));
+
+        let exposed = [
+            format!("{:?}", collection),
+            format!("{:?}", crate::list_collections()),
+            format!("{:?}", crate::get_collection_with_stats(c.clone())),
+            format!("{:?}", crate::list_collections_with_stats()),
+            format!("{:?}", super::super::audit::query(&AuditQuery::default())),
+        ];
+        for output in exposed {
+            assert!(!output.contains(SECRET), "secret value exposed: {}", output);
+        }
+    }
+
+    #[test]
+    fn deleting_a_collection_removes_its_secret_headers() {
+        let c = setup();
+        crate::set_collection_secret_headers(c.clone(), headers()).unwrap();
+
+        crate::delete_collection(c.clone()).unwrap();
+        assert!(get_secret_headers(&c).is_empty());
+    }
+
+    #[test]
+    fn only_the_genesis_admin_moves_outcalls_while_secrets_are_set() {
+        let c = setup();
+        let editor = Principal::from_slice(&[3]);
+        crate::set_collection_member(c.clone(), editor.to_text(), CollectionRole::Editor).unwrap();
+        let mut settings = crate::get_collection(c.clone()).unwrap().settings;
+        settings.chunk_size = 256;
+
+        // Without secrets, editors manage every setting
+        set_test_caller(editor);
+        crate::update_collection_settings(c.clone(), settings.clone()).unwrap();
+
+        set_test_caller(owner());
+        crate::set_collection_secret_headers(c.clone(), headers()).unwrap();
+
+        set_test_caller(editor);
+        settings.chunk_size = 128;
+        crate::update_collection_settings(c.clone(), settings.clone()).unwrap();
+        let mut moved = settings.clone();
+        moved.fallback_proxy_urls = Some(vec!["https://capture.example.com/v1".to_string()]);
+        let err = crate::update_collection_settings(c.clone(), moved.clone()).unwrap_err();
+        assert!(err.contains("genesis admin"));
+        assert!(outcall_secret_headers(&c, "https://capture.example.com/v1").is_empty());
+
+        set_test_caller(owner());
+        crate::update_collection_settings(c.clone(), moved).unwrap();
+        assert_eq!(
+            outcall_secret_headers(&c, "https://capture.example.com/v1")[0].value,
+            SECRET
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// storage/tokens.rs
+//
+// Exact token counts with the byte-pair encodings OpenAI models use. The
+// vocabularies are compiled into the canister and loaded on first use, once per
+// canister instance. Models from other providers are counted with cl100k,
+// which is close for most modern tokenizers but not exact.
+
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, CoreBPE};
+
+/// BPE vocabulary used to count tokens
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// GPT-3.5, GPT-4 and every OpenAI embedding model
+    Cl100k,
+    /// GPT-4o, GPT-4.1, GPT-5 and the o-series
+    O200k,
+}
+
+const O200K_MODEL_PREFIXES: &[&str] = &[
+    "gpt-4o",
+    "chatgpt-4o",
+    "gpt-4.1",
+    "gpt-4.5",
+    "gpt-5",
+    "o1",
+    "o3",
+    "o4",
+];
+
+/// Encoding of a model, by name. Unknown models get cl100k.
+pub fn encoding_for_model(model: &str) -> Encoding {
+    // Names may carry a provider prefix, as in "openai/gpt-4o"
+    let name = model.rsplit('/').next().unwrap_or(model);
+    if O200K_MODEL_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+    {
+        Encoding::O200k
+    } else {
+        Encoding::Cl100k
+    }
+}
+
+fn bpe(encoding: Encoding) -> &'static CoreBPE {
+    match encoding {
+        Encoding::Cl100k => cl100k_base_singleton(),
+        Encoding::O200k => o200k_base_singleton(),
+    }
+}
+
+pub fn count_tokens(text: &str, encoding: Encoding) -> u32 {
+    bpe(encoding).encode_ordinary(text).len() as u32
+}
+
+/// Byte offset in `text` at which each token starts. A token that ends inside a
+/// multi-byte character is merged with the next, so every offset is a
+/// character boundary.
+pub fn token_starts(text: &str, encoding: Encoding) -> Vec<usize> {
+    let bpe = bpe(encoding);
+    let tokens = bpe.encode_ordinary(text);
+
+    let mut starts = Vec::with_capacity(tokens.len());
+    let mut offset = 0;
+    for bytes in bpe._decode_native_and_split(tokens) {
+        if text.is_char_boundary(offset) && starts.last() != Some(&offset) {
+            starts.push(offset);
+        }
+        offset += bytes.len();
+    }
+    starts
+}
+
+/// Longest prefix of `text` that fits in `max_tokens`
+pub fn truncate_to_tokens(text: &str, max_tokens: u32, encoding: Encoding) -> &str {
+    match token_starts(text, encoding).get(max_tokens as usize) {
+        Some(&end) => &text[..end],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_match_the_reference_encodings() {
+        // Token counts published for these strings by OpenAI's tiktoken
+        assert_eq!(count_tokens("hello world", Encoding::Cl100k), 2);
+        assert_eq!(count_tokens("tiktoken is great!", Encoding::Cl100k), 6);
+        assert_eq!(count_tokens("hello world", Encoding::O200k), 2);
+
+        assert_eq!(
+            encoding_for_model("text-embedding-3-small"),
+            Encoding::Cl100k
+        );
+        assert_eq!(encoding_for_model("openai/gpt-4o-mini"), Encoding::O200k);
+
+        // Starts fall on character boundaries even when a token splits a character
+        let text = "Größere Pizzen 🍕🍕 mit Käse";
+        let starts = token_starts(text, Encoding::Cl100k);
+        assert_eq!(starts[0], 0);
+        assert!(starts.iter().all(|&s| text.is_char_boundary(s)));
+        assert!(starts.windows(2).all(|w| w[0] < w[1]));
+
+        let prefix = truncate_to_tokens(text, 3, Encoding::Cl100k);
+        assert!(count_tokens(prefix, Encoding::Cl100k) <= 3);
+        assert!(text.starts_with(prefix) && prefix.len() < text.len());
+    }
+}
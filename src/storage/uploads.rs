@@ -0,0 +1,223 @@
+// storage/uploads.rs
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+use super::memory::{get_memory, MemoryType, UPLOADS_MEMORY_ID, UPLOAD_PARTS_MEMORY_ID};
+use crate::types::*;
+
+// =============================================================================
+// GLOBAL STORAGE
+// =============================================================================
+
+thread_local! {
+    // Uploads: upload_id -> Upload
+    static UPLOADS: RefCell<StableBTreeMap<String, Upload, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(UPLOADS_MEMORY_ID))
+    );
+
+    // Upload Parts: upload_id::part_index (zero-padded, so keys sort in order) -> bytes
+    static UPLOAD_PARTS: RefCell<StableBTreeMap<String, Vec<u8>, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(UPLOAD_PARTS_MEMORY_ID))
+    );
+}
+
+/// Same cap as inline document content
+pub const MAX_UPLOAD_SIZE: u64 = 10_000_000;
+
+/// Uploads left unfinished this long are dropped when the next one starts
+const UPLOAD_TTL_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Unfinished uploads one principal may have open per collection
+const MAX_OPEN_UPLOADS: usize = 3;
+
+// =============================================================================
+// UPLOAD OPERATIONS
+// =============================================================================
+
+/// Appends `bytes` to an upload, starting a new one when `upload_id` is `None`.
+/// Parts are kept in the order they arrive.
+pub fn append_part(
+    collection_id: &str,
+    upload_id: Option<&str>,
+    owner: &str,
+    bytes: Vec<u8>,
+) -> Result<UploadStatus, BluebandError> {
+    if bytes.is_empty() {
+        return Err("Upload part is empty".into());
+    }
+
+    let mut upload = match upload_id {
+        Some(id) => get_upload(id, collection_id, owner)?,
+        None => {
+            purge_expired_uploads();
+            let open = UPLOADS.with(|u| {
+                u.borrow()
+                    .iter()
+                    .filter(|(_, upload)| {
+                        upload.collection_id == collection_id && upload.owner == owner
+                    })
+                    .count()
+            });
+            if open >= MAX_OPEN_UPLOADS {
+                return Err(BluebandError::StorageFull(format!(
+                    "At most {} uploads can be open per collection; finish or cancel one first",
+                    MAX_OPEN_UPLOADS
+                )));
+            }
+            new_upload(collection_id, owner)
+        }
+    };
+
+    let size = upload.size + bytes.len() as u64;
+    if size > MAX_UPLOAD_SIZE {
+        return Err(BluebandError::Validation(format!(
+            "Upload exceeds {} byte limit",
+            MAX_UPLOAD_SIZE
+        )));
+    }
+
+    UPLOAD_PARTS.with(|p| {
+        p.borrow_mut()
+            .insert(part_key(&upload.id, upload.parts), bytes)
+    });
+    upload.size = size;
+    upload.parts += 1;
+    UPLOADS.with(|u| u.borrow_mut().insert(upload.id.clone(), upload.clone()));
+
+    Ok(UploadStatus {
+        upload_id: upload.id,
+        size: upload.size,
+        parts: upload.parts,
+    })
+}
+
+/// Upload started by `owner` for `collection_id`. Anyone else gets `NotFound`.
+pub fn get_upload(
+    upload_id: &str,
+    collection_id: &str,
+    owner: &str,
+) -> Result<Upload, BluebandError> {
+    UPLOADS
+        .with(|u| u.borrow().get(&upload_id.to_string()))
+        .filter(|upload| upload.collection_id == collection_id && upload.owner == owner)
+        .ok_or_else(|| BluebandError::not_found("Upload", upload_id))
+}
+
+/// Joins the parts of an upload. The upload stays until it is discarded, so
+/// a document that fails to add can be retried from the same upload.
+pub fn read_upload(
+    upload_id: &str,
+    collection_id: &str,
+    owner: &str,
+) -> Result<Vec<u8>, BluebandError> {
+    let upload = get_upload(upload_id, collection_id, owner)?;
+
+    let mut bytes = Vec::with_capacity(upload.size as usize);
+    for index in 0..upload.parts {
+        let part = UPLOAD_PARTS.with(|p| p.borrow().get(&part_key(upload_id, index)));
+        bytes.extend(part.unwrap_or_default());
+    }
+    Ok(bytes)
+}
+
+pub fn discard_upload(
+    upload_id: &str,
+    collection_id: &str,
+    owner: &str,
+) -> Result<(), BluebandError> {
+    let upload = get_upload(upload_id, collection_id, owner)?;
+    remove_upload(&upload);
+    Ok(())
+}
+
+fn new_upload(collection_id: &str, owner: &str) -> Upload {
+    let id = generate_unique_id("upload", &format!("{}::{}", collection_id, owner), |id| {
+        UPLOADS.with(|u| u.borrow().contains_key(&id.to_string()))
+    });
+
+    Upload {
+        id,
+        collection_id: collection_id.to_string(),
+        owner: owner.to_string(),
+        created_at: current_time(),
+        size: 0,
+        parts: 0,
+    }
+}
+
+fn remove_upload(upload: &Upload) {
+    UPLOAD_PARTS.with(|p| {
+        let mut parts = p.borrow_mut();
+        for index in 0..upload.parts {
+            parts.remove(&part_key(&upload.id, index));
+        }
+    });
+    UPLOADS.with(|u| u.borrow_mut().remove(&upload.id));
+}
+
+fn purge_expired_uploads() {
+    let cutoff = current_time().saturating_sub(UPLOAD_TTL_NS);
+    let expired: Vec<Upload> = UPLOADS.with(|u| {
+        u.borrow()
+            .iter()
+            .map(|(_, upload)| upload)
+            .filter(|upload| upload.created_at < cutoff)
+            .collect()
+    });
+    for upload in expired {
+        remove_upload(&upload);
+    }
+}
+
+fn part_key(upload_id: &str, index: u32) -> String {
+    format!("{}::{:08}", upload_id, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::set_test_caller;
+    use candid::Principal;
+
+    #[test]
+    fn parts_are_charged_and_open_uploads_capped() {
+        let c = "uploads".to_string();
+        set_test_caller(Principal::from_slice(&[2]));
+        crate::create_collection(CreateCollectionRequest {
+            id: c.clone(),
+            name: "Uploads".to_string(),
+            description: None,
+            settings: None,
+            visibility: None,
+        })
+        .unwrap();
+        super::super::limits::set_limits_config(RateLimitConfig {
+            per_principal: LimitSet {
+                bytes: Some(LimitRule {
+                    burst: 10,
+                    per_minute: 1,
+                    daily: None,
+                }),
+                ..Default::default()
+            },
+            per_collection: LimitSet::default(),
+        })
+        .unwrap();
+
+        let first = crate::upload_document_part(c.clone(), None, vec![1; 4]).unwrap();
+        crate::upload_document_part(c.clone(), None, vec![1; 2]).unwrap();
+        crate::upload_document_part(c.clone(), None, vec![1; 2]).unwrap();
+        let err = crate::upload_document_part(c.clone(), None, vec![1; 1]).unwrap_err();
+        assert!(err.contains("At most 3 uploads"));
+
+        // Parts are charged before they are checked: 9 of the 10 bytes are spent
+        let err = crate::upload_document_part(c.clone(), Some(first.upload_id.clone()), vec![1; 3])
+            .unwrap_err();
+        assert!(err.contains("limit"), "{}", err);
+        let upload = get_upload(&first.upload_id, &c, &Principal::from_slice(&[2]).to_text());
+        assert_eq!(upload.unwrap().size, 4);
+
+        crate::cancel_upload(c.clone(), first.upload_id).unwrap();
+        crate::upload_document_part(c, None, vec![1; 1]).unwrap();
+    }
+}
@@ -1,5 +1,8 @@
 // storage/vectors.rs
-use super::memory::{get_memory, MemoryType, VECTORS_MEMORY_ID, VECTOR_INDEX_MEMORY_ID};
+use super::memory::{
+    get_memory, MemoryType, RETIRED_VECTORS_MEMORY_ID, SHADOW_VECTOR_INDEX_MEMORY_ID,
+    VECTORS_MEMORY_ID, VECTOR_INDEX_MEMORY_ID,
+};
 use ic_stable_structures::StableBTreeMap;
 use std::cell::RefCell;
 
@@ -19,6 +22,17 @@ thread_local! {
     static VECTOR_INDEX: RefCell<StableBTreeMap<String, StringList, MemoryType>> = RefCell::new(
         StableBTreeMap::init(get_memory(VECTOR_INDEX_MEMORY_ID))
     );
+
+    // Shadow Vector Index: collection_id -> Vec<VectorId> embedded by a model
+    // migration. Stored in VECTORS, but invisible to search until swapped in.
+    static SHADOW_VECTOR_INDEX: RefCell<StableBTreeMap<String, StringList, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(SHADOW_VECTOR_INDEX_MEMORY_ID))
+    );
+
+    // Retired Vectors: collection_id::retired_at -> Vec<VectorId> awaiting deletion
+    static RETIRED_VECTORS: RefCell<StableBTreeMap<String, StringList, MemoryType>> = RefCell::new(
+        StableBTreeMap::init(get_memory(RETIRED_VECTORS_MEMORY_ID))
+    );
 }
 
 // =============================================================================
@@ -29,10 +43,10 @@ pub fn get_vector(vector_id: &str) -> Option<Vector> {
     VECTORS.with(|v| v.borrow().get(&vector_id.to_string()))
 }
 
-pub fn delete_vector(vector_id: &str) -> Result<(), String> {
+pub fn delete_vector(vector_id: &str) -> Result<(), BluebandError> {
     let vector = VECTORS
         .with(|v| v.borrow().get(&vector_id.to_string()))
-        .ok_or_else(|| format!("Vector '{}' not found", vector_id))?;
+        .ok_or_else(|| BluebandError::not_found("Vector", vector_id))?;
 
     let collection_id = extract_collection_id_from_document_id(&vector.document_id)?;
 
@@ -49,7 +63,7 @@ pub fn delete_vector(vector_id: &str) -> Result<(), String> {
                 }
                 Ok(())
             } else {
-                Err(format!("Failed to remove vector '{}'", vector_id))
+                Err(format!("Failed to remove vector '{}'", vector_id).into())
             }
         })
     })
@@ -72,7 +86,7 @@ pub fn get_collection_vectors(collection_id: &str) -> Vec<Vector> {
     })
 }
 
-pub fn delete_document_vectors(document_id: &str) -> Result<(), String> {
+pub fn delete_document_vectors(document_id: &str) -> Result<(), BluebandError> {
     let vector_ids_to_delete: Vec<String> = VECTORS.with(|v| {
         v.borrow()
             .iter()
@@ -95,7 +109,7 @@ pub fn delete_document_vectors(document_id: &str) -> Result<(), String> {
         if let Some(vector) = vector {
             extract_collection_id_from_document_id(&vector.document_id)?
         } else {
-            return Err("Vector not found".to_string());
+            return Err(BluebandError::not_found("Vector", first_vector_id));
         }
     } else {
         return Ok(());
@@ -112,6 +126,13 @@ pub fn delete_document_vectors(document_id: &str) -> Result<(), String> {
                 vector_ids.0.retain(|id| id != vector_id);
             }
 
+            SHADOW_VECTOR_INDEX.with(|si| {
+                let mut shadow = si.borrow_mut();
+                if let Some(mut shadow_ids) = shadow.get(&collection_id) {
+                    shadow_ids.0.retain(|id| !vector_ids_to_delete.contains(id));
+                    shadow.insert(collection_id.clone(), shadow_ids);
+                }
+            });
             index.insert(collection_id, vector_ids);
 
             Ok(())
@@ -119,12 +140,28 @@ pub fn delete_document_vectors(document_id: &str) -> Result<(), String> {
     })
 }
 
-pub fn get_document_vectors(document_id: &str) -> Vec<Vector> {
+/// Deletes a document's vectors and marks its chunks pending, so it can be
+/// embedded again
+pub fn unembed_document(collection_id: &str, document_id: &str) -> Result<(), BluebandError> {
+    delete_document_vectors(document_id)?;
+    super::documents::mark_chunks_pending(collection_id, document_id, None).map(|_| ())
+}
+
+/// Searchable vectors of a document, leaving out those of a migration in
+/// progress and those awaiting deletion
+pub fn get_document_vectors(collection_id: &str, document_id: &str) -> Vec<Vector> {
+    let live: std::collections::HashSet<String> = VECTOR_INDEX.with(|vi| {
+        vi.borrow()
+            .get(&collection_id.to_string())
+            .map(|ids| ids.0.into_iter().collect())
+            .unwrap_or_default()
+    });
+
     VECTORS.with(|v| {
         v.borrow()
             .iter()
-            .filter_map(|(_, vector)| {
-                if vector.document_id == document_id {
+            .filter_map(|(vector_id, vector)| {
+                if vector.document_id == document_id && live.contains(&vector_id) {
                     Some(vector)
                 } else {
                     None
@@ -146,15 +183,37 @@ pub fn init_collection_index(collection_id: &str) {
     });
 }
 
-/// Cleans up vector index when collection is deleted
+/// Cleans up vector index when collection is deleted; the vectors themselves
+/// are deleted in the background
 pub fn cleanup_collection_index(collection_id: &str) {
-    VECTOR_INDEX.with(|vi| vi.borrow_mut().remove(&collection_id.to_string()));
+    if let Some(vector_ids) =
+        VECTOR_INDEX.with(|vi| vi.borrow_mut().remove(&collection_id.to_string()))
+    {
+        retire_vectors(collection_id, vector_ids.0);
+    }
+}
+
+/// Retires every searchable vector of a collection and marks all its chunks
+/// pending, so its documents can be embedded again
+pub fn unembed_collection(collection_id: &str) -> Result<(), BluebandError> {
+    let vector_ids = VECTOR_INDEX.with(|vi| {
+        vi.borrow_mut()
+            .insert(collection_id.to_string(), StringList::new())
+    });
+    retire_vectors(collection_id, vector_ids.unwrap_or_default().0);
+
+    for document in super::documents::list_documents(collection_id) {
+        super::documents::mark_chunks_pending(collection_id, &document.id, None)?;
+    }
+    Ok(())
 }
 
 /// Clears all vectors and vector index
 pub fn clear_vectors() {
     VECTORS.with(|v| v.borrow_mut().clear_new());
     VECTOR_INDEX.with(|idx| idx.borrow_mut().clear_new());
+    SHADOW_VECTOR_INDEX.with(|idx| idx.borrow_mut().clear_new());
+    RETIRED_VECTORS.with(|r| r.borrow_mut().clear_new());
 }
 
 /// Validates and optionally repairs vector index integrity for a specific collection
@@ -230,7 +289,7 @@ pub fn validate_vectors(collection_id: &str, should_repair: bool) -> Vec<String>
 // =============================================================================
 
 /// Stores multiple vectors in batch
-pub fn store_vectors_batch(vectors: Vec<Vector>) -> Result<u32, String> {
+pub fn store_vectors_batch(vectors: Vec<Vector>) -> Result<u32, BluebandError> {
     if vectors.is_empty() {
         return Ok(0);
     }
@@ -238,13 +297,10 @@ pub fn store_vectors_batch(vectors: Vec<Vector>) -> Result<u32, String> {
     // Validate ALL vectors before storing ANY (fail-fast)
     for (i, vector) in vectors.iter().enumerate() {
         if vector.embedding.is_empty() {
-            return Err(format!("Vector at index {} has empty embedding", i));
+            return Err(format!("Vector at index {} has empty embedding", i).into());
         }
         if vector.norm <= 0.0 || !vector.norm.is_finite() {
-            return Err(format!(
-                "Vector at index {} has invalid norm: {}",
-                i, vector.norm
-            ));
+            return Err(format!("Vector at index {} has invalid norm: {}", i, vector.norm).into());
         }
     }
 
@@ -266,7 +322,7 @@ pub fn store_vectors_batch(vectors: Vec<Vector>) -> Result<u32, String> {
     for (collection_id, collection_vectors) in vectors_by_collection {
         // Verify collection exists
         if !super::collections::collection_exists(&collection_id) {
-            return Err(format!("Collection '{}' not found", collection_id));
+            return Err(BluebandError::collection_not_found(&collection_id));
         }
 
         let stored_count: Result<u32, String> = VECTORS.with(|v| {
@@ -305,14 +361,14 @@ pub fn store_vectors_batch(vectors: Vec<Vector>) -> Result<u32, String> {
 }
 
 /// Deletes multiple vectors in batch
-pub fn delete_vectors_batch(vector_ids: Vec<String>) -> Result<u32, String> {
+pub fn delete_vectors_batch(vector_ids: Vec<String>) -> Result<u32, BluebandError> {
     let mut deleted_count = 0u32;
     let mut errors = Vec::new();
 
     for vector_id in vector_ids {
         match delete_vector(&vector_id) {
             Ok(()) => deleted_count += 1,
-            Err(e) => errors.push(e),
+            Err(e) => errors.push(e.to_string()),
         }
     }
 
@@ -321,12 +377,121 @@ pub fn delete_vectors_batch(vector_ids: Vec<String>) -> Result<u32, String> {
             "Failed to delete {} vectors: {}",
             errors.len(),
             errors.join("; ")
-        ));
+        )
+        .into());
     }
 
     Ok(deleted_count)
 }
 
+// =============================================================================
+// MODEL MIGRATION (Shadow vector sets)
+// =============================================================================
+
+/// Stores vectors of a collection in its shadow set, where search does not see them
+pub fn store_shadow_vectors(
+    collection_id: &str,
+    vectors: Vec<Vector>,
+) -> Result<u32, BluebandError> {
+    for (i, vector) in vectors.iter().enumerate() {
+        if vector.embedding.is_empty() {
+            return Err(format!("Vector at index {} has empty embedding", i).into());
+        }
+        if vector.norm <= 0.0 || !vector.norm.is_finite() {
+            return Err(format!("Vector at index {} has invalid norm: {}", i, vector.norm).into());
+        }
+    }
+
+    VECTORS.with(|v| {
+        SHADOW_VECTOR_INDEX.with(|si| {
+            let mut vectors_map = v.borrow_mut();
+            let mut shadow = si.borrow_mut();
+            let mut vector_ids = shadow.get(&collection_id.to_string()).unwrap_or_default();
+
+            let count = vectors.len() as u32;
+            for vector in vectors {
+                if !vectors_map.contains_key(&vector.id) {
+                    vector_ids.0.push(vector.id.clone());
+                }
+                vectors_map.insert(vector.id.clone(), vector);
+            }
+
+            shadow.insert(collection_id.to_string(), vector_ids);
+            Ok(count)
+        })
+    })
+}
+
+/// Makes the shadow set of a collection its searchable set and retires the
+/// vectors it replaces
+pub fn swap_in_shadow_vectors(collection_id: &str) {
+    let shadow_ids = SHADOW_VECTOR_INDEX
+        .with(|si| si.borrow_mut().remove(&collection_id.to_string()))
+        .unwrap_or_default();
+    let live_ids = VECTOR_INDEX.with(|vi| {
+        vi.borrow_mut()
+            .insert(collection_id.to_string(), shadow_ids)
+    });
+
+    if let Some(live_ids) = live_ids {
+        retire_vectors(collection_id, live_ids.0);
+    }
+}
+
+/// Drops the shadow set of a collection, for a migration that will not complete
+pub fn discard_shadow_vectors(collection_id: &str) {
+    if let Some(shadow_ids) =
+        SHADOW_VECTOR_INDEX.with(|si| si.borrow_mut().remove(&collection_id.to_string()))
+    {
+        retire_vectors(collection_id, shadow_ids.0);
+    }
+}
+
+fn retire_vectors(collection_id: &str, vector_ids: Vec<String>) {
+    if vector_ids.is_empty() {
+        return;
+    }
+
+    // Time does not move within a message, so retiring twice in one call
+    // appends to the same entry
+    let key = format!("{}::{:020}", collection_id, current_time());
+    RETIRED_VECTORS.with(|r| {
+        let mut retired = r.borrow_mut();
+        let mut ids = retired.get(&key).unwrap_or_default();
+        ids.0.extend(vector_ids);
+        retired.insert(key, ids);
+    });
+}
+
+pub fn has_retired_vectors() -> bool {
+    RETIRED_VECTORS.with(|r| !r.borrow().is_empty())
+}
+
+/// Deletes up to `max` retired vectors. Returns how many were deleted.
+pub fn delete_retired_vectors(max: usize) -> usize {
+    let Some((key, mut ids)) = RETIRED_VECTORS.with(|r| r.borrow().first_key_value()) else {
+        return 0;
+    };
+
+    let batch: Vec<String> = ids.0.drain(..max.min(ids.0.len())).collect();
+    VECTORS.with(|v| {
+        let mut vectors = v.borrow_mut();
+        for vector_id in &batch {
+            vectors.remove(vector_id);
+        }
+    });
+
+    RETIRED_VECTORS.with(|r| {
+        let mut retired = r.borrow_mut();
+        if ids.0.is_empty() {
+            retired.remove(&key);
+        } else {
+            retired.insert(key, ids);
+        }
+    });
+    batch.len()
+}
+
 // =============================================================================
 // STORAGE FILTERING (For Compute Layer)
 // =============================================================================
@@ -370,16 +535,13 @@ pub fn get_collection_embedding_dimensions(collection_id: &str) -> Option<u32> {
 // =============================================================================
 
 /// Extracts collection_id from document_id by checking all collections
-fn extract_collection_id_from_document_id(document_id: &str) -> Result<String, String> {
+pub fn extract_collection_id_from_document_id(document_id: &str) -> Result<String, BluebandError> {
     for collection in super::collections::list_collections() {
         if super::documents::document_exists(&collection.id, document_id) {
             return Ok(collection.id);
         }
     }
 
-    Err(format!(
-        "Could not determine collection for document '{}'",
-        document_id
-    ))
+    Err(BluebandError::not_found("Document", document_id))
 }
 
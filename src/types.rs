@@ -14,6 +14,124 @@ pub type DocumentId = String;
 pub type ChunkId = String;
 pub type VectorId = String;
 
+// =============================================================================
+// ERROR TYPES
+// =============================================================================
+
+/// Error returned by the `_v2` endpoints. The v1 endpoints return its text,
+/// which is the message they returned before the typed error existed.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum BluebandError {
+    NotFound {
+        resource: String,
+        id: String,
+    },
+    Unauthorized(String),
+    Validation(String),
+    /// Over a rate limit or daily quota
+    QuotaExceeded(RateLimitExceeded),
+    /// The collection's prepaid balance can't cover an outcall
+    InsufficientBalance {
+        collection_id: CollectionId,
+        available: u64,
+        required: u64,
+    },
+    /// `retryable` failures (timeouts, HTTP 429 and 5xx) may succeed if the call is repeated
+    OutcallFailed {
+        message: String,
+        retryable: bool,
+    },
+    DimensionMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// A fixed capacity, such as a collection's document limit, is used up
+    StorageFull(String),
+}
+
+impl BluebandError {
+    pub fn not_found(resource: &str, id: &str) -> Self {
+        BluebandError::NotFound {
+            resource: resource.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    pub fn collection_not_found(collection_id: &str) -> Self {
+        Self::not_found("Collection", collection_id)
+    }
+
+    /// A provider answered, but not with what was asked for; repeating the
+    /// call won't help
+    pub fn provider_error(message: String) -> Self {
+        BluebandError::OutcallFailed {
+            message,
+            retryable: false,
+        }
+    }
+
+    /// Prefixes the message of message-carrying errors with `context`
+    pub fn with_context(self, context: &str) -> Self {
+        let prefix = |message: String| format!("{}: {}", context, message);
+        match self {
+            BluebandError::Unauthorized(message) => BluebandError::Unauthorized(prefix(message)),
+            BluebandError::Validation(message) => BluebandError::Validation(prefix(message)),
+            BluebandError::StorageFull(message) => BluebandError::StorageFull(prefix(message)),
+            BluebandError::OutcallFailed { message, retryable } => BluebandError::OutcallFailed {
+                message: prefix(message),
+                retryable,
+            },
+            other => other,
+        }
+    }
+}
+
+impl std::fmt::Display for BluebandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BluebandError::NotFound { resource, id } => write!(f, "{} '{}' not found", resource, id),
+            BluebandError::Unauthorized(message)
+            | BluebandError::Validation(message)
+            | BluebandError::StorageFull(message) => write!(f, "{}", message),
+            BluebandError::QuotaExceeded(exceeded) => write!(f, "{}", exceeded),
+            BluebandError::InsufficientBalance {
+                collection_id,
+                available,
+                required,
+            } => write!(
+                f,
+                "Insufficient balance for collection '{}': {} cycles available, {} required. Top up with wallet_receive_for_collection",
+                collection_id, available, required
+            ),
+            BluebandError::OutcallFailed { message, .. } => write!(f, "{}", message),
+            BluebandError::DimensionMismatch { expected, actual } => write!(
+                f,
+                "Dimension mismatch: expected {}, got {}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// Untyped errors from validation helpers
+impl From<String> for BluebandError {
+    fn from(message: String) -> Self {
+        BluebandError::Validation(message)
+    }
+}
+
+impl From<&str> for BluebandError {
+    fn from(message: &str) -> Self {
+        BluebandError::Validation(message.to_string())
+    }
+}
+
+impl From<BluebandError> for String {
+    fn from(error: BluebandError) -> Self {
+        error.to_string()
+    }
+}
+
 // =============================================================================
 // DOCUMENT TYPES
 // =============================================================================
@@ -37,10 +155,46 @@ pub struct DocumentMetadata {
     pub timestamp: u64,
     pub total_chunks: u32,
     pub size: u64,
+    /// True only once every chunk is embedded
     pub is_embedded: bool,
     pub checksum: String,
     pub author: Option<String>,
     pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub embedding_state: DocumentEmbeddingState,
+    #[serde(default)]
+    pub embedded_chunks: u32,
+    /// Title found in the content itself, such as an HTML `<title>`
+    #[serde(default)]
+    pub source_title: Option<String>,
+    /// `<link rel="canonical">` of an HTML document
+    #[serde(default)]
+    pub canonical_url: Option<String>,
+}
+
+/// Content of a document as it was added
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DocumentSource {
+    pub content_type: ContentType,
+    pub content: String,
+    /// Text the chunks were cut from, when it differs from `content`.
+    /// Chunk offsets point into this text.
+    pub extracted_text: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum DocumentEmbeddingState {
+    #[default]
+    NotEmbedded,
+    PartiallyEmbedded,
+    Embedded,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ChunkEmbeddingStatus {
+    Pending,
+    Embedded,
+    Failed(String),
 }
 
 #[derive(CandidType, Default, Clone, Debug, Serialize, Deserialize)]
@@ -52,6 +206,24 @@ pub struct SemanticChunk {
     pub char_start: u64,
     pub char_end: u64,
     pub token_count: Option<u32>,
+    /// `None` on chunks stored before per-chunk tracking: follows the document state
+    #[serde(default)]
+    pub embedding_status: Option<ChunkEmbeddingStatus>,
+    /// Headings of the Markdown section the chunk belongs to, outermost first
+    #[serde(default)]
+    pub heading_path: Option<Vec<String>>,
+    /// 1-based PDF page the chunk was cut from
+    #[serde(default)]
+    pub page: Option<u32>,
+}
+
+/// Result of embedding the missing chunks of one document
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ChunkEmbeddingReport {
+    pub embedded_chunks: u32,
+    pub failed_chunks: u32,
+    pub pending_chunks: u32,
+    pub error: Option<BluebandError>,
 }
 
 // =============================================================================
@@ -76,6 +248,10 @@ pub struct VectorMatch {
     pub chunk_id: ChunkId,
     pub document_title: Option<String>,
     pub chunk_text: Option<String>,
+    #[serde(default)]
+    pub heading_path: Option<Vec<String>>,
+    #[serde(default)]
+    pub page: Option<u32>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -97,8 +273,100 @@ pub struct Collection {
     pub created_at: u64,
     pub updated_at: u64,
     pub genesis_admin: String,
+    /// Principals with the Owner or Editor role, kept in sync with `members`
     pub admins: Vec<String>,
     pub settings: CollectionSettings,
+    /// Masked fingerprint of the provider secret headers, never the values
+    #[serde(default)]
+    pub credentials_fingerprint: Option<String>,
+    #[serde(default)]
+    pub visibility: CollectionVisibility,
+    /// Principals with the Searcher role, kept in sync with `members`
+    #[serde(default)]
+    pub readers: Vec<String>,
+    /// Role of every principal with access; the genesis admin is always an Owner
+    #[serde(default)]
+    pub members: Vec<CollectionMember>,
+    /// When set, destructive and sensitive operations go through proposals
+    #[serde(default)]
+    pub approval_policy: Option<ApprovalPolicy>,
+    /// Principal that created the collection. Empty on collections stored
+    /// before it was recorded; those count for their genesis admin.
+    #[serde(default)]
+    pub created_by: String,
+}
+
+/// Who can read a collection and find it through `list_collections`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum CollectionVisibility {
+    /// Readable and listed for everyone
+    #[default]
+    Public,
+    /// Readable by anyone who knows its id, listed only for admins and readers
+    Unlisted,
+    /// Readable and listed only for admins and readers
+    Private,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CollectionMember {
+    pub principal: String,
+    pub role: CollectionRole,
+}
+
+/// Named collection roles, each granting a fixed set of permissions
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CollectionRole {
+    Owner,
+    Editor,
+    Ingestor,
+    Searcher,
+    Auditor,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum CollectionPermission {
+    ManageMembers,
+    ManageSettings,
+    WriteDocuments,
+    TriggerEmbeddings,
+    ReadContent,
+    ViewUsage,
+}
+
+impl CollectionRole {
+    pub fn permissions(&self) -> &'static [CollectionPermission] {
+        use CollectionPermission::*;
+        match self {
+            CollectionRole::Owner => &[
+                ManageMembers,
+                ManageSettings,
+                WriteDocuments,
+                TriggerEmbeddings,
+                ReadContent,
+                ViewUsage,
+            ],
+            CollectionRole::Editor => &[
+                ManageSettings,
+                WriteDocuments,
+                TriggerEmbeddings,
+                ReadContent,
+                ViewUsage,
+            ],
+            CollectionRole::Ingestor => &[WriteDocuments, TriggerEmbeddings, ReadContent],
+            CollectionRole::Searcher => &[ReadContent],
+            CollectionRole::Auditor => &[ViewUsage],
+        }
+    }
+
+    pub fn grants(&self, permission: CollectionPermission) -> bool {
+        self.permissions().contains(&permission)
+    }
+
+    /// Roles reported through the legacy `admins` list
+    pub fn is_admin(&self) -> bool {
+        matches!(self, CollectionRole::Owner | CollectionRole::Editor)
+    }
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -119,10 +387,113 @@ pub struct CollectionWithStats {
 pub struct CollectionSettings {
     pub embedding_model: String,
     pub proxy_url: String,
+    /// Characters per chunk, or tokens with the `Token` strategy
     pub chunk_size: u32,
+    /// Repeated between consecutive chunks, in the same unit as `chunk_size`
     pub chunk_overlap: u32,
     pub max_documents: Option<u32>,
     pub auto_embed: bool,
+    /// Tried in order when `proxy_url` fails with a retryable error
+    #[serde(default)]
+    pub fallback_proxy_urls: Option<Vec<String>>,
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
+    /// Re-sorts search candidates with a cross-encoder before they are returned
+    #[serde(default)]
+    pub rerank: Option<RerankConfig>,
+    /// Chat model and prompt used by `ask`
+    #[serde(default)]
+    pub ask: Option<AskConfig>,
+    /// How documents are cut into chunks (default: `Fixed`)
+    #[serde(default)]
+    pub chunking_strategy: Option<ChunkingStrategy>,
+    /// Prefix each Markdown chunk with its heading path when embedding it (default: false)
+    #[serde(default)]
+    pub embed_heading_path: Option<bool>,
+    /// Tokens the embedding model accepts per text. Required to enforce a limit
+    /// on custom models; overrides the known limit of OpenAI models.
+    #[serde(default)]
+    pub max_input_tokens: Option<u32>,
+}
+
+/// Chunks never cut through the boundaries a strategy keeps unless a single
+/// sentence, paragraph or word is larger than `chunk_size` on its own
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum ChunkingStrategy {
+    /// Windows of `chunk_size` characters, cut anywhere
+    #[default]
+    Fixed,
+    /// Whole sentences packed up to `chunk_size` characters
+    Sentence,
+    /// Whole paragraphs (separated by blank lines) packed up to `chunk_size` characters
+    Paragraph,
+    /// Paragraphs, then lines, sentences and words, whichever is the largest that fits
+    Recursive,
+    /// Whole words packed up to `chunk_size` tokens of the embedding model
+    Token,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RetryPolicy {
+    /// Extra rounds over all endpoints after the first attempt
+    pub max_retries: u32,
+    /// Not applied: a call cannot sleep between retries (see `send_with_failover`)
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RerankConfig {
+    /// Rerank endpoint, checked against the proxy host allowlist
+    pub url: String,
+    /// Model name sent with the request; TEI servers ignore it
+    pub model: Option<String>,
+    pub format: RerankFormat,
+    /// Cosine candidates sent to the reranker (default: 20, max: 100)
+    pub top_n: Option<u32>,
+    /// Return the cosine order instead of an error when the outcall fails
+    #[serde(default)]
+    pub fallback_on_error: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AskConfig {
+    /// OpenAI-compatible chat-completions endpoint, checked against the proxy host allowlist
+    pub chat_url: String,
+    pub model: String,
+    pub system_prompt: Option<String>,
+    /// User message with `{context}` and `{question}` placeholders
+    pub prompt_template: Option<String>,
+    /// Estimated tokens of retrieved context put in the prompt (default: 3000)
+    pub max_context_tokens: Option<u32>,
+    /// Completion limit sent to the model (default: 512)
+    pub max_answer_tokens: Option<u32>,
+}
+
+/// Request/response shape of the rerank endpoint
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RerankFormat {
+    /// `{query, documents, top_n}` -> `{results: [{index, relevance_score}]}`, also used by Jina
+    Cohere,
+    /// Text Embeddings Inference: `{query, texts}` -> `[{index, score}]`
+    Tei,
+}
+
+/// Header injected into embedding outcalls for a collection (e.g. `Authorization`)
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct SecretHeader {
+    pub name: String,
+    pub value: String,
+}
+
+// Values are secrets: keep them out of debug output and logs
+impl std::fmt::Debug for SecretHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretHeader")
+            .field("name", &self.name)
+            .field("value", &"***")
+            .finish()
+    }
 }
 
 // =============================================================================
@@ -135,6 +506,8 @@ pub struct CreateCollectionRequest {
     pub name: String,
     pub description: Option<String>,
     pub settings: Option<CollectionSettings>,
+    #[serde(default)]
+    pub visibility: Option<CollectionVisibility>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -148,6 +521,22 @@ pub struct AddDocumentRequest {
     pub tags: Option<Vec<String>>,
 }
 
+/// Document given as bytes, either inline or as a finished multi-part upload.
+/// PDFs have their text extracted; text types must be UTF-8.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AddBlobDocumentRequest {
+    pub collection_id: CollectionId,
+    /// May be empty when the file names itself (PDF `Title` or HTML `<title>`)
+    pub title: String,
+    pub content_type: ContentType,
+    /// Set exactly one of `content` and `upload_id`
+    pub content: Option<Vec<u8>>,
+    pub upload_id: Option<String>,
+    pub source_url: Option<String>,
+    pub author: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct QueryRequest {
     pub collection_id: CollectionId,
@@ -164,6 +553,18 @@ pub struct SearchRequest {
     pub min_score: Option<f64>,
     pub filter: Option<String>,
     pub use_approximate: Option<bool>,
+    /// Include matches from partially embedded documents (default: true)
+    pub include_partial: Option<bool>,
+    /// Overrides the collection's rerank setting: `false` skips the stage,
+    /// `true` fails if the collection has no rerank endpoint
+    #[serde(default)]
+    pub rerank: Option<bool>,
+    /// Candidates sent to the reranker, overriding the collection's `top_n`
+    #[serde(default)]
+    pub rerank_top_n: Option<u32>,
+    /// Neighbouring chunks added on each side of every match (default: 0, max: 10)
+    #[serde(default)]
+    pub context_window: Option<u32>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -171,6 +572,47 @@ pub struct SearchResponse {
     pub results: Vec<MemorySearchResult>,
 }
 
+/// Per-call retrieval and generation options for `ask`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AskOptions {
+    /// Chunks retrieved for the prompt (default: 5)
+    pub limit: Option<u32>,
+    pub min_score: Option<f64>,
+    /// Only retrieve chunks of this document
+    pub filter: Option<String>,
+    pub rerank: Option<bool>,
+    /// Neighbouring chunks added on each side of every retrieved chunk
+    pub context_window: Option<u32>,
+    /// Overrides the collection's `max_context_tokens`
+    pub max_context_tokens: Option<u32>,
+    /// Sampling temperature (default: 0)
+    pub temperature: Option<f32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AskResponse {
+    pub answer: String,
+    /// Context passages in prompt order; `[n]` in the answer refers to `index` n
+    pub citations: Vec<Citation>,
+    pub model: String,
+    pub usage_tokens: Option<u32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Citation {
+    pub index: u32,
+    pub document_id: DocumentId,
+    pub chunk_id: ChunkId,
+    pub document_title: Option<String>,
+    pub source_url: Option<String>,
+    pub score: f64,
+    /// Whether the answer refers to this passage
+    pub cited: bool,
+    /// PDF page of the passage's chunk (its best match, with a context window)
+    #[serde(default)]
+    pub page: Option<u32>,
+}
+
 // =============================================================================
 // STABLE STORAGE IMPLEMENTATIONS - BOUNDED
 // =============================================================================
@@ -284,6 +726,24 @@ impl Storable for ChunkList {
     }
 }
 
+#[derive(CandidType, Default, Clone, Debug, Serialize, Deserialize)]
+pub struct SecretHeaderList(pub Vec<SecretHeader>);
+
+impl Storable for SecretHeaderList {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 65_536, // 64KB
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(&self.0).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        Self(from_slice(&bytes).unwrap_or_default())
+    }
+}
+
 // =============================================================================
 // DEFAULT IMPLEMENTATIONS
 // =============================================================================
@@ -297,6 +757,23 @@ impl Default for CollectionSettings {
             chunk_overlap: 64,
             max_documents: None,
             auto_embed: true,
+            fallback_proxy_urls: None,
+            retry_policy: None,
+            rerank: None,
+            ask: None,
+            chunking_strategy: None,
+            embed_heading_path: None,
+            max_input_tokens: None,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
         }
     }
 }
@@ -311,10 +788,27 @@ impl Default for ContentType {
 // UTILITY FUNCTIONS
 // =============================================================================
 
+#[cfg(not(test))]
 pub fn current_time() -> u64 {
     ic_cdk::api::time()
 }
 
+#[cfg(test)]
+thread_local! {
+    static TEST_CLOCK: std::cell::Cell<u64> = const { std::cell::Cell::new(1_700_000_000_000_000_000) };
+}
+
+/// Manual clock for native unit tests, where the system API is unavailable
+#[cfg(test)]
+pub fn current_time() -> u64 {
+    TEST_CLOCK.with(|c| c.get())
+}
+
+#[cfg(test)]
+pub fn advance_test_clock(nanos: u64) {
+    TEST_CLOCK.with(|c| c.set(c.get() + nanos));
+}
+
 pub fn generate_id(prefix: &str, content: &str) -> String {
     use sha2::{Digest, Sha256};
     let mut hasher = Sha256::new();
@@ -330,6 +824,16 @@ pub fn generate_id(prefix: &str, content: &str) -> String {
     )
 }
 
+/// An id from `generate_id` that `taken` reports as free. Ids are time-based,
+/// and time does not move within a message, so records created in the same
+/// call are told apart by a counter appended to `content`.
+pub fn generate_unique_id(prefix: &str, content: &str, taken: impl Fn(&str) -> bool) -> String {
+    (0u32..)
+        .map(|n| generate_id(prefix, &format!("{}::{}", content, n)))
+        .find(|id| !taken(id))
+        .unwrap_or_default()
+}
+
 pub fn calculate_vector_norm(embedding: &[f32]) -> f32 {
     embedding.iter().map(|x| x * x).sum::<f32>().sqrt()
 }
@@ -356,6 +860,110 @@ pub fn validate_collection_id(id: &str) -> Result<(), String> {
     Ok(())
 }
 
+// Settings are stored inside the 8KB collection record
+const MAX_SETTINGS_URL_BYTES: usize = 256;
+const MAX_SETTINGS_MODEL_BYTES: usize = 128;
+const MAX_PROMPT_BYTES: usize = 1_000;
+const MAX_ENCODED_SETTINGS_BYTES: usize = 3_072;
+
+fn check_length(field: &str, value: &str, max_bytes: usize) -> Result<(), String> {
+    if value.len() > max_bytes {
+        return Err(format!("{} exceeds {} bytes", field, max_bytes));
+    }
+    Ok(())
+}
+
+pub fn validate_collection_settings(
+    settings: &CollectionSettings,
+    outcalls: &OutcallConfig,
+) -> Result<(), String> {
+    check_length("Proxy URL", &settings.proxy_url, MAX_SETTINGS_URL_BYTES)?;
+    check_length(
+        "Embedding model",
+        &settings.embedding_model,
+        MAX_SETTINGS_MODEL_BYTES,
+    )?;
+
+    if let Some(fallback_urls) = &settings.fallback_proxy_urls {
+        if fallback_urls.len() > 5 {
+            return Err("At most 5 fallback proxy URLs are allowed".to_string());
+        }
+
+        for url in fallback_urls {
+            check_length("Fallback proxy URL", url, MAX_SETTINGS_URL_BYTES)?;
+            if !url.starts_with("https://") {
+                return Err(format!("Fallback proxy URL '{}' must use HTTPS", url));
+            }
+        }
+    }
+
+    if let Some(rerank) = &settings.rerank {
+        check_length("Rerank URL", &rerank.url, MAX_SETTINGS_URL_BYTES)?;
+        if let Some(model) = &rerank.model {
+            check_length("Rerank model", model, MAX_SETTINGS_MODEL_BYTES)?;
+        }
+    }
+
+    if let Some(ask) = &settings.ask {
+        check_length("Chat URL", &ask.chat_url, MAX_SETTINGS_URL_BYTES)?;
+        check_length("Chat model", &ask.model, MAX_SETTINGS_MODEL_BYTES)?;
+        if let Some(system_prompt) = &ask.system_prompt {
+            check_length("System prompt", system_prompt, MAX_PROMPT_BYTES)?;
+        }
+        if let Some(template) = &ask.prompt_template {
+            check_length("Prompt template", template, MAX_PROMPT_BYTES)?;
+            if !template.contains("{context}") || !template.contains("{question}") {
+                return Err(
+                    "Prompt template must contain {context} and {question} placeholders"
+                        .to_string(),
+                );
+            }
+        }
+    }
+
+    if let Some(policy) = &settings.retry_policy {
+        if policy.max_retries > 5 {
+            return Err("Retry policy allows at most 5 retries".to_string());
+        }
+
+        if policy.initial_backoff_ms > policy.max_backoff_ms || policy.max_backoff_ms > 60_000 {
+            return Err(
+                "Retry backoff must satisfy initial <= max <= 60000 milliseconds".to_string(),
+            );
+        }
+    }
+
+    // Every chunk of a token-sized strategy must be embeddable on its own. A
+    // token is at least one character, so the text limit caps the size too.
+    if settings.chunking_strategy == Some(ChunkingStrategy::Token) {
+        let max_tokens = settings
+            .max_input_tokens
+            .or_else(|| EmbeddingModel::from_name(&settings.embedding_model).max_input_tokens());
+        if let Some(max_tokens) = max_tokens.filter(|max| settings.chunk_size > *max) {
+            return Err(format!(
+                "Chunk size of {} tokens exceeds the embedding model's limit of {}",
+                settings.chunk_size, max_tokens
+            ));
+        }
+        if settings.chunk_size > outcalls.max_chars_per_text {
+            return Err(format!(
+                "Chunk size of {} tokens exceeds the outcall limit of {} characters per text",
+                settings.chunk_size, outcalls.max_chars_per_text
+            ));
+        }
+    }
+
+    // Escaping can still grow text within the field limits
+    if to_vec(settings).map_or(0, |bytes| bytes.len()) > MAX_ENCODED_SETTINGS_BYTES {
+        return Err(format!(
+            "Settings exceed {} bytes once encoded",
+            MAX_ENCODED_SETTINGS_BYTES
+        ));
+    }
+
+    Ok(())
+}
+
 pub fn validate_document_content(content: &str) -> Result<(), String> {
     if content.is_empty() {
         return Err("Document content cannot be empty".to_string());
@@ -375,6 +983,20 @@ pub struct MemorySearchResult {
     pub chunk_id: ChunkId,
     pub score: f64,
     pub text: String,
+    /// Offsets of `text` in the document, set when neighbouring chunks were requested
+    #[serde(default)]
+    pub char_start: Option<u64>,
+    #[serde(default)]
+    pub char_end: Option<u64>,
+    /// Chunks merged into `text`, in document order
+    #[serde(default)]
+    pub context_chunk_ids: Option<Vec<ChunkId>>,
+    /// Markdown headings above the matching chunk, outermost first
+    #[serde(default)]
+    pub heading_path: Option<Vec<String>>,
+    /// PDF page of the matching chunk
+    #[serde(default)]
+    pub page: Option<u32>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -447,6 +1069,26 @@ impl EmbeddingModel {
             EmbeddingModel::Custom(_) => None,
         }
     }
+
+    /// Model for a `CollectionSettings::embedding_model` name
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "text-embedding-ada-002" => EmbeddingModel::OpenAIAda002,
+            "text-embedding-3-small" => EmbeddingModel::OpenAISmall,
+            "text-embedding-3-large" => EmbeddingModel::OpenAILarge,
+            custom => EmbeddingModel::Custom(custom.to_string()),
+        }
+    }
+
+    /// Longest text the model embeds, in tokens
+    pub fn max_input_tokens(&self) -> Option<u32> {
+        match self {
+            EmbeddingModel::OpenAIAda002
+            | EmbeddingModel::OpenAISmall
+            | EmbeddingModel::OpenAILarge => Some(8191),
+            EmbeddingModel::Custom(_) => None,
+        }
+    }
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -454,6 +1096,8 @@ pub struct EmbeddingRequest {
     pub texts: Vec<String>,
     pub model: EmbeddingModel,
     pub proxy_url: String,
+    /// Collection whose secret headers are attached to the outcall
+    pub collection_id: Option<CollectionId>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -463,6 +1107,568 @@ pub struct EmbeddingResponse {
     pub usage_tokens: Option<u32>,
 }
 
+/// Recent outcall statistics for one embedding endpoint
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ProviderHealth {
+    pub endpoint: String,
+    pub recent_requests: u32,
+    pub recent_successes: u32,
+    pub success_rate: f64,
+    pub avg_latency_ms: u64,
+    pub consecutive_failures: u32,
+    pub circuit_open: bool,
+    pub circuit_open_until: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_used_at: u64,
+}
+
+// =============================================================================
+// JOB TYPES
+// =============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum JobStatus {
+    #[default]
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum JobDocumentStatus {
+    Pending,
+    InProgress,
+    Embedded,
+    Skipped,
+    Failed,
+    Cancelled,
+}
+
+/// Per-document outcome of an embedding job
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct JobDocument {
+    pub document_id: DocumentId,
+    pub status: JobDocumentStatus,
+    pub chunks_embedded: u32,
+    pub total_chunks: u32,
+    pub error: Option<String>,
+}
+
+/// Background embedding job, checkpointed after every batch of chunks
+#[derive(CandidType, Default, Clone, Debug, Serialize, Deserialize)]
+pub struct EmbeddingJob {
+    pub id: String,
+    pub collection_id: CollectionId,
+    pub created_by: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub status: JobStatus,
+    pub documents: Vec<JobDocument>,
+    pub embedded: u32,
+    pub skipped: u32,
+    pub failed: u32,
+    /// Set when the job moves the collection to another embedding model
+    #[serde(default)]
+    pub migration: Option<ModelMigration>,
+}
+
+/// Re-embeds every chunk of a collection into a shadow vector set. Search
+/// keeps using the current vectors and model until all chunks are embedded;
+/// the job then switches both in one step and deletes the old vectors.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelMigration {
+    pub from_model: String,
+    pub to_model: String,
+}
+
+impl EmbeddingJob {
+    pub fn is_active(&self) -> bool {
+        matches!(self.status, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+impl Storable for EmbeddingJob {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2_097_152, // 2MB - up to MAX_JOB_DOCUMENTS outcomes
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(self).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_slice(&bytes).unwrap_or_default()
+    }
+}
+
+// =============================================================================
+// BILLING TYPES
+// =============================================================================
+
+/// Prepaid cycles account of a collection, charged for its embedding outcalls
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CollectionAccount {
+    pub collection_id: CollectionId,
+    pub balance: u64,
+    pub total_deposited: u64,
+    pub total_cycles: u64,
+    pub total_tokens: u64,
+    pub total_outcalls: u64,
+    pub updated_at: u64,
+}
+
+impl Storable for CollectionAccount {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(self).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_slice(&bytes).unwrap_or_default()
+    }
+}
+
+/// Usage of one collection over one ledger window (an hour)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UsageWindow {
+    pub start: u64,
+    pub end: u64,
+    pub outcalls: u64,
+    pub cycles: u64,
+    pub tokens: u64,
+}
+
+impl Storable for UsageWindow {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 256,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(self).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_slice(&bytes).unwrap_or_default()
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CollectionUsage {
+    pub collection_id: CollectionId,
+    pub balance: u64,
+    pub total_deposited: u64,
+    pub from: u64,
+    pub to: u64,
+    /// Totals over the requested range
+    pub outcalls: u64,
+    pub cycles: u64,
+    pub tokens: u64,
+    pub windows: Vec<UsageWindow>,
+}
+
+// =============================================================================
+// RATE LIMIT TYPES
+// =============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LimitMetric {
+    Documents,
+    Bytes,
+    Searches,
+    Outcalls,
+}
+
+/// Token bucket (`burst` capacity refilled at `per_minute`) plus an optional daily quota.
+/// A `burst` of 0 disables the bucket.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LimitRule {
+    pub burst: u64,
+    pub per_minute: u64,
+    pub daily: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LimitSet {
+    pub documents: Option<LimitRule>,
+    pub bytes: Option<LimitRule>,
+    pub searches: Option<LimitRule>,
+    pub outcalls: Option<LimitRule>,
+}
+
+impl LimitSet {
+    pub fn rule(&self, metric: LimitMetric) -> Option<&LimitRule> {
+        match metric {
+            LimitMetric::Documents => self.documents.as_ref(),
+            LimitMetric::Bytes => self.bytes.as_ref(),
+            LimitMetric::Searches => self.searches.as_ref(),
+            LimitMetric::Outcalls => self.outcalls.as_ref(),
+        }
+    }
+}
+
+/// Limits applied to every caller and to every collection. Controllers are exempt.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RateLimitConfig {
+    pub per_principal: LimitSet,
+    pub per_collection: LimitSet,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_principal: LimitSet {
+                documents: Some(LimitRule {
+                    burst: 20,
+                    per_minute: 10,
+                    daily: Some(1_000),
+                }),
+                bytes: Some(LimitRule {
+                    burst: 5_000_000,
+                    per_minute: 1_000_000,
+                    daily: Some(100_000_000),
+                }),
+                searches: Some(LimitRule {
+                    burst: 30,
+                    per_minute: 30,
+                    daily: Some(5_000),
+                }),
+                outcalls: Some(LimitRule {
+                    burst: 60,
+                    per_minute: 60,
+                    daily: Some(20_000),
+                }),
+            },
+            per_collection: LimitSet::default(),
+        }
+    }
+}
+
+/// Bucket and daily counter of one principal or collection for one metric
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LimitState {
+    pub tokens_milli: u64,
+    pub refilled_at: u64,
+    pub day: u64,
+    pub used_today: u64,
+}
+
+impl Storable for LimitState {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 256,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(self).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_slice(&bytes).unwrap_or_default()
+    }
+}
+
+/// Returned (rendered as text) when a call is over a rate limit or quota
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RateLimitExceeded {
+    pub metric: LimitMetric,
+    pub scope: String,
+    pub retry_after_ms: u64,
+    pub retry_at: u64,
+}
+
+impl std::fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Rate limit exceeded: metric={:?}; scope={}; retry_after_ms={}; retry_at={}",
+            self.metric, self.scope, self.retry_after_ms, self.retry_at
+        )
+    }
+}
+
+// =============================================================================
+// CANISTER CONFIG TYPES
+// =============================================================================
+
+/// Canister-wide configuration, editable by controllers
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CanisterConfig {
+    /// Applied to collections created without settings
+    pub default_collection_settings: CollectionSettings,
+    pub cache: CacheConfig,
+    pub outcalls: OutcallConfig,
+    /// Hosts that proxy URLs may point at; empty allows any HTTPS host
+    pub allowed_proxy_hosts: Vec<String>,
+    pub creation_policy: CreationPolicy,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CacheConfig {
+    pub max_memory_bytes: u64,
+    pub max_entries: u32,
+    pub ttl_seconds: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_memory_bytes: 100 * 1024 * 1024,
+            max_entries: 1000,
+            ttl_seconds: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Limits on a single embedding outcall
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OutcallConfig {
+    pub max_texts_per_request: u32,
+    pub max_chars_per_request: u32,
+    /// Cheap size guard checked before texts are tokenized; the model's token
+    /// limit is what normally applies
+    pub max_chars_per_text: u32,
+    /// Upper bound on the response size requested from the IC
+    pub max_response_bytes: u64,
+}
+
+impl Default for OutcallConfig {
+    fn default() -> Self {
+        Self {
+            max_texts_per_request: 50,
+            max_chars_per_request: 50_000,
+            max_chars_per_text: 32_000,
+            max_response_bytes: 2_000_000,
+        }
+    }
+}
+
+/// Who may call `create_collection`. Controllers can always create collections.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum CreationPolicy {
+    #[default]
+    Open,
+    /// Only the listed principals
+    Allowlist {
+        principals: Vec<String>,
+    },
+    ControllersOnly,
+    /// Anyone, up to `max_collections` collections they created
+    PerPrincipalLimit {
+        max_collections: u32,
+    },
+}
+
+// =============================================================================
+// AUDIT TYPES
+// =============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum AuditOutcome {
+    #[default]
+    Success,
+    Failure(String),
+}
+
+/// One administrative or destructive call, recorded whether or not it succeeded
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub caller: String,
+    pub action: String,
+    pub collection_id: Option<CollectionId>,
+    /// Call arguments as JSON, truncated to `MAX_AUDIT_ARGS_BYTES` or shorter
+    /// when the escaped entry would not fit its storage bound
+    pub args: String,
+    pub outcome: AuditOutcome,
+}
+
+impl Storable for AuditEntry {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 4096,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(self).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_slice(&bytes).unwrap_or_default()
+    }
+}
+
+/// Filters for reading the audit log, newest entries first. All filters are optional.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AuditQuery {
+    pub collection_id: Option<CollectionId>,
+    pub caller: Option<String>,
+    pub action: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// Cursor from a previous page: only entries with a smaller id are returned
+    pub before: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Pass as `before` to continue; None once the log is exhausted
+    pub next_cursor: Option<u64>,
+}
+
+// =============================================================================
+// APPROVAL TYPES
+// =============================================================================
+
+/// Requires `required_approvals` distinct admins (owners or editors) to approve
+/// a proposal within `expiry_hours` before it can be executed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ApprovalPolicy {
+    pub required_approvals: u32,
+    pub expiry_hours: u32,
+}
+
+/// Operations that need approval when the collection has an approval policy
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ProposalAction {
+    DeleteCollection,
+    DeleteDocument {
+        document_id: DocumentId,
+    },
+    DeleteDocumentVectors {
+        document_id: DocumentId,
+    },
+    ClearCollectionVectors,
+    DeleteCollectionDocuments,
+    TransferGenesisAdmin {
+        new_genesis_admin: String,
+    },
+    /// Needs approval only when it changes the embedding model, `max_documents`
+    /// or an outcall URL
+    UpdateSettings {
+        settings: Box<CollectionSettings>,
+    },
+    MigrateEmbeddingModel {
+        embedding_model: String,
+    },
+    SetApprovalPolicy {
+        policy: Option<ApprovalPolicy>,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum ProposalStatus {
+    #[default]
+    Open,
+    Executed,
+    Failed(String),
+    Cancelled,
+    Expired,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Proposal {
+    pub id: String,
+    pub collection_id: CollectionId,
+    pub action: ProposalAction,
+    pub proposer: String,
+    /// Admins who approved, the proposer first
+    pub approvals: Vec<String>,
+    /// Owners and editors when the proposal was made; only their approvals
+    /// count. Empty on proposals stored before it was recorded.
+    #[serde(default)]
+    pub eligible_approvers: Vec<String>,
+    pub required_approvals: u32,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub status: ProposalStatus,
+    pub executed_at: Option<u64>,
+}
+
+impl Default for Proposal {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            collection_id: String::new(),
+            action: ProposalAction::DeleteCollection,
+            proposer: String::new(),
+            approvals: Vec::new(),
+            eligible_approvers: Vec::new(),
+            required_approvals: 0,
+            created_at: 0,
+            expires_at: 0,
+            // A record that fails to decode can never be executed
+            status: ProposalStatus::Cancelled,
+            executed_at: None,
+        }
+    }
+}
+
+impl Storable for Proposal {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 8_192,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(self).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_slice(&bytes).unwrap_or_default()
+    }
+}
+
+// =============================================================================
+// UPLOAD TYPES
+// =============================================================================
+
+/// Blob received in parts, waiting to be added as a document
+#[derive(CandidType, Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Upload {
+    pub id: String,
+    pub collection_id: CollectionId,
+    /// Only the principal that started an upload can extend or use it
+    pub owner: String,
+    pub created_at: u64,
+    pub size: u64,
+    pub parts: u32,
+}
+
+impl Storable for Upload {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1_024,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(to_vec(self).unwrap_or_default())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        from_slice(&bytes).unwrap_or_default()
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct UploadStatus {
+    pub upload_id: String,
+    /// Bytes received so far
+    pub size: u64,
+    pub parts: u32,
+}
+
 // =============================================================================
 // CACHE TYPES
 // =============================================================================